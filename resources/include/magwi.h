@@ -33,6 +33,8 @@
         __mw_section_impl(type, arg, __mw_symbol_safe_filename, __LINE__, __COUNTER__)
 
     #define mw_replace(address) __mw_section(replace, address)
+    // Places read-only data like tables at address
+    #define mw_data(address) __mw_section(data, address)
 
     #define mw_loader_code \
         __attribute__((section(".mw_loader_text"), optimize("Os")))
@@ -46,6 +48,27 @@
     __mw_extern char __mw_text_start;
     __mw_extern char __mw_text_end;
 
+    typedef void (*__mw_array_func)(void);
+
+    __mw_extern __mw_array_func __init_array_start[];
+    __mw_extern __mw_array_func __init_array_end[];
+    __mw_extern __mw_array_func __fini_array_start[];
+    __mw_extern __mw_array_func __fini_array_end[];
+
+    // Runs global constructors of the custom code. Call this once from the loader.
+    static inline void mw_call_init_array(void)
+    {
+        for (__mw_array_func* f = __init_array_start; f != __init_array_end; f++)
+            (*f)();
+    }
+
+    // Runs global destructors of the custom code in reverse order.
+    static inline void mw_call_fini_array(void)
+    {
+        for (__mw_array_func* f = __fini_array_end; f != __fini_array_start;)
+            (*--f)();
+    }
+
 #else
 
     #define __mw_hook_label_impl2(type, arg, file, line, counter) .global __mw_hook_##type##$##arg##$##file##$##line##$##counter; __mw_hook_##type##$##arg##$##file##$##line##$##counter:
@@ -67,9 +90,16 @@
 
     #define mw_replace(address) __mw_section(replace, address)
     #define mw_replace_end .popsection
+    #define mw_data(address) __mw_section(data, address)
+    #define mw_data_end .popsection
 
     #define mw_loader_section .mw_loader_text
 
+    // Starts the global function name in ARM or Thumb code. Hooks call Thumb functions with
+    // blx, whole files can be assembled as Thumb with [source] thumb in magwi.toml instead.
+    #define mw_arm_func(name) .arm; .global name; .type name, %function; name:
+    #define mw_thumb_func(name) .thumb; .thumb_func; .global name; .type name, %function; name:
+
 #endif
 
 #define mw_b(address) __mw_hook_label(b, address)
@@ -107,4 +137,16 @@
 #define mw_pre(address) __mw_hook_label(pre, address)
 #define mw_post(address) __mw_hook_label(post, address)
 
+// Like mw_pre/mw_post, but force the trampoline into the loader or the tail of the code
+// instead of choosing by address.
+#define mw_pre_loader(address) __mw_hook_label(pre_loader, address)
+#define mw_pre_tail(address) __mw_hook_label(pre_tail, address)
+#define mw_post_loader(address) __mw_hook_label(post_loader, address)
+#define mw_post_tail(address) __mw_hook_label(post_tail, address)
+
 #define mw_symptr(address) __mw_hook_label(symptr, address)
+
+// Branches from the function entry at address to the hook. Put mw_detour_original(address) on a
+// function pointer to get a trampoline running the original function.
+#define mw_detour(address) __mw_hook_label(detour, address)
+#define mw_detour_original(address) __mw_hook_label(detour_original, address)
@@ -0,0 +1,510 @@
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum ArgsError {
+    #[error("Unknown option: {0}")]
+    UnknownOption(String),
+
+    #[error("Unexpected argument: {0}")]
+    UnexpectedArgument(String),
+
+    #[error("Missing value for option: {0}")]
+    MissingValue(String),
+
+    #[error("Invalid value for option {0}: {1}")]
+    InvalidValue(String, String),
+
+    #[error("Option {0} can't be combined with {1}")]
+    Conflicting(String, String),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Command {
+    Build,
+    Schema,
+    /// Builds and reports everything done at a single address.
+    Explain,
+    /// Creates the skeleton of a project in the project path.
+    New,
+    /// Builds and compares the patched code against a reference code.bin.
+    Verify,
+    /// Lists the original symbols together with the custom symbols of the last build.
+    Symbols,
+}
+
+impl Command {
+    fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "build" => Some(Command::Build),
+            "schema" => Some(Command::Schema),
+            "explain" => Some(Command::Explain),
+            "new" => Some(Command::New),
+            "verify" => Some(Command::Verify),
+            "symbols" => Some(Command::Symbols),
+            _ => None,
+        }
+    }
+}
+
+/// Controls debug info of the build outputs.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Profile {
+    Default,
+    /// Compiles with `-g`, build/out.elf and build/out.map keep full debug info.
+    Debug,
+    /// Strips debug sections from build/out.elf. Symbols needed for hooks are kept.
+    Release,
+}
+
+impl Profile {
+    pub fn name(self) -> &'static str {
+        match self {
+            Profile::Default => "default",
+            Profile::Debug => "debug",
+            Profile::Release => "release",
+        }
+    }
+}
+
+/// What happens to objects without code or data, e.g. of a source disabled by `#ifdef`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum EmptyObjects {
+    Allow,
+    Warn,
+    Deny,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Args {
+    pub command: Command,
+    pub explain_address: Option<u32>,
+    pub verify_reference: Option<PathBuf>,
+    pub project_path: Option<PathBuf>,
+    /// Set by --build-dir, see [`Args::build_dir`]
+    pub build_dir: Option<PathBuf>,
+    /// Variants from magwi.toml to build
+    pub variants: Vec<String>,
+    pub all_variants: bool,
+    pub gc_sections: bool,
+    pub allow_overlaps: bool,
+    /// Links and writes the outputs even if they are up to date
+    pub force: bool,
+    pub profile: Profile,
+    pub log_level: log::LevelFilter,
+    /// Only errors and explicitly requested reports are printed
+    pub quiet: bool,
+    /// Prints the time spent in each build phase
+    pub time: bool,
+    /// Prints the instructions of every pre/post and detour trampoline
+    pub dump_trampolines: bool,
+    /// Globs relative to the source directory, only matching sources are compiled
+    pub only: Vec<String>,
+    /// Only checks the syntax of the sources, nothing is linked or patched
+    pub check: bool,
+    /// Fails the build if the loader uses more than this percentage of its maximum size
+    pub max_loader_usage: Option<f32>,
+    /// Writes a hexdump of every changed region of the original code to this file, relative to
+    /// the project directory
+    pub emit_hexdiff: Option<PathBuf>,
+    pub empty_objects: EmptyObjects,
+    /// Prints the effective configuration and the source of every value instead of building
+    pub print_config: bool,
+    /// Prints the jobs and why they would be built as JSON instead of building
+    pub list_jobs: bool,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Self {
+            command: Command::Build,
+            explain_address: None,
+            verify_reference: None,
+            project_path: None,
+            build_dir: None,
+            variants: Vec::new(),
+            all_variants: false,
+            gc_sections: true,
+            allow_overlaps: false,
+            force: false,
+            profile: Profile::Default,
+            log_level: log::LevelFilter::Warn,
+            quiet: false,
+            time: false,
+            dump_trampolines: false,
+            only: Vec::new(),
+            check: false,
+            max_loader_usage: None,
+            emit_hexdiff: None,
+            empty_objects: EmptyObjects::Allow,
+            print_config: false,
+            list_jobs: false,
+        }
+    }
+}
+
+impl Args {
+    /// Build directory, relative to the project directory unless absolute.
+    pub fn build_dir(&self) -> &Path {
+        self.build_dir.as_deref().unwrap_or(Path::new("build"))
+    }
+
+    /// Parses the command line arguments, excluding the executable name.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, ArgsError> {
+        let mut result = Self::default();
+        let mut args = args.into_iter().peekable();
+
+        // The command is optional and defaults to build
+        if let Some(command) = args.peek().and_then(|arg| Command::from_str(arg)) {
+            result.command = command;
+            args.next();
+        }
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| ArgsError::MissingValue(arg.clone()));
+
+            match arg.as_str() {
+                "--log-level" => {
+                    let level = value()?;
+                    result.log_level = level
+                        .parse()
+                        .map_err(|_| ArgsError::InvalidValue(arg, level))?;
+                }
+                "--build-dir" => result.build_dir = Some(value()?.into()),
+                "--variant" => result.variants.push(value()?),
+                "--all" => result.all_variants = true,
+                "--gc-sections" => result.gc_sections = true,
+                "--no-gc-sections" => result.gc_sections = false,
+                "--allow-overlaps" => result.allow_overlaps = true,
+                "--force" => result.force = true,
+                "--debug" | "--keep-intermediates" => result.profile = Profile::Debug,
+                "--release" => result.profile = Profile::Release,
+                "--time" => result.time = true,
+                "--quiet" | "-q" => result.quiet = true,
+                "--dump-trampolines" => result.dump_trampolines = true,
+                "--only" => result.only.push(value()?),
+                "--check" => result.check = true,
+                "--max-loader-usage" => {
+                    let percent = value()?;
+                    result.max_loader_usage = match percent.trim_end_matches('%').parse() {
+                        Ok(p) if (0.0..=100.0).contains(&p) => Some(p),
+                        _ => return Err(ArgsError::InvalidValue(arg, percent)),
+                    };
+                }
+                "--emit-hexdiff" => result.emit_hexdiff = Some(value()?.into()),
+                "--empty-objects" => {
+                    let action = value()?;
+                    result.empty_objects = match action.as_str() {
+                        "allow" => EmptyObjects::Allow,
+                        "warn" => EmptyObjects::Warn,
+                        "deny" => EmptyObjects::Deny,
+                        _ => return Err(ArgsError::InvalidValue(arg, action)),
+                    };
+                }
+                "--print-config" => result.print_config = true,
+                "--list-jobs" => result.list_jobs = true,
+                _ if arg.starts_with("--") => return Err(ArgsError::UnknownOption(arg)),
+                _ if result.command == Command::Explain && result.explain_address.is_none() => {
+                    let address = super::hook::parse_address(&arg)
+                        .map_err(|_| ArgsError::InvalidValue("explain".into(), arg))?;
+                    result.explain_address = Some(address);
+                }
+                _ if result.command == Command::Verify && result.verify_reference.is_none() => {
+                    result.verify_reference = Some(arg.into());
+                }
+                _ if result.project_path.is_none() => result.project_path = Some(arg.into()),
+                _ => return Err(ArgsError::UnexpectedArgument(arg)),
+            }
+        }
+
+        if result.quiet {
+            // Only the verbose levels contradict it, warnings are silenced anyway
+            if result.log_level > log::LevelFilter::Warn {
+                return Err(ArgsError::Conflicting(
+                    "--quiet".into(),
+                    format!(
+                        "--log-level {}",
+                        result.log_level.as_str().to_ascii_lowercase()
+                    ),
+                ));
+            }
+            if result.log_level > log::LevelFilter::Error {
+                result.log_level = log::LevelFilter::Error;
+            }
+        }
+
+        if result.command == Command::Explain && result.explain_address.is_none() {
+            return Err(ArgsError::MissingValue("explain".into()));
+        }
+
+        if result.command == Command::Verify && result.verify_reference.is_none() {
+            return Err(ArgsError::MissingValue("verify".into()));
+        }
+
+        if result.command == Command::New && result.project_path.is_none() {
+            return Err(ArgsError::MissingValue("new".into()));
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, ArgsError> {
+        Args::parse(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse(&[]), Ok(Args::default()));
+        assert_eq!(
+            parse(&["project", "--no-gc-sections"]),
+            Ok(Args {
+                project_path: Some(PathBuf::from("project")),
+                gc_sections: false,
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse(&["--log-level", "debug"]),
+            Ok(Args {
+                log_level: log::LevelFilter::Debug,
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse(&["--log-level", "TRACE"]).map(|args| args.log_level),
+            Ok(log::LevelFilter::Trace)
+        );
+        assert_eq!(
+            parse(&["--log-level"]),
+            Err(ArgsError::MissingValue("--log-level".into()))
+        );
+        assert_eq!(
+            parse(&["--log-level", "loud"]),
+            Err(ArgsError::InvalidValue("--log-level".into(), "loud".into()))
+        );
+        assert_eq!(
+            parse(&["--allow-overlaps"]),
+            Ok(Args {
+                allow_overlaps: true,
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse(&["--force"]),
+            Ok(Args {
+                force: true,
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse(&["--keep-intermediates"]),
+            Ok(Args {
+                profile: Profile::Debug,
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse(&["--debug", "--release"]),
+            Ok(Args {
+                profile: Profile::Release,
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse(&["--time"]),
+            Ok(Args {
+                time: true,
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse(&["--dump-trampolines"]),
+            Ok(Args {
+                dump_trampolines: true,
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse(&["--only", "ui/**", "--only", "*.s", "--check"]),
+            Ok(Args {
+                only: vec!["ui/**".into(), "*.s".into()],
+                check: true,
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse(&["--only"]),
+            Err(ArgsError::MissingValue("--only".into()))
+        );
+        assert_eq!(
+            parse(&["--max-loader-usage", "80"]),
+            Ok(Args {
+                max_loader_usage: Some(80.0),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse(&["--max-loader-usage", "92.5%"]).map(|args| args.max_loader_usage),
+            Ok(Some(92.5))
+        );
+        assert_eq!(
+            parse(&["--max-loader-usage", "120"]),
+            Err(ArgsError::InvalidValue(
+                "--max-loader-usage".into(),
+                "120".into()
+            ))
+        );
+        assert_eq!(
+            parse(&["--quiet"]),
+            Ok(Args {
+                quiet: true,
+                log_level: log::LevelFilter::Error,
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse(&["-q", "--log-level", "off"]).map(|args| args.log_level),
+            Ok(log::LevelFilter::Off)
+        );
+        assert_eq!(
+            parse(&["--log-level", "debug", "-q"]),
+            Err(ArgsError::Conflicting(
+                "--quiet".into(),
+                "--log-level debug".into()
+            ))
+        );
+        assert_eq!(
+            parse(&["--emit-hexdiff", "build/changes.txt"]),
+            Ok(Args {
+                emit_hexdiff: Some(PathBuf::from("build/changes.txt")),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse(&["--empty-objects", "deny"]),
+            Ok(Args {
+                empty_objects: EmptyObjects::Deny,
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse(&["--empty-objects", "error"]),
+            Err(ArgsError::InvalidValue(
+                "--empty-objects".into(),
+                "error".into()
+            ))
+        );
+        assert_eq!(
+            parse(&["--print-config", "project"]),
+            Ok(Args {
+                print_config: true,
+                project_path: Some(PathBuf::from("project")),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse(&["--list-jobs", "project", "--only", "ui/**"]),
+            Ok(Args {
+                list_jobs: true,
+                project_path: Some(PathBuf::from("project")),
+                only: vec!["ui/**".into()],
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse(&["--build-dir", "/tmp/out"]),
+            Ok(Args {
+                build_dir: Some(PathBuf::from("/tmp/out")),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse(&["--variant", "eur", "--variant", "usa"]),
+            Ok(Args {
+                variants: vec!["eur".into(), "usa".into()],
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse(&["--all"]),
+            Ok(Args {
+                all_variants: true,
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse(&["schema"]),
+            Ok(Args {
+                command: Command::Schema,
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse(&["explain", "0x100004", "project"]),
+            Ok(Args {
+                command: Command::Explain,
+                explain_address: Some(0x100004),
+                project_path: Some(PathBuf::from("project")),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse(&["explain"]),
+            Err(ArgsError::MissingValue("explain".into()))
+        );
+        assert_eq!(
+            parse(&["explain", "xyz"]),
+            Err(ArgsError::InvalidValue("explain".into(), "xyz".into()))
+        );
+        assert_eq!(
+            parse(&["verify", "golden/code.bin", "project"]),
+            Ok(Args {
+                command: Command::Verify,
+                verify_reference: Some(PathBuf::from("golden/code.bin")),
+                project_path: Some(PathBuf::from("project")),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse(&["verify"]),
+            Err(ArgsError::MissingValue("verify".into()))
+        );
+        assert_eq!(
+            parse(&["symbols", "project", "--variant", "eur"]),
+            Ok(Args {
+                command: Command::Symbols,
+                project_path: Some(PathBuf::from("project")),
+                variants: vec!["eur".into()],
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse(&["new", "mygame"]),
+            Ok(Args {
+                command: Command::New,
+                project_path: Some(PathBuf::from("mygame")),
+                ..Default::default()
+            })
+        );
+        assert_eq!(parse(&["new"]), Err(ArgsError::MissingValue("new".into())));
+        assert_eq!(
+            parse(&["build", "project"]),
+            Ok(Args {
+                project_path: Some(PathBuf::from("project")),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse(&["--xyz"]),
+            Err(ArgsError::UnknownOption("--xyz".into()))
+        );
+        assert_eq!(
+            parse(&["a", "b"]),
+            Err(ArgsError::UnexpectedArgument("b".into()))
+        );
+    }
+}
@@ -0,0 +1,697 @@
+use std::path::{Path, PathBuf};
+
+use enum_map::{enum_map, EnumMap};
+
+use super::hook::HookPrefix;
+use super::jobs::JobKind;
+use super::toml::{self, TomlTable, TomlValue};
+
+pub const CONFIG_FILE_NAME: &str = "magwi.toml";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("Reading {CONFIG_FILE_NAME} failed: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("{CONFIG_FILE_NAME}: {0}")]
+    Toml(#[from] toml::TomlError),
+
+    #[error("{CONFIG_FILE_NAME}: Unknown table [{0}]")]
+    UnknownTable(String),
+
+    #[error("{CONFIG_FILE_NAME}: Line {0}: Unknown key \"{1}\"")]
+    UnknownKey(usize, String),
+
+    #[error("{CONFIG_FILE_NAME}: Line {0}: \"{1}\" must be a {2}, got {3}")]
+    InvalidType(usize, String, &'static str, &'static str),
+
+    #[error("{CONFIG_FILE_NAME}: Line {0}: \"{1}\" must be {2}")]
+    InvalidValue(usize, String, &'static str),
+
+    #[error("{CONFIG_FILE_NAME}: [{0}] is missing key \"{1}\"")]
+    MissingKey(String, &'static str),
+
+    #[error("Unknown variant \"{0}\", available: {1}")]
+    UnknownVariant(String, String),
+
+    #[error("No variants are declared in {CONFIG_FILE_NAME}")]
+    NoVariants,
+
+    #[error("Select a variant with --variant or --all, available: {0}")]
+    NoVariantSelected(String),
+
+    #[error("Environment variable {0} must be {2}, got \"{1}\"")]
+    InvalidEnv(&'static str, String, &'static str),
+}
+
+/// A region or other version of the game, built from its own original files.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Variant {
+    pub name: String,
+    /// Directory containing code.bin and exheader.bin, or a CXI
+    pub original_dir: PathBuf,
+    pub symbols_path: PathBuf,
+    /// Patterns of the variant's `ignore`, sources only the other variants compile
+    pub ignore: Vec<String>,
+}
+
+/// Names magwi.h and the hook parsing agree on, set by `[hooks]` if other code already uses
+/// the `__mw_` namespace.
+#[derive(Debug, PartialEq)]
+pub struct HooksConfig {
+    pub prefix: HookPrefix,
+    /// Define holding the symbol safe path of the compiled source
+    pub filename_define: String,
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        Self {
+            prefix: HookPrefix::default(),
+            filename_define: "__mw_symbol_safe_filename".into(),
+        }
+    }
+}
+
+/// CPU the code is compiled and linked for, set by `[cpu]`. Defaults to the ARM11 app core,
+/// other values allow retargeting e.g. the ARM9.
+#[derive(Debug, PartialEq, Clone)]
+pub struct CpuConfig {
+    pub march: String,
+    pub mtune: String,
+    pub mfloat_abi: String,
+    pub mtp: String,
+}
+
+impl Default for CpuConfig {
+    fn default() -> Self {
+        Self {
+            march: "armv6k+fp".into(),
+            mtune: "mpcore".into(),
+            mfloat_abi: "hard".into(),
+            mtp: "soft".into(),
+        }
+    }
+}
+
+impl CpuConfig {
+    /// Passed to the compiler and the linker alike. The linker picks the multilib by them, so
+    /// separate values could link objects against libraries of a different ABI.
+    pub fn flags(&self) -> Vec<String> {
+        vec![
+            format!("-march={}", self.march),
+            format!("-mtune={}", self.mtune),
+            format!("-mfloat-abi={}", self.mfloat_abi),
+            format!("-mtp={}", self.mtp),
+        ]
+    }
+}
+
+#[derive(Debug, PartialEq, Default)]
+pub struct Config {
+    pub variants: Vec<Variant>,
+    /// Patterns of `[source] ignore`, excluded from compilation
+    pub source_ignore: Vec<String>,
+    /// Patterns of `[source] thumb`, built as Thumb instead of ARM code, e.g. hand written
+    /// Thumb assembly. Their symbols keep the Thumb bit, so hooks call them with blx.
+    pub source_thumb: Vec<String>,
+    pub hooks: HooksConfig,
+    pub cpu: CpuConfig,
+    /// File name of an ExeFS in the original directories set by `[output] exefs`. It's
+    /// repacked with the patched code into exefs.bin.
+    pub exefs: Option<String>,
+    /// Set by `[output] luma`, also writes the outputs in the layout of Luma3DS title patches
+    pub luma: bool,
+    /// Set by `[output] manifest`, writes hooks.manifest listing every applied hook
+    pub manifest: bool,
+    /// Set by `[output] text_align`, the custom text is padded up to a multiple of it with the
+    /// `fill` word. Defaults to a page.
+    pub text_align: Option<u32>,
+    /// Set by `[output] split_extra`, writes everything appended to the original code to
+    /// extra.bin instead of code.bin, for loaders placing the custom code themselves
+    pub split_extra: bool,
+    /// Set by `[output] fill`, word the padding magwi inserts is filled with instead of zeros,
+    /// e.g. 0xDEADBEEF to spot stray writes into it in memory dumps
+    pub fill: u32,
+    /// Linker script fragments of `[link] scripts`, appended to build/linker.ld in order after
+    /// the generated MEMORY and SECTIONS. They may add regions and output sections, e.g.
+    /// NOLOAD scratch memory at a fixed address, whose symbols hooks can use like any other.
+    pub link_scripts: Vec<String>,
+    /// Keys given in the file as `table.key`, so values equal to their default can still be
+    /// told apart from unset ones
+    pub set_keys: std::collections::BTreeSet<String>,
+}
+
+fn get_string(table: &mut TomlTable, key: &'static str) -> Result<Option<String>, ConfigError> {
+    match table.remove(key) {
+        None => Ok(None),
+        Some((_, TomlValue::String(s))) => Ok(Some(s)),
+        Some((line, value)) => Err(ConfigError::InvalidType(
+            line,
+            key.to_string(),
+            "string",
+            value.type_name(),
+        )),
+    }
+}
+
+fn get_bool(table: &mut TomlTable, key: &'static str) -> Result<Option<bool>, ConfigError> {
+    match table.remove(key) {
+        None => Ok(None),
+        Some((_, TomlValue::Bool(b))) => Ok(Some(b)),
+        Some((line, value)) => Err(ConfigError::InvalidType(
+            line,
+            key.to_string(),
+            "bool",
+            value.type_name(),
+        )),
+    }
+}
+
+fn get_choice(
+    table: &mut TomlTable,
+    key: &'static str,
+    choices: &[&str],
+    expected: &'static str,
+) -> Result<Option<String>, ConfigError> {
+    let line = table.get(key).map(|(line, _)| *line);
+    match get_string(table, key)? {
+        Some(s) if !choices.contains(&s.as_str()) => Err(ConfigError::InvalidValue(
+            line.unwrap(),
+            key.to_string(),
+            expected,
+        )),
+        s => Ok(s),
+    }
+}
+
+fn get_u32(table: &mut TomlTable, key: &'static str) -> Result<Option<u32>, ConfigError> {
+    match table.remove(key) {
+        None => Ok(None),
+        Some((line, TomlValue::Integer(i))) => u32::try_from(i)
+            .map(Some)
+            .map_err(|_| ConfigError::InvalidValue(line, key.to_string(), "a 32 bit word")),
+        Some((line, value)) => Err(ConfigError::InvalidType(
+            line,
+            key.to_string(),
+            "integer",
+            value.type_name(),
+        )),
+    }
+}
+
+fn get_alignment(table: &mut TomlTable, key: &'static str) -> Result<Option<u32>, ConfigError> {
+    match table.remove(key) {
+        None => Ok(None),
+        Some((line, TomlValue::Integer(i))) => match u32::try_from(i) {
+            Ok(align) if align.is_power_of_two() => Ok(Some(align)),
+            _ => Err(ConfigError::InvalidValue(
+                line,
+                key.to_string(),
+                "a power of two",
+            )),
+        },
+        Some((line, value)) => Err(ConfigError::InvalidType(
+            line,
+            key.to_string(),
+            "integer",
+            value.type_name(),
+        )),
+    }
+}
+
+fn get_string_array(
+    table: &mut TomlTable,
+    key: &'static str,
+) -> Result<Option<Vec<String>>, ConfigError> {
+    let invalid_type = |line, value: &TomlValue| {
+        ConfigError::InvalidType(line, key.to_string(), "string array", value.type_name())
+    };
+
+    match table.remove(key) {
+        None => Ok(None),
+        Some((line, TomlValue::Array(values))) => values
+            .into_iter()
+            .map(|value| match value {
+                TomlValue::String(s) => Ok(s),
+                value => Err(invalid_type(line, &value)),
+            })
+            .collect::<Result<_, _>>()
+            .map(Some),
+        Some((line, value)) => Err(invalid_type(line, &value)),
+    }
+}
+
+fn check_no_keys_left(table: &TomlTable) -> Result<(), ConfigError> {
+    match table.iter().min_by_key(|(_, (line, _))| *line) {
+        Some((key, (line, _))) => Err(ConfigError::UnknownKey(*line, key.clone())),
+        None => Ok(()),
+    }
+}
+
+impl Config {
+    pub fn parse(s: &str) -> Result<Self, ConfigError> {
+        let mut config = Config::default();
+
+        for (name, mut table) in toml::parse(s)? {
+            if name.is_empty() || name == "variants" {
+                check_no_keys_left(&table)?;
+                continue;
+            }
+
+            config
+                .set_keys
+                .extend(table.keys().map(|key| format!("{name}.{key}")));
+
+            if name == "source" {
+                config.source_ignore = get_string_array(&mut table, "ignore")?.unwrap_or_default();
+                config.source_thumb = get_string_array(&mut table, "thumb")?.unwrap_or_default();
+                check_no_keys_left(&table)?;
+                continue;
+            }
+
+            if name == "link" {
+                config.link_scripts = get_string_array(&mut table, "scripts")?.unwrap_or_default();
+                check_no_keys_left(&table)?;
+                continue;
+            }
+
+            if name == "hooks" {
+                let hooks = &mut config.hooks;
+                if let Some(prefix) = get_string(&mut table, "symbol_prefix")? {
+                    hooks.prefix.symbol = prefix;
+                }
+                if let Some(prefix) = get_string(&mut table, "section_prefix")? {
+                    hooks.prefix.section = prefix;
+                }
+                if let Some(define) = get_string(&mut table, "filename_define")? {
+                    hooks.filename_define = define;
+                }
+                check_no_keys_left(&table)?;
+                continue;
+            }
+
+            if name == "cpu" {
+                let cpu = &mut config.cpu;
+                if let Some(march) = get_string(&mut table, "march")? {
+                    cpu.march = march;
+                }
+                if let Some(mtune) = get_string(&mut table, "mtune")? {
+                    cpu.mtune = mtune;
+                }
+                if let Some(abi) = get_choice(
+                    &mut table,
+                    "mfloat_abi",
+                    &["soft", "softfp", "hard"],
+                    "one of soft, softfp, hard",
+                )? {
+                    cpu.mfloat_abi = abi;
+                }
+                if let Some(tp) = get_choice(
+                    &mut table,
+                    "mtp",
+                    &["soft", "cp15", "auto"],
+                    "one of soft, cp15, auto",
+                )? {
+                    cpu.mtp = tp;
+                }
+                check_no_keys_left(&table)?;
+                continue;
+            }
+
+            if name == "output" {
+                config.exefs = get_string(&mut table, "exefs")?;
+                config.luma = get_bool(&mut table, "luma")?.unwrap_or(false);
+                config.manifest = get_bool(&mut table, "manifest")?.unwrap_or(false);
+                config.text_align = get_alignment(&mut table, "text_align")?;
+                config.split_extra = get_bool(&mut table, "split_extra")?.unwrap_or(false);
+                config.fill = get_u32(&mut table, "fill")?.unwrap_or(0);
+                check_no_keys_left(&table)?;
+                continue;
+            }
+
+            let Some(variant_name) = name.strip_prefix("variants.") else {
+                return Err(ConfigError::UnknownTable(name));
+            };
+
+            if variant_name.contains('.') {
+                return Err(ConfigError::UnknownTable(name));
+            }
+
+            let original_dir = get_string(&mut table, "original")?
+                .ok_or_else(|| ConfigError::MissingKey(name.clone(), "original"))?;
+            let symbols_path = get_string(&mut table, "symbols")?
+                .ok_or_else(|| ConfigError::MissingKey(name.clone(), "symbols"))?;
+            let ignore = get_string_array(&mut table, "ignore")?.unwrap_or_default();
+            check_no_keys_left(&table)?;
+
+            config.variants.push(Variant {
+                name: variant_name.to_string(),
+                original_dir: original_dir.into(),
+                symbols_path: symbols_path.into(),
+                ignore,
+            });
+        }
+
+        Ok(config)
+    }
+
+    /// Whether `key`, given as `table.key`, is set in the file.
+    pub fn is_set(&self, key: &str) -> bool {
+        self.set_keys.contains(key)
+    }
+
+    /// Loads magwi.toml from the project directory. A missing file is an empty config.
+    pub fn load(project_path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        match std::fs::read_to_string(project_path.as_ref().join(CONFIG_FILE_NAME)) {
+            Ok(s) => Self::parse(&s),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn variant_names(&self) -> String {
+        self.variants
+            .iter()
+            .map(|v| v.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Returns the variants to build. Empty if the project has no variants, in which case
+    /// the original files are used directly.
+    pub fn select_variants(
+        &self,
+        names: &[String],
+        all: bool,
+    ) -> Result<Vec<&Variant>, ConfigError> {
+        if self.variants.is_empty() {
+            return match names.is_empty() && !all {
+                true => Ok(Vec::new()),
+                false => Err(ConfigError::NoVariants),
+            };
+        }
+
+        if all {
+            return Ok(self.variants.iter().collect());
+        }
+
+        if names.is_empty() {
+            return Err(ConfigError::NoVariantSelected(self.variant_names()));
+        }
+
+        names
+            .iter()
+            .map(|name| {
+                self.variants
+                    .iter()
+                    .find(|v| &v.name == name)
+                    .ok_or_else(|| ConfigError::UnknownVariant(name.clone(), self.variant_names()))
+            })
+            .collect()
+    }
+}
+
+/// Settings of `MAGWI_*` environment variables, e.g. set once by a CI container for all
+/// invocations. They apply on top of magwi.toml, empty variables count as unset.
+#[derive(Debug, PartialEq, Default)]
+pub struct EnvConfig {
+    /// `MAGWI_JOBS`, number of sources compiled in parallel instead of one per CPU
+    pub jobs: Option<usize>,
+    /// `MAGWI_CC`, compiler of C and assembly sources
+    pub cc: Option<PathBuf>,
+    /// `MAGWI_CXX`, compiler of C++ sources, also used to link
+    pub cxx: Option<PathBuf>,
+    /// `MAGWI_FLAGS_C`, `MAGWI_FLAGS_CPP` and `MAGWI_FLAGS_ASM`, whitespace separated flags
+    /// appended after the built-in ones. Flags the compiler takes the last of, like `-O2`,
+    /// override the built-in ones.
+    pub flags: EnumMap<JobKind, Vec<String>>,
+}
+
+impl EnvConfig {
+    pub fn from_env() -> Result<Self, ConfigError> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self, ConfigError> {
+        let var = |name| var(name).filter(|value| !value.trim().is_empty());
+        let flags = |name| {
+            var(name)
+                .map(|value| value.split_whitespace().map(String::from).collect())
+                .unwrap_or_default()
+        };
+
+        let jobs = match var("MAGWI_JOBS") {
+            Some(value) => match value.trim().parse() {
+                Ok(jobs) if jobs > 0 => Some(jobs),
+                _ => {
+                    return Err(ConfigError::InvalidEnv(
+                        "MAGWI_JOBS",
+                        value,
+                        "a positive number",
+                    ))
+                }
+            },
+            None => None,
+        };
+
+        Ok(Self {
+            jobs,
+            cc: var("MAGWI_CC").map(PathBuf::from),
+            cxx: var("MAGWI_CXX").map(PathBuf::from),
+            flags: enum_map! {
+                JobKind::C => flags("MAGWI_FLAGS_C"),
+                JobKind::CPP => flags("MAGWI_FLAGS_CPP"),
+                JobKind::ASM => flags("MAGWI_FLAGS_ASM"),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+[variants.eur]
+original = "original/eur"
+symbols = "symbols/eur.ld"
+
+[variants.usa]
+original = "original/usa"
+symbols = "symbols/usa.ld"
+ignore = ["regions/eur/"]
+"#;
+
+    #[test]
+    fn test_parse() {
+        let config = Config::parse(CONFIG).unwrap();
+        assert_eq!(
+            config.variants,
+            vec![
+                Variant {
+                    name: "eur".into(),
+                    original_dir: "original/eur".into(),
+                    symbols_path: "symbols/eur.ld".into(),
+                    ignore: Vec::new(),
+                },
+                Variant {
+                    name: "usa".into(),
+                    original_dir: "original/usa".into(),
+                    symbols_path: "symbols/usa.ld".into(),
+                    ignore: vec!["regions/eur/".into()],
+                },
+            ]
+        );
+
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+
+        assert_eq!(
+            Config::parse("[source]\nignore = [\"vendor/\", \"*.bak.c\"]")
+                .unwrap()
+                .source_ignore,
+            vec!["vendor/", "*.bak.c"]
+        );
+        assert_eq!(
+            Config::parse("[source]\nthumb = [\"thumb/*.s\"]")
+                .unwrap()
+                .source_thumb,
+            vec!["thumb/*.s"]
+        );
+        assert!(matches!(
+            Config::parse("[source]\nignore = [\"a\", 1]"),
+            Err(ConfigError::InvalidType(2, _, "string array", "integer"))
+        ));
+
+        assert_eq!(
+            Config::parse("[link]\nscripts = [\"link/scratch.ld\"]")
+                .unwrap()
+                .link_scripts,
+            vec!["link/scratch.ld"]
+        );
+
+        let hooks = Config::parse(
+            "[hooks]\nsymbol_prefix = \"__game_hook_\"\nsection_prefix = \".__game_hook_\"\nfilename_define = \"GAME_FILE\"",
+        )
+        .unwrap()
+        .hooks;
+        assert_eq!(hooks.prefix.symbol, "__game_hook_");
+        assert_eq!(hooks.prefix.section, ".__game_hook_");
+        assert_eq!(hooks.filename_define, "GAME_FILE");
+        assert_eq!(
+            Config::parse("[hooks]\nsymbol_prefix = \"__game_hook_\"")
+                .unwrap()
+                .hooks
+                .prefix
+                .section,
+            ".__mw_hook_"
+        );
+
+        let cpu = Config::parse(
+            "[cpu]\nmarch = \"armv5te\"\nmtune = \"arm946e-s\"\nmfloat_abi = \"soft\"",
+        )
+        .unwrap()
+        .cpu;
+        assert_eq!(
+            cpu.flags(),
+            vec![
+                "-march=armv5te",
+                "-mtune=arm946e-s",
+                "-mfloat-abi=soft",
+                "-mtp=soft"
+            ]
+        );
+        // A value equal to its default still counts as set
+        let config = Config::parse("[cpu]\nmtune = \"mpcore\"").unwrap();
+        assert_eq!(config.cpu, CpuConfig::default());
+        assert!(config.is_set("cpu.mtune"));
+        assert!(!config.is_set("cpu.march"));
+        assert!(matches!(
+            Config::parse("[cpu]\nmtp = \"cp14\""),
+            Err(ConfigError::InvalidValue(2, _, "one of soft, cp15, auto"))
+        ));
+
+        assert_eq!(
+            Config::parse("[output]\nexefs = \"exefs.bin\"")
+                .unwrap()
+                .exefs
+                .as_deref(),
+            Some("exefs.bin")
+        );
+        assert!(Config::parse("[output]\nluma = true").unwrap().luma);
+        assert!(Config::parse("[output]\nmanifest = true").unwrap().manifest);
+        assert!(
+            Config::parse("[output]\nsplit_extra = true")
+                .unwrap()
+                .split_extra
+        );
+        assert_eq!(
+            Config::parse("[output]\ntext_align = 0x100")
+                .unwrap()
+                .text_align,
+            Some(0x100)
+        );
+        assert!(matches!(
+            Config::parse("[output]\ntext_align = 0x300"),
+            Err(ConfigError::InvalidValue(2, _, "a power of two"))
+        ));
+        assert!(matches!(
+            Config::parse("[output]\ntext_align = -1"),
+            Err(ConfigError::InvalidValue(2, _, "a power of two"))
+        ));
+        assert_eq!(
+            Config::parse("[output]\nfill = 0xDEADBEEF").unwrap().fill,
+            0xDEADBEEF
+        );
+        assert!(matches!(
+            Config::parse("[output]\nfill = 0x100000000"),
+            Err(ConfigError::InvalidValue(2, _, "a 32 bit word"))
+        ));
+        assert!(matches!(
+            Config::parse("[output]\nluma = \"yes\""),
+            Err(ConfigError::InvalidType(2, _, "bool", "string"))
+        ));
+
+        assert!(matches!(
+            Config::parse("[variant.eur]"),
+            Err(ConfigError::UnknownTable(t)) if t == "variant.eur"
+        ));
+        assert!(matches!(
+            Config::parse("[variants.eur]\noriginal = \"a\"\nsymbols = \"b\"\nsymbol = \"c\""),
+            Err(ConfigError::UnknownKey(4, k)) if k == "symbol"
+        ));
+        assert!(matches!(
+            Config::parse("[variants.eur]\noriginal = 1"),
+            Err(ConfigError::InvalidType(2, _, "string", "integer"))
+        ));
+        assert!(matches!(
+            Config::parse("[variants.eur]\noriginal = \"a\""),
+            Err(ConfigError::MissingKey(_, "symbols"))
+        ));
+    }
+
+    #[test]
+    fn test_select_variants() {
+        let config = Config::parse(CONFIG).unwrap();
+        let names =
+            |variants: Vec<&Variant>| variants.iter().map(|v| v.name.clone()).collect::<Vec<_>>();
+
+        assert_eq!(
+            names(config.select_variants(&[], true).unwrap()),
+            vec!["eur", "usa"]
+        );
+        assert_eq!(
+            names(config.select_variants(&["usa".into()], false).unwrap()),
+            vec!["usa"]
+        );
+        assert!(matches!(
+            config.select_variants(&[], false),
+            Err(ConfigError::NoVariantSelected(names)) if names == "eur, usa"
+        ));
+        assert!(matches!(
+            config.select_variants(&["jpn".into()], false),
+            Err(ConfigError::UnknownVariant(name, _)) if name == "jpn"
+        ));
+
+        let empty = Config::default();
+        assert_eq!(
+            empty.select_variants(&[], false).unwrap(),
+            Vec::<&Variant>::new()
+        );
+        assert!(matches!(
+            empty.select_variants(&[], true),
+            Err(ConfigError::NoVariants)
+        ));
+    }
+
+    #[test]
+    fn test_env_config() {
+        let vars = |vars: &[(&str, &str)]| {
+            let vars = vars
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect::<std::collections::HashMap<_, _>>();
+            EnvConfig::from_vars(|name| vars.get(name).cloned())
+        };
+
+        assert_eq!(vars(&[]).unwrap(), EnvConfig::default());
+        assert_eq!(vars(&[("MAGWI_CC", " ")]).unwrap(), EnvConfig::default());
+
+        let env = vars(&[
+            ("MAGWI_JOBS", "2"),
+            ("MAGWI_CXX", "/opt/gcc/bin/arm-none-eabi-g++"),
+            ("MAGWI_FLAGS_CPP", " -O2  -DCI "),
+        ])
+        .unwrap();
+        assert_eq!(env.jobs, Some(2));
+        assert_eq!(env.cc, None);
+        assert_eq!(env.cxx, Some("/opt/gcc/bin/arm-none-eabi-g++".into()));
+        assert_eq!(env.flags[JobKind::CPP], vec!["-O2", "-DCI"]);
+        assert!(env.flags[JobKind::C].is_empty());
+
+        assert!(matches!(
+            vars(&[("MAGWI_JOBS", "0")]),
+            Err(ConfigError::InvalidEnv("MAGWI_JOBS", value, _)) if value == "0"
+        ));
+    }
+}
@@ -0,0 +1,143 @@
+use super::sha256::sha256;
+
+/// Size of the ExeFS header, file data follows it.
+pub const HEADER_SIZE: usize = 0x200;
+/// Files are aligned to media units.
+const FILE_ALIGN: usize = 0x200;
+const MAX_FILES: usize = 10;
+const HASHES_OFFSET: usize = 0xC0;
+
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum ExefsError {
+    #[error("ExeFS is truncated")]
+    Truncated,
+
+    #[error("ExeFS has no file \"{0}\"")]
+    MissingFile(String),
+}
+
+/// Files of an ExeFS in header order.
+#[derive(Debug, PartialEq)]
+pub struct Exefs {
+    pub files: Vec<(String, Vec<u8>)>,
+}
+
+impl Exefs {
+    pub fn parse(data: &[u8]) -> Result<Self, ExefsError> {
+        let header = data.get(..HEADER_SIZE).ok_or(ExefsError::Truncated)?;
+
+        let mut files = Vec::new();
+        for entry in header[..MAX_FILES * 0x10].chunks_exact(0x10) {
+            let name = &entry[..8];
+            // Unused entries are zeroed
+            if name[0] == 0 {
+                continue;
+            }
+            let name_len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+            let offset = u32::from_le_bytes(entry[8..12].try_into().unwrap()) as usize;
+            let size = u32::from_le_bytes(entry[12..16].try_into().unwrap()) as usize;
+
+            let start = HEADER_SIZE + offset;
+            let file = data
+                .get(start..start + size)
+                .ok_or(ExefsError::Truncated)?;
+            files.push((
+                String::from_utf8_lossy(&name[..name_len]).into_owned(),
+                file.to_vec(),
+            ));
+        }
+
+        Ok(Self { files })
+    }
+
+    /// Contents of the file `name`.
+    pub fn get(&self, name: &str) -> Result<&[u8], ExefsError> {
+        self.files
+            .iter()
+            .find(|(file_name, _)| file_name == name)
+            .map(|(_, file)| file.as_slice())
+            .ok_or_else(|| ExefsError::MissingFile(name.to_string()))
+    }
+
+    /// Replaces the contents of the file `name`, e.g. `.code`.
+    pub fn replace(&mut self, name: &str, data: Vec<u8>) -> Result<(), ExefsError> {
+        let (_, file) = self
+            .files
+            .iter_mut()
+            .find(|(file_name, _)| file_name == name)
+            .ok_or_else(|| ExefsError::MissingFile(name.to_string()))?;
+        *file = data;
+        Ok(())
+    }
+
+    /// Lays out the files back to back and recomputes the header and its hashes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = vec![0u8; HEADER_SIZE];
+
+        for (i, (name, file)) in self.files.iter().enumerate() {
+            let offset = data.len() - HEADER_SIZE;
+
+            let entry = &mut data[i * 0x10..(i + 1) * 0x10];
+            let name_len = name.len().min(8);
+            entry[..name_len].copy_from_slice(&name.as_bytes()[..name_len]);
+            entry[8..12].copy_from_slice(&(offset as u32).to_le_bytes());
+            entry[12..16].copy_from_slice(&(file.len() as u32).to_le_bytes());
+
+            // Hashes are stored in reverse order of the file entries
+            let hash_offset = HASHES_OFFSET + (MAX_FILES - 1 - i) * 0x20;
+            data[hash_offset..hash_offset + 0x20].copy_from_slice(&sha256(file));
+
+            data.extend_from_slice(file);
+            data.resize((data.len() + FILE_ALIGN - 1) & !(FILE_ALIGN - 1), 0);
+        }
+
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_exefs() -> Exefs {
+        Exefs {
+            files: vec![
+                (".code".into(), vec![1; 0x234]),
+                ("banner".into(), vec![2; 0x10]),
+                ("icon".into(), vec![3; 0x200]),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let exefs = test_exefs();
+        let data = exefs.to_bytes();
+
+        assert_eq!(data.len(), HEADER_SIZE + 0x400 + 0x200 + 0x200);
+        assert_eq!(&data[0x10..0x18], b"banner\0\0");
+        assert_eq!(&data[0x18..0x1C], &0x400u32.to_le_bytes());
+        assert_eq!(&data[0x1E0..0x200], &sha256(&[1; 0x234]));
+        assert_eq!(&data[0x1C0..0x1E0], &sha256(&[2; 0x10]));
+        assert_eq!(Exefs::parse(&data), Ok(exefs));
+    }
+
+    #[test]
+    fn test_replace() {
+        let mut exefs = Exefs::parse(&test_exefs().to_bytes()).unwrap();
+        exefs.replace(".code", vec![4; 0x600]).unwrap();
+        assert_eq!(exefs.get(".code"), Ok(&[4; 0x600][..]));
+        assert_eq!(
+            exefs.replace("logo", Vec::new()),
+            Err(ExefsError::MissingFile("logo".into()))
+        );
+
+        let data = exefs.to_bytes();
+        assert_eq!(&data[0x18..0x1C], &0x600u32.to_le_bytes());
+        assert_eq!(&data[0x1E0..0x200], &sha256(&[4; 0x600]));
+        assert_eq!(Exefs::parse(&data).unwrap().files[2].1, vec![3; 0x200]);
+
+        assert_eq!(Exefs::parse(&data[..0x100]), Err(ExefsError::Truncated));
+        assert_eq!(Exefs::parse(&data[..0x300]), Err(ExefsError::Truncated));
+    }
+}
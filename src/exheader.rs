@@ -49,12 +49,42 @@ pub struct Exheader {
     pub aci_ext: ACIExt,
 }
 
+impl Exheader {
+    /// Program id of the title, the first field of the ARM11 local system capabilities.
+    /// Usually matches `SCI::jump_id`.
+    pub fn title_id(&self) -> u64 {
+        u64::from_le_bytes(self.info.aci.data[..8].try_into().unwrap())
+    }
+}
+
 pub const PAGE_SIZE: u32 = 0x1000;
 
+/// Bit of `SCI::flags[5]` set if the ExeFS .code is compressed.
+pub const FLAG_COMPRESS_CODE: u8 = 1;
+
 pub fn round_to_page(v: u32) -> u32 {
     (v + PAGE_SIZE - 1) & !(PAGE_SIZE - 1)
 }
 
 pub fn page_count(v: u32) -> u32 {
     round_to_page(v) / PAGE_SIZE
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use binrw::BinReaderExt;
+
+    #[test]
+    fn test_title_id() {
+        let mut data = vec![0u8; 0x800];
+        // SCI::jump_id
+        data[0x1C8..0x1D0].copy_from_slice(&0x0004000000055D00u64.to_le_bytes());
+        // ACI program id
+        data[0x200..0x208].copy_from_slice(&0x0004000000055D00u64.to_le_bytes());
+
+        let exheader: Exheader = std::io::Cursor::new(data).read_ne().unwrap();
+        assert_eq!(exheader.title_id(), 0x0004000000055D00);
+        assert_eq!(exheader.info.sci.jump_id, exheader.title_id());
+    }
+}
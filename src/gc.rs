@@ -0,0 +1,92 @@
+use std::collections::{BTreeSet, HashSet};
+
+use super::hook::HookPrefix;
+use super::symbol_map;
+
+/// Parses a `--print-gc-sections` line of ld into the removed section name and its object file.
+pub fn parse_removed_section(line: &str) -> Option<(&str, &str)> {
+    let (_, rest) = line.split_once("removing unused section '")?;
+    let (section, rest) = rest.split_once("' in file '")?;
+    let obj_path = rest.strip_suffix('\'')?;
+    Some((section, obj_path))
+}
+
+/// Collects the input sections that have to be kept alive during `--gc-sections`.
+///
+/// Hooks reference their targets by name only, so the linker does not see a reference to
+/// the sections defining hook symbols or symbols used by hks files. `symbols` yields
+/// pairs of symbol name and the name of the section defining it.
+///
+/// The symbol names of symbol hooks contain symbol versions, which can't be referenced with
+/// `EXTERN`, so the defining sections are kept instead.
+pub fn collect_keep_sections<'a>(
+    symbols: impl IntoIterator<Item = (&'a str, &'a str)>,
+    referenced_symbols: &HashSet<String>,
+    prefix: &HookPrefix,
+) -> BTreeSet<String> {
+    let mut keep_sections = BTreeSet::new();
+
+    for (sym_name, section_name) in symbols {
+        // Already kept by the generated sections
+        if section_name.starts_with(prefix.section.as_str())
+            || section_name.starts_with(".mw_loader_text")
+        {
+            continue;
+        }
+
+        let referenced = sym_name.starts_with(prefix.symbol.as_str())
+            || referenced_symbols.contains(sym_name)
+            || symbol_map::demangle(sym_name).is_some_and(|s| referenced_symbols.contains(&s));
+
+        if referenced {
+            keep_sections.insert(section_name.to_string());
+        }
+    }
+
+    keep_sections
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_removed_section() {
+        assert_eq!(
+            parse_removed_section(
+                "ld: removing unused section '.text.unused' in file 'build/obj/a.c.o'"
+            ),
+            Some((".text.unused", "build/obj/a.c.o"))
+        );
+        assert_eq!(parse_removed_section("ld: warning: something"), None);
+    }
+
+    #[test]
+    fn test_hook_only_function_is_kept() {
+        let referenced: HashSet<String> = ["hks_func", "ns::hks_cpp_func()"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let keep = collect_keep_sections(
+            [
+                ("hks_func", ".text.hks_func"),
+                ("_ZN2ns12hks_cpp_funcEv", ".text._ZN2ns12hks_cpp_funcEv"),
+                ("__mw_hook_bl$0x100000$MFZQ$1$0@0", ".text.sym_hook_func"),
+                ("__mw_hook_pre$0x100004$MFZQ$2$0@0", ".mw_loader_text"),
+                ("unused_func", ".text.unused_func"),
+            ],
+            &referenced,
+            &HookPrefix::default(),
+        );
+
+        assert_eq!(
+            keep.into_iter().collect::<Vec<_>>(),
+            vec![
+                ".text._ZN2ns12hks_cpp_funcEv",
+                ".text.hks_func",
+                ".text.sym_hook_func",
+            ]
+        );
+    }
+}
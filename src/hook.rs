@@ -1,17 +1,21 @@
 pub mod arm;
+mod discover;
 mod error;
 pub mod hks;
 mod info;
 mod kind;
 mod location;
 mod meta;
+pub mod schema;
 pub mod symbol_safe;
 mod util;
 mod writer;
 
+pub use discover::{discover_section_hooks, discover_symbol_hooks, CodeModes};
 pub use error::*;
-pub use info::HookInfo;
+pub use info::{HookInfo, HookPrefix};
 pub use kind::HookKind;
 pub use location::HookLocation;
 use meta::HookMeta;
-pub use writer::{HookExtraPos, HookWriter};
+pub use util::{parse_address, parse_hex_data};
+pub use writer::{HookExtraPos, HookOverlap, HookWriteReason, HookWriter};
@@ -1,8 +1,18 @@
+//! Encoders for the ARM (A32) instructions magwi emits into patched code.
+//!
+//! All encodings follow the ARM Architecture Reference Manual (ARMv6K as implemented by the
+//! ARM11 MPCore of the 3DS). Addresses are absolute and must be word aligned. Branch offsets
+//! are relative to the branch address + 8, as the PC reads two instructions ahead.
+//!
+//! Branch destinations in Thumb code are marked by bit 0 of their address, like the values of
+//! Thumb function symbols in ELF files.
+
 use super::error::*;
 use super::util::parse_address;
 
 use std::str::FromStr;
 
+/// Condition field (bits 28-31) shared by all conditional ARM instructions.
 #[repr(u8)]
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum ArmCondition {
@@ -53,6 +63,7 @@ impl FromStr for ArmCondition {
     }
 }
 
+/// A `B`/`BL` instruction located at `from_addr`.
 #[derive(Debug, PartialEq)]
 pub struct ArmBranch {
     pub condition: ArmCondition,
@@ -60,20 +71,46 @@ pub struct ArmBranch {
     pub from_addr: u32,
 }
 
+/// Returns whether `address` is in Thumb code, i.e. has bit 0 set.
+pub fn is_thumb(address: u32) -> bool {
+    address & 1 != 0
+}
+
 impl ArmBranch {
-    pub fn to_u32(&self, to_addr: u32) -> Option<u32> {
-        let offset = (to_addr as i64 / 4) - (self.from_addr as i64 / 4) - 2;
-        if offset < -0x1000000 || offset > 0xFFFFFF {
-            return None;
+    /// Encodes the branch to `to_addr` (A8.8.18 `B`, A8.8.25 `BL`).
+    ///
+    /// `cond | 101 | L | imm24` where `imm24` is the signed word offset from `from_addr + 8`.
+    /// A `BL` to Thumb code becomes a `BLX` (immediate), `1111 | 101 | H | imm24` with the
+    /// halfword offset in `H`, which has no conditional or non-link form.
+    pub fn to_u32(&self, to_addr: u32) -> Result<u32, EncodingError> {
+        if !self.from_addr.is_multiple_of(4) {
+            return Err(EncodingError::UnalignedBranch(self.from_addr));
+        }
+
+        let thumb = is_thumb(to_addr);
+        if thumb && (!self.link || self.condition != ArmCondition::AL) {
+            return Err(EncodingError::ThumbBranch(self.from_addr, to_addr));
+        }
+
+        let to_addr = to_addr & !1;
+        let relative = to_addr as i64 - (self.from_addr as i64 + 8);
+        let offset = relative >> 2;
+        if !(-0x800000..=0x7FFFFF).contains(&offset) {
+            return Err(EncodingError::BranchOutOfRange(self.from_addr, to_addr));
         }
         let offset = (offset & 0xFFFFFF) as u32;
 
         let mut result = 0b101u32 << 25;
-        result |= (self.condition as u32) << 28;
-        result |= (self.link as u32) << 24;
+        if thumb {
+            result |= 0xF << 28;
+            result |= (((relative >> 1) & 1) as u32) << 24;
+        } else {
+            result |= (self.condition as u32) << 28;
+            result |= (self.link as u32) << 24;
+        }
         result |= offset;
 
-        Some(result)
+        Ok(result)
     }
 }
 
@@ -104,12 +141,13 @@ impl ArmBranch {
     }
 }
 
+/// Encodes a `B`/`BL`/`BLX` from `from_addr` to `to_addr`. See `ArmBranch::to_u32`.
 pub fn make_branch_u32(
     link: bool,
     from_addr: u32,
     to_addr: u32,
     condition: ArmCondition,
-) -> Option<u32> {
+) -> Result<u32, EncodingError> {
     ArmBranch {
         condition,
         link,
@@ -118,40 +156,409 @@ pub fn make_branch_u32(
     .to_u32(to_addr)
 }
 
+/// `MOV r0, r0`. Preferred over the `NOP` hint of ARMv6K, which older cores retargeted by
+/// `[cpu]` lack.
+pub fn make_nop_u32() -> u32 {
+    0xE1A00000
+}
+
+/// `MOV r8, r8`, the Thumb counterpart of `make_nop_u32`.
+pub fn make_thumb_nop_u16() -> u16 {
+    0x46C0
+}
+
+/// Addressing mode of `LDM`/`STM`, where the block starts relative to the base register.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ArmBlockMode {
+    /// Increment after, the block starts at the base
+    IA,
+    /// Increment before, the block starts a word after the base
+    #[cfg_attr(not(test), allow(dead_code))]
+    IB,
+    /// Decrement after, the block ends at the base
+    #[cfg_attr(not(test), allow(dead_code))]
+    DA,
+    /// Decrement before, the block ends a word before the base
+    DB,
+}
+
+impl ArmBlockMode {
+    /// P (bit 24) and U (bit 23) of the encoding.
+    fn bits(self) -> u32 {
+        match self {
+            ArmBlockMode::IA => 0x00800000,
+            ArmBlockMode::IB => 0x01800000,
+            ArmBlockMode::DA => 0x00000000,
+            ArmBlockMode::DB => 0x01000000,
+        }
+    }
+}
+
+const REGISTER_SP_INDEX: u32 = 13;
+const BLOCK_TRANSFER: u32 = 0x08000000;
+const BLOCK_WRITEBACK: u32 = 1 << 21;
+const BLOCK_LOAD: u32 = 1 << 20;
+
+fn make_block_transfer_u32(
+    load: bool,
+    base: u32,
+    writeback: bool,
+    mode: ArmBlockMode,
+    registers_bitfield: u16,
+    cond: ArmCondition,
+) -> u32 {
+    debug_assert_eq!(
+        check_block_registers(base, writeback, registers_bitfield),
+        Ok(())
+    );
+    let mut val = BLOCK_TRANSFER | (cond as u32) << 28 | mode.bits() | base << 16;
+    if writeback {
+        val |= BLOCK_WRITEBACK;
+    }
+    if load {
+        val |= BLOCK_LOAD;
+    }
+    val | registers_bitfield as u32
+}
+
+fn check_block_registers(
+    base: u32,
+    writeback: bool,
+    registers_bitfield: u16,
+) -> Result<(), EncodingError> {
+    if base >= REGISTER_PC_INDEX {
+        return Err(EncodingError::InvalidBaseRegister(base));
+    }
+    if registers_bitfield == 0 {
+        return Err(EncodingError::EmptyRegisterList);
+    }
+    if writeback && registers_bitfield & (1 << base) != 0 {
+        return Err(EncodingError::RegisterListContainsBase(
+            base,
+            registers_bitfield,
+        ));
+    }
+    Ok(())
+}
+
+/// Encodes `STM<mode> rn{!}, {registers}` (A8.8.199-A8.8.202), `base` is the index of `rn`.
+///
+/// Bit `n` of `registers_bitfield` selects register `rn`, so `0x5FFF` is `{r0-r12, lr}`.
+pub fn make_stm_u32(
+    base: u32,
+    writeback: bool,
+    mode: ArmBlockMode,
+    registers_bitfield: u16,
+    cond: ArmCondition,
+) -> u32 {
+    make_block_transfer_u32(false, base, writeback, mode, registers_bitfield, cond)
+}
+
+/// Encodes `LDM<mode> rn{!}, {registers}` (A8.8.58-A8.8.61), see `make_stm_u32`.
+pub fn make_ldm_u32(
+    base: u32,
+    writeback: bool,
+    mode: ArmBlockMode,
+    registers_bitfield: u16,
+    cond: ArmCondition,
+) -> u32 {
+    make_block_transfer_u32(true, base, writeback, mode, registers_bitfield, cond)
+}
+
+/// Checked variant of `make_stm_u32`. Rejects pc as base, empty register lists and lists
+/// containing the base register with writeback, which is unpredictable.
+#[allow(dead_code)]
+pub fn try_make_stm_u32(
+    base: u32,
+    writeback: bool,
+    mode: ArmBlockMode,
+    registers_bitfield: u16,
+    cond: ArmCondition,
+) -> Result<u32, EncodingError> {
+    check_block_registers(base, writeback, registers_bitfield)?;
+    Ok(make_stm_u32(
+        base,
+        writeback,
+        mode,
+        registers_bitfield,
+        cond,
+    ))
+}
+
+/// Checked variant of `make_ldm_u32`, see `try_make_stm_u32`.
+#[allow(dead_code)]
+pub fn try_make_ldm_u32(
+    base: u32,
+    writeback: bool,
+    mode: ArmBlockMode,
+    registers_bitfield: u16,
+    cond: ArmCondition,
+) -> Result<u32, EncodingError> {
+    check_block_registers(base, writeback, registers_bitfield)?;
+    Ok(make_ldm_u32(
+        base,
+        writeback,
+        mode,
+        registers_bitfield,
+        cond,
+    ))
+}
+
+/// Encodes `PUSH {registers}`, i.e. `STMDB sp!, {registers}` (A8.8.133).
+///
+/// Bit `n` of `registers_bitfield` selects register `rn`, so `0x5FFF` is `{r0-r12, lr}`.
 pub fn make_push_u32(registers_bitfield: u16, cond: ArmCondition) -> u32 {
-    0x092D0000u32 | (cond as u32) << 28 | registers_bitfield as u32
+    debug_assert_eq!(check_push_registers(registers_bitfield), Ok(()));
+    make_stm_u32(
+        REGISTER_SP_INDEX,
+        true,
+        ArmBlockMode::DB,
+        registers_bitfield,
+        cond,
+    )
+}
+
+const REGISTER_SP: u16 = 1 << 13;
+const REGISTER_PC: u16 = 1 << 15;
+
+fn check_push_registers(registers_bitfield: u16) -> Result<(), EncodingError> {
+    if registers_bitfield & REGISTER_PC != 0 {
+        return Err(EncodingError::RegisterListContainsPc(registers_bitfield));
+    }
+    check_pop_registers(registers_bitfield)
+}
+
+fn check_pop_registers(registers_bitfield: u16) -> Result<(), EncodingError> {
+    if registers_bitfield == 0 {
+        return Err(EncodingError::EmptyRegisterList);
+    }
+    if registers_bitfield & REGISTER_SP != 0 {
+        return Err(EncodingError::RegisterListContainsSp(registers_bitfield));
+    }
+    Ok(())
+}
+
+/// Checked variant of `make_push_u32`. Rejects empty register lists and lists containing
+/// sp (unpredictable with writeback) or pc (stores an implementation defined value).
+pub fn try_make_push_u32(
+    registers_bitfield: u16,
+    cond: ArmCondition,
+) -> Result<u32, EncodingError> {
+    check_push_registers(registers_bitfield)?;
+    Ok(make_push_u32(registers_bitfield, cond))
 }
 
+/// Encodes `POP {registers}`, i.e. `LDMIA sp!, {registers}` (A8.8.131).
+///
+/// Bit `n` of `registers_bitfield` selects register `rn`, so `0x5FFF` is `{r0-r12, lr}`.
 pub fn make_pop_u32(registers_bitfield: u16, cond: ArmCondition) -> u32 {
-    0x08BD0000u32 | (cond as u32) << 28 | registers_bitfield as u32
+    debug_assert_eq!(check_pop_registers(registers_bitfield), Ok(()));
+    make_ldm_u32(
+        REGISTER_SP_INDEX,
+        true,
+        ArmBlockMode::IA,
+        registers_bitfield,
+        cond,
+    )
+}
+
+/// Checked variant of `make_pop_u32`. Rejects empty register lists and lists containing sp.
+/// Popping pc is allowed as it is the usual way to return.
+pub fn try_make_pop_u32(registers_bitfield: u16, cond: ArmCondition) -> Result<u32, EncodingError> {
+    check_pop_registers(registers_bitfield)?;
+    Ok(make_pop_u32(registers_bitfield, cond))
+}
+
+const CONDITION_SUFFIXES: [&str; 16] = [
+    "eq", "ne", "cs", "cc", "mi", "pl", "vs", "vc", "hi", "ls", "ge", "lt", "gt", "le", "", "nv",
+];
+
+fn register_list_str(registers_bitfield: u16) -> String {
+    (0..16)
+        .filter(|i| registers_bitfield & (1 << i) != 0)
+        .map(|i| match i {
+            13 => "sp".to_string(),
+            14 => "lr".to_string(),
+            15 => "pc".to_string(),
+            _ => format!("r{i}"),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Describes the instruction `val` located at `address`.
+///
+/// Only knows the instructions magwi generates itself: `B`/`BL`/`BLX` with their absolute
+/// destination and `PUSH`/`POP`. Everything else is shown as `.word`.
+pub fn disassemble_u32(val: u32, address: u32) -> String {
+    let cond = CONDITION_SUFFIXES[(val >> 28) as usize];
+
+    match (val >> 24) & 0xF {
+        0xA | 0xB if val >> 28 == 0xF => {
+            let dest = branch_destination(val, address);
+            format!("blx 0x{dest:08x}")
+        }
+        0xA | 0xB => {
+            let link = if val & 0x01000000 != 0 { "l" } else { "" };
+            let dest = branch_destination(val, address);
+            format!("b{link}{cond} 0x{dest:08x}")
+        }
+        _ if val & 0x0FFF0000 == 0x092D0000 => {
+            format!("push{cond} {{{}}}", register_list_str(val as u16))
+        }
+        _ if val & 0x0FFF0000 == 0x08BD0000 => {
+            format!("pop{cond} {{{}}}", register_list_str(val as u16))
+        }
+        _ => format!(".word 0x{val:08x}"),
+    }
+}
+
+/// How an instruction has to be adjusted when it is copied to a different address.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RelocationClass {
+    /// Doesn't depend on its address, e.g. data processing and loads/stores not involving pc.
+    Verbatim,
+    /// `B`/`BL`/`BLX` (immediate), the offset is adjusted to keep the destination.
+    Branch,
+    /// `LDR`/`LDRB`/`STR`/`STRB` with a pc relative immediate offset, e.g. `ldr r0, =literal`.
+    /// The offset is adjusted to keep the accessed address.
+    PcRelativeLoadStore,
+    /// Reads pc in a way that can't be adjusted, e.g. `adr`, `mov r0, pc` or `vldr` literals.
+    Unsupported,
+}
+
+const REGISTER_PC_INDEX: u32 = 15;
+
+/// Destination of the `B`/`BL`/`BLX` (immediate) `val` at `address`, including the halfword
+/// offset of `BLX` which always switches to Thumb.
+fn branch_destination(val: u32, address: u32) -> u32 {
+    let mut offset = ((((val << 8) as i32) >> 8) as i64 + 2) * 4;
+    if val >> 28 == 0xF {
+        offset += ((val >> 23) & 2) as i64;
+    }
+    (address as i64 + offset) as u32
 }
 
-pub fn relocate_u32(val: u32, src_address: u32, dest_address: u32) -> Option<u32> {
-    let mut r = val;
+/// Classifies the instruction `val` by how it can be relocated. See `RelocationClass`.
+///
+/// Instructions where using pc is unpredictable are not considered.
+pub fn classify_relocation(val: u32) -> RelocationClass {
+    let rn = (val >> 16) & 0xF;
+    let rd = (val >> 12) & 0xF;
+    let rm = val & 0xF;
+    let immediate = val & (1 << 25) != 0;
+
+    // b/bl, and blx (immediate) in the unconditional space
+    if (val >> 25) & 0b111 == 0b101 {
+        return RelocationClass::Branch;
+    }
+
+    // Other unconditional instructions (A5.7) are hints and system instructions
+    if val >> 28 == 0xF {
+        return RelocationClass::Verbatim;
+    }
+
+    let reads_pc = match (val >> 25) & 0b111 {
+        0b000 | 0b001 => {
+            let opcode = (val >> 21) & 0xF;
 
-    let nybble14 = (val >> 24) & 0xF;
+            if !immediate && val & 0x90 == 0x90 {
+                // Multiplies with pc are unpredictable, extra loads/stores (A5.2.8) use rn and
+                // an immediate or rm
+                let extra_load_store = val & 0x60 != 0;
+                let register_offset = val & (1 << 22) == 0;
+                extra_load_store
+                    && (rn == REGISTER_PC_INDEX || (register_offset && rm == REGISTER_PC_INDEX))
+            } else if opcode & 0b1100 == 0b1000 && val & (1 << 20) == 0 {
+                // Miscellaneous instructions like bx, blx (register) and clz only use rm,
+                // msr (immediate) and hints use none (A5.2.12)
+                !immediate && rm == REGISTER_PC_INDEX
+            } else {
+                // mov and mvn have no rn
+                let uses_rn = opcode != 0b1101 && opcode != 0b1111;
+                (uses_rn && rn == REGISTER_PC_INDEX) || (!immediate && rm == REGISTER_PC_INDEX)
+            }
+        }
+        0b010 | 0b011 => {
+            // The immediate bit selects a register offset here
+            let register_offset = immediate;
+
+            // Media instructions share the space with register offsets (A5.3)
+            if register_offset && val & 0x10 != 0 {
+                return RelocationClass::Verbatim;
+            }
 
-    // b/bl
-    if nybble14 == 0xA || nybble14 == 0xB {
-        r &= 0xFF000000;
+            // Storing pc stores the address of the instruction + 8
+            let stores_pc = val & (1 << 20) == 0 && rd == REGISTER_PC_INDEX;
+            let offset_only = val & (1 << 24) != 0 && val & (1 << 21) == 0;
 
-        let old_offset = ((val as i64 & 0xFFFFFF) + 2) * 4;
-        let b_dest_address = src_address as i64 + old_offset;
-        let new_offset = (b_dest_address / 4) - (dest_address as i64 / 4) - 2;
+            if rn == REGISTER_PC_INDEX && !register_offset && offset_only && !stores_pc {
+                return RelocationClass::PcRelativeLoadStore;
+            }
 
-        if new_offset < -0x1000000 || new_offset > 0xFFFFFF {
-            return None;
+            rn == REGISTER_PC_INDEX || (register_offset && rm == REGISTER_PC_INDEX) || stores_pc
+        }
+        0b100 => {
+            // ldm/stm, storing pc stores the address of the instruction + 8
+            let store = val & (1 << 20) == 0;
+            rn == REGISTER_PC_INDEX || (store && val & (1 << 15) != 0)
         }
+        // Coprocessor loads/stores, e.g. vldr from a literal pool
+        0b110 => rn == REGISTER_PC_INDEX,
+        _ => false,
+    };
 
-        r |= (new_offset & 0xFFFFFF) as u32;
+    match reads_pc {
+        true => RelocationClass::Unsupported,
+        false => RelocationClass::Verbatim,
     }
+}
+
+/// Relocates the instruction `val` from `src_address` to `dest_address`, keeping its effect.
+///
+/// `B`/`BL`/`BLX` get their offset adjusted so they keep branching to the same absolute address,
+/// pc relative `LDR`/`STR` keep accessing the same address. Instructions not depending on their
+/// address are copied as is. See `classify_relocation` for which instructions are supported.
+pub fn relocate_u32(val: u32, src_address: u32, dest_address: u32) -> Result<u32, RelocationError> {
+    match classify_relocation(val) {
+        RelocationClass::Verbatim => Ok(val),
+        RelocationClass::Branch => {
+            let b_dest_address = branch_destination(val, src_address);
+            let relative = b_dest_address as i64 - (dest_address as i64 + 8);
+            let new_offset = relative >> 2;
 
-    Some(r)
+            if !(-0x800000..=0x7FFFFF).contains(&new_offset) {
+                return Err(RelocationError::OutOfRange(val, dest_address));
+            }
+
+            // The halfword offset of blx may change as well
+            let mut result = (val & 0xFF000000) | (new_offset & 0xFFFFFF) as u32;
+            if val >> 28 == 0xF {
+                result = (result & !(1 << 24)) | (((relative >> 1) & 1) as u32) << 24;
+            }
+            Ok(result)
+        }
+        RelocationClass::PcRelativeLoadStore => {
+            let imm12 = (val & 0xFFF) as i64;
+            let old_offset = if val & (1 << 23) != 0 { imm12 } else { -imm12 };
+            let target_address = src_address as i64 + 8 + old_offset;
+            let new_offset = target_address - (dest_address as i64 + 8);
+
+            if new_offset.abs() > 0xFFF {
+                return Err(RelocationError::OutOfRange(val, dest_address));
+            }
+
+            let up = ((new_offset >= 0) as u32) << 23;
+            Ok((val & !(0xFFF | (1 << 23))) | up | new_offset.unsigned_abs() as u32)
+        }
+        RelocationClass::Unsupported => Err(RelocationError::Unsupported(val)),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     #[test]
     fn test_parse_condition() {
@@ -183,6 +590,448 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_make_branch() {
+        assert_eq!(
+            make_branch_u32(false, 0x100000, 0x100008, ArmCondition::AL),
+            Ok(0xEA000000)
+        );
+        assert_eq!(
+            make_branch_u32(true, 0x100000, 0x100010, ArmCondition::AL),
+            Ok(0xEB000002)
+        );
+        assert_eq!(
+            make_branch_u32(true, 0x100008, 0x100000, ArmCondition::AL),
+            Ok(0xEBFFFFFC)
+        );
+        assert_eq!(
+            make_branch_u32(false, 0x100000, 0x100000, ArmCondition::NE),
+            Ok(0x1AFFFFFE)
+        );
+    }
+
+    #[test]
+    fn test_make_thumb_branch() {
+        // bl to a Thumb function becomes blx, H holds bit 1 of the offset
+        assert_eq!(
+            make_branch_u32(true, 0x100000, 0x100101, ArmCondition::AL),
+            Ok(0xFA00003E)
+        );
+        assert_eq!(
+            make_branch_u32(true, 0x100000, 0x100103, ArmCondition::AL),
+            Ok(0xFB00003E)
+        );
+        assert_eq!(
+            make_branch_u32(true, 0x100100, 0x100001, ArmCondition::AL),
+            Ok(0xFAFFFFBE)
+        );
+
+        for (from_addr, to_addr) in [
+            (0x100000, 0x100101),
+            (0x100000, 0x100103),
+            (0x100400, 0x0FF00F),
+        ] {
+            let val = make_branch_u32(true, from_addr, to_addr, ArmCondition::AL).unwrap();
+            assert_eq!(branch_destination(val, from_addr), to_addr & !1);
+            assert_eq!(classify_relocation(val), RelocationClass::Branch);
+
+            // Relocating may flip H
+            for new_addr in [0x200000, 0x0FF004] {
+                let relocated = relocate_u32(val, from_addr, new_addr).unwrap();
+                assert_eq!(branch_destination(relocated, new_addr), to_addr & !1);
+                assert_eq!(
+                    Ok(relocated),
+                    make_branch_u32(true, new_addr, to_addr, ArmCondition::AL)
+                );
+            }
+        }
+
+        // Only bl can switch to Thumb
+        assert_eq!(
+            make_branch_u32(false, 0x100000, 0x100101, ArmCondition::AL),
+            Err(EncodingError::ThumbBranch(0x100000, 0x100101))
+        );
+        assert_eq!(
+            make_branch_u32(true, 0x100000, 0x100101, ArmCondition::EQ),
+            Err(EncodingError::ThumbBranch(0x100000, 0x100101))
+        );
+        assert_eq!(
+            make_branch_u32(true, 0x100000, 0x2200001, ArmCondition::AL),
+            Err(EncodingError::BranchOutOfRange(0x100000, 0x2200000))
+        );
+
+        // The offset is in words, an unaligned branch would be silently misencoded
+        for from_addr in [0x100001, 0x100002, 0x100003] {
+            assert_eq!(
+                make_branch_u32(false, from_addr, 0x100100, ArmCondition::AL),
+                Err(EncodingError::UnalignedBranch(from_addr))
+            );
+        }
+    }
+
+    #[test]
+    fn test_make_push_pop() {
+        // push {r0-r12, lr} / pop {r0-r12, lr} as used by pre/post trampolines
+        assert_eq!(make_push_u32(0x5FFF, ArmCondition::AL), 0xE92D5FFF);
+        assert_eq!(make_pop_u32(0x5FFF, ArmCondition::AL), 0xE8BD5FFF);
+        assert_eq!(
+            make_push_u32(0x5FFF, ArmCondition::AL).to_le_bytes(),
+            [0xFF, 0x5F, 0x2D, 0xE9]
+        );
+        assert_eq!(
+            make_pop_u32(0x5FFF, ArmCondition::AL).to_le_bytes(),
+            [0xFF, 0x5F, 0xBD, 0xE8]
+        );
+
+        // push {lr} / pop {r4}
+        assert_eq!(make_push_u32(0x4000, ArmCondition::AL), 0xE92D4000);
+        assert_eq!(make_pop_u32(0x0010, ArmCondition::AL), 0xE8BD0010);
+
+        // Condition nibble
+        assert_eq!(make_push_u32(0x0001, ArmCondition::EQ), 0x092D0001);
+        assert_eq!(make_pop_u32(0x0001, ArmCondition::LT), 0xB8BD0001);
+    }
+
+    #[test]
+    fn test_try_make_push_pop() {
+        assert_eq!(try_make_push_u32(0x5FFF, ArmCondition::AL), Ok(0xE92D5FFF));
+        assert_eq!(try_make_pop_u32(0x5FFF, ArmCondition::AL), Ok(0xE8BD5FFF));
+        assert_eq!(try_make_pop_u32(0x8010, ArmCondition::AL), Ok(0xE8BD8010));
+
+        assert_eq!(
+            try_make_push_u32(0x0000, ArmCondition::AL),
+            Err(EncodingError::EmptyRegisterList)
+        );
+        assert_eq!(
+            try_make_pop_u32(0x0000, ArmCondition::AL),
+            Err(EncodingError::EmptyRegisterList)
+        );
+        assert_eq!(
+            try_make_push_u32(0x2001, ArmCondition::AL),
+            Err(EncodingError::RegisterListContainsSp(0x2001))
+        );
+        assert_eq!(
+            try_make_pop_u32(0x2001, ArmCondition::AL),
+            Err(EncodingError::RegisterListContainsSp(0x2001))
+        );
+        assert_eq!(
+            try_make_push_u32(0x8001, ArmCondition::AL),
+            Err(EncodingError::RegisterListContainsPc(0x8001))
+        );
+    }
+
+    #[test]
+    fn test_make_stm_ldm() {
+        let al = ArmCondition::AL;
+
+        // stmia r0!, {r1, r2} / stmib r0, {r1, r2} / stmda r0!, {r1, r2} / stmdb r0, {r1, r2}
+        assert_eq!(
+            make_stm_u32(0, true, ArmBlockMode::IA, 0x0006, al),
+            0xE8A00006
+        );
+        assert_eq!(
+            make_stm_u32(0, false, ArmBlockMode::IB, 0x0006, al),
+            0xE9800006
+        );
+        assert_eq!(
+            make_stm_u32(0, true, ArmBlockMode::DA, 0x0006, al),
+            0xE8200006
+        );
+        assert_eq!(
+            make_stm_u32(0, false, ArmBlockMode::DB, 0x0006, al),
+            0xE9000006
+        );
+
+        // ldmia r4, {r0-r3} / ldmib r4!, {r0-r3} / ldmda r4, {r0-r3} / ldmdb r4!, {r0-r3}
+        assert_eq!(
+            make_ldm_u32(4, false, ArmBlockMode::IA, 0x000F, al),
+            0xE894000F
+        );
+        assert_eq!(
+            make_ldm_u32(4, true, ArmBlockMode::IB, 0x000F, al),
+            0xE9B4000F
+        );
+        assert_eq!(
+            make_ldm_u32(4, false, ArmBlockMode::DA, 0x000F, al),
+            0xE814000F
+        );
+        assert_eq!(
+            make_ldm_u32(4, true, ArmBlockMode::DB, 0x000F, al),
+            0xE934000F
+        );
+
+        // Push and pop are the sp forms
+        assert_eq!(
+            make_stm_u32(13, true, ArmBlockMode::DB, 0x5FFF, al),
+            make_push_u32(0x5FFF, al)
+        );
+        assert_eq!(
+            make_ldm_u32(13, true, ArmBlockMode::IA, 0x5FFF, al),
+            make_pop_u32(0x5FFF, al)
+        );
+        assert_eq!(
+            make_ldm_u32(1, false, ArmBlockMode::IA, 0x0001, ArmCondition::NE),
+            0x18910001
+        );
+    }
+
+    #[test]
+    fn test_try_make_stm_ldm() {
+        let al = ArmCondition::AL;
+        assert_eq!(
+            try_make_stm_u32(0, true, ArmBlockMode::IA, 0x0006, al),
+            Ok(0xE8A00006)
+        );
+        // The base may be in the list without writeback
+        assert_eq!(
+            try_make_ldm_u32(4, false, ArmBlockMode::IA, 0x0010, al),
+            Ok(0xE8940010)
+        );
+
+        assert_eq!(
+            try_make_stm_u32(0, true, ArmBlockMode::IA, 0x0001, al),
+            Err(EncodingError::RegisterListContainsBase(0, 0x0001))
+        );
+        assert_eq!(
+            try_make_ldm_u32(4, true, ArmBlockMode::DB, 0x0011, al),
+            Err(EncodingError::RegisterListContainsBase(4, 0x0011))
+        );
+        assert_eq!(
+            try_make_ldm_u32(4, true, ArmBlockMode::DB, 0x0000, al),
+            Err(EncodingError::EmptyRegisterList)
+        );
+        assert_eq!(
+            try_make_stm_u32(15, false, ArmBlockMode::IA, 0x0001, al),
+            Err(EncodingError::InvalidBaseRegister(15))
+        );
+    }
+
+    #[test]
+    fn test_relocate() {
+        // Non-branch instructions are copied verbatim: mov r0, r1
+        assert_eq!(
+            relocate_u32(0xE1A00001, 0x100000, 0x200000),
+            Ok(0xE1A00001)
+        );
+
+        let cases = [
+            (0x100000, 0x100100, 0x200000),
+            (0x100100, 0x100000, 0x200000),
+            (0x200000, 0x100000, 0x100800),
+            (0x200000, 0x200004, 0x100800),
+        ];
+
+        for (from_addr, to_addr, new_addr) in cases {
+            for link in [false, true] {
+                let val = make_branch_u32(link, from_addr, to_addr, ArmCondition::AL).unwrap();
+                assert_eq!(branch_destination(val, from_addr), to_addr);
+
+                let relocated = relocate_u32(val, from_addr, new_addr).unwrap();
+                assert_eq!(branch_destination(relocated, new_addr), to_addr);
+                assert_eq!(relocated & 0xFF000000, val & 0xFF000000);
+                assert_eq!(
+                    Ok(relocated),
+                    make_branch_u32(link, new_addr, to_addr, ArmCondition::AL)
+                );
+            }
+        }
+
+        // Out of range after relocation
+        let val = make_branch_u32(false, 0x100000, 0x100100, ArmCondition::AL).unwrap();
+        assert_eq!(
+            relocate_u32(val, 0x100000, 0x4000000),
+            Err(RelocationError::OutOfRange(val, 0x4000000))
+        );
+    }
+
+    #[test]
+    fn test_classify_relocation() {
+        let cases = [
+            // push {r4, lr} / sub sp, sp, #0x10 / mov r4, r0 / add r0, r1, r2, lsl #2
+            (0xE92D4010, RelocationClass::Verbatim),
+            (0xE24DD010, RelocationClass::Verbatim),
+            (0xE1A04000, RelocationClass::Verbatim),
+            (0xE0810102, RelocationClass::Verbatim),
+            // cmp r0, #0 / bx lr / mrs r0, cpsr / mul r0, r1, r2
+            (0xE3500000, RelocationClass::Verbatim),
+            (0xE12FFF1E, RelocationClass::Verbatim),
+            (0xE10F0000, RelocationClass::Verbatim),
+            (0xE0000291, RelocationClass::Verbatim),
+            // ldr r0, [r1, #4] / strh r0, [r1, #-2] / ldrd r2, [sp, #8] / vldr d0, [r0]
+            (0xE5910004, RelocationClass::Verbatim),
+            (0xE14100B2, RelocationClass::Verbatim),
+            (0xE1CD20D8, RelocationClass::Verbatim),
+            (0xED900B00, RelocationClass::Verbatim),
+            // svc #0 / pld [r0]
+            (0xEF000000, RelocationClass::Verbatim),
+            (0xF5D0F000, RelocationClass::Verbatim),
+            // b / bleq / blx (immediate)
+            (0xEA000000, RelocationClass::Branch),
+            (0x0B000010, RelocationClass::Branch),
+            (0xFA000000, RelocationClass::Branch),
+            // ldr r0, [pc, #8] / ldrb r1, [pc, #-4] / str r0, [pc, #0] / ldr pc, [pc, #-4]
+            (0xE59F0008, RelocationClass::PcRelativeLoadStore),
+            (0xE55F1004, RelocationClass::PcRelativeLoadStore),
+            (0xE58F0000, RelocationClass::PcRelativeLoadStore),
+            (0xE51FF004, RelocationClass::PcRelativeLoadStore),
+            // adr r0, #8 (add r0, pc, #8) / mov r0, pc / add r0, r1, pc
+            (0xE28F0008, RelocationClass::Unsupported),
+            (0xE1A0000F, RelocationClass::Unsupported),
+            (0xE081000F, RelocationClass::Unsupported),
+            // ldrh r0, [pc, #4] / ldr r0, [r1, pc] / str pc, [sp]
+            (0xE1DF00B4, RelocationClass::Unsupported),
+            (0xE791000F, RelocationClass::Unsupported),
+            (0xE58DF000, RelocationClass::Unsupported),
+            // push {r0, pc} / vldr d0, [pc, #8] / bx pc
+            (0xE92D8001, RelocationClass::Unsupported),
+            (0xED9F0B02, RelocationClass::Unsupported),
+            (0xE12FFF1F, RelocationClass::Unsupported),
+        ];
+
+        for (val, class) in cases {
+            assert_eq!(classify_relocation(val), class, "0x{val:08x}");
+        }
+    }
+
+    #[test]
+    fn test_relocate_pc_relative_load_store() {
+        // ldr r0, [pc, #8] at 0x100000 loads from 0x100010
+        assert_eq!(relocate_u32(0xE59F0008, 0x100000, 0x100008), Ok(0xE59F0000));
+        assert_eq!(relocate_u32(0xE59F0008, 0x100000, 0x100010), Ok(0xE51F0008));
+        assert_eq!(relocate_u32(0xE59F0008, 0x100000, 0x101004), Ok(0xE51F0FFC));
+
+        // ldrb r1, [pc, #-4] at 0x100000 loads from 0x100004
+        assert_eq!(relocate_u32(0xE55F1004, 0x100000, 0x0FF004), Ok(0xE5DF1FF8));
+
+        assert_eq!(
+            relocate_u32(0xE59F0008, 0x100000, 0x101010),
+            Err(RelocationError::OutOfRange(0xE59F0008, 0x101010))
+        );
+        assert_eq!(
+            relocate_u32(0xE28F0008, 0x100000, 0x100004),
+            Err(RelocationError::Unsupported(0xE28F0008))
+        );
+    }
+
+    #[test]
+    fn test_branch_range_limits() {
+        let from_addr = 0x4000000;
+
+        // imm24 spans -0x800000..=0x7FFFFF words from from_addr + 8
+        let min = from_addr + 8 - 0x2000000;
+        let max = from_addr + 8 + 0x1FFFFFC;
+
+        for link in [false, true] {
+            let val = make_branch_u32(link, from_addr, min, ArmCondition::AL).unwrap();
+            assert_eq!(val & 0xFFFFFF, 0x800000);
+            assert_eq!(branch_destination(val, from_addr), min);
+
+            let val = make_branch_u32(link, from_addr, max, ArmCondition::AL).unwrap();
+            assert_eq!(val & 0xFFFFFF, 0x7FFFFF);
+            assert_eq!(branch_destination(val, from_addr), max);
+
+            assert_eq!(
+                make_branch_u32(link, from_addr, min - 4, ArmCondition::AL),
+                Err(EncodingError::BranchOutOfRange(from_addr, min - 4))
+            );
+            assert_eq!(
+                make_branch_u32(link, from_addr, max + 4, ArmCondition::AL),
+                Err(EncodingError::BranchOutOfRange(from_addr, max + 4))
+            );
+        }
+
+        // Relocating the branch at min so that it ends up exactly at the limits and past them
+        let val = make_branch_u32(false, from_addr, min, ArmCondition::AL).unwrap();
+        assert!(relocate_u32(val, from_addr, from_addr).is_ok());
+        assert_eq!(
+            relocate_u32(val, from_addr, from_addr + 4),
+            Err(RelocationError::OutOfRange(val, from_addr + 4))
+        );
+        let val = make_branch_u32(false, from_addr, max, ArmCondition::AL).unwrap();
+        assert!(relocate_u32(val, from_addr, from_addr).is_ok());
+        assert_eq!(
+            relocate_u32(val, from_addr, from_addr - 4),
+            Err(RelocationError::OutOfRange(val, from_addr - 4))
+        );
+    }
+
+    fn condition_strategy() -> impl Strategy<Value = ArmCondition> {
+        (0..15u8).prop_map(|c| ArmCondition::from_str(CONDITION_SUFFIXES[c as usize]).unwrap())
+    }
+
+    // Word aligned addresses at least 128MB away from both ends of the address space, so twice
+    // the branch range around them is representable
+    fn address_strategy() -> impl Strategy<Value = u32> {
+        (0x2000000u32..0x3C000000).prop_map(|w| w * 4)
+    }
+
+    proptest! {
+        #[test]
+        fn prop_branch_roundtrip(
+            from_addr in address_strategy(),
+            offset in -0x800000i64..=0x7FFFFF,
+            link: bool,
+            condition in condition_strategy(),
+        ) {
+            let to_addr = (from_addr as i64 + 8 + offset * 4) as u32;
+            let val = make_branch_u32(link, from_addr, to_addr, condition).unwrap();
+
+            prop_assert_eq!(branch_destination(val, from_addr), to_addr);
+            prop_assert_eq!(val >> 28, condition as u32);
+            prop_assert_eq!(val & 0x01000000 != 0, link);
+        }
+
+        #[test]
+        fn prop_branch_out_of_range(
+            from_addr in address_strategy(),
+            distance in 0x800000i64..0x1000000,
+            backwards: bool,
+        ) {
+            let offset = if backwards { -distance - 1 } else { distance };
+            let to_addr = (from_addr as i64 + 8 + offset * 4) as u32;
+
+            prop_assert_eq!(
+                make_branch_u32(false, from_addr, to_addr, ArmCondition::AL),
+                Err(EncodingError::BranchOutOfRange(from_addr, to_addr))
+            );
+        }
+
+        #[test]
+        fn prop_relocate_preserves_destination(
+            from_addr in address_strategy(),
+            offset in -0x800000i64..=0x7FFFFF,
+            new_offset in -0x800000i64..=0x7FFFFF,
+            link: bool,
+            condition in condition_strategy(),
+        ) {
+            let to_addr = (from_addr as i64 + 8 + offset * 4) as u32;
+            let new_addr = (to_addr as i64 - 8 - new_offset * 4) as u32;
+            let val = make_branch_u32(link, from_addr, to_addr, condition).unwrap();
+
+            let relocated = relocate_u32(val, from_addr, new_addr).unwrap();
+            prop_assert_eq!(branch_destination(relocated, new_addr), to_addr);
+            prop_assert_eq!(relocated & 0xFF000000, val & 0xFF000000);
+        }
+
+        #[test]
+        fn prop_relocate_out_of_range(
+            from_addr in address_strategy(),
+            offset in -0x800000i64..=0x7FFFFF,
+            distance in 0x800000i64..0x1000000,
+            backwards: bool,
+        ) {
+            let to_addr = (from_addr as i64 + 8 + offset * 4) as u32;
+            let new_offset = if backwards { -distance - 1 } else { distance };
+            let new_addr = (to_addr as i64 - 8 - new_offset * 4) as u32;
+            let val = make_branch_u32(false, from_addr, to_addr, ArmCondition::AL).unwrap();
+
+            prop_assert_eq!(
+                relocate_u32(val, from_addr, new_addr),
+                Err(RelocationError::OutOfRange(val, new_addr))
+            );
+        }
+    }
+
     #[test]
     fn test_parse_branch() {
         assert_eq!(
@@ -234,4 +1083,24 @@ mod tests {
             Err(ParsingError::InvalidAddress("xyz".to_string()))
         );
     }
+
+    #[test]
+    fn test_disassemble() {
+        let bl = make_branch_u32(true, 0x100000, 0x100100, ArmCondition::AL).unwrap();
+        assert_eq!(disassemble_u32(bl, 0x100000), "bl 0x00100100");
+
+        let bne = make_branch_u32(false, 0x100100, 0x100000, ArmCondition::NE).unwrap();
+        assert_eq!(disassemble_u32(bne, 0x100100), "bne 0x00100000");
+
+        assert_eq!(
+            disassemble_u32(make_push_u32(0x5FFF, ArmCondition::AL), 0),
+            "push {r0, r1, r2, r3, r4, r5, r6, r7, r8, r9, r10, r11, r12, lr}"
+        );
+        assert_eq!(
+            disassemble_u32(make_pop_u32(0x8010, ArmCondition::EQ), 0),
+            "popeq {r4, pc}"
+        );
+        assert_eq!(disassemble_u32(0xE1A00000, 0), ".word 0xe1a00000");
+        assert_eq!(disassemble_u32(0xFB00003E, 0x100000), "blx 0x00100102");
+    }
 }
@@ -0,0 +1,353 @@
+use object::{Object, ObjectSection, ObjectSymbol, SectionIndex, SymbolKind};
+
+use super::{Error, HookInfo, HookPrefix};
+
+/// Maps a parsing result of the `kind` hook `name` to the hook, `None` if it has no hook prefix.
+fn hook_or_skip(
+    kind: &'static str,
+    name: &str,
+    result: Result<HookInfo, Error>,
+) -> Result<Option<HookInfo>, Error> {
+    match result {
+        Ok(hi) => Ok(Some(hi)),
+        Err(Error::InvalidPrefix) => Ok(None),
+        Err(Error::MetaParsingError(e)) => Err(Error::InvalidMeta(kind, name.to_string(), e)),
+        Err(e) => Err(e),
+    }
+}
+
+/// Finds the section hooks of an object file, with their section names.
+pub fn discover_section_hooks(
+    obj: &object::File,
+    prefix: &HookPrefix,
+) -> Result<Vec<(String, HookInfo)>, Error> {
+    let mut hooks = Vec::new();
+
+    for section in obj.sections() {
+        let Ok(name) = section.name() else {
+            continue;
+        };
+
+        if let Some(hi) = hook_or_skip("section", name, HookInfo::from_section_str(name, prefix))? {
+            hooks.push((name.to_string(), hi));
+        }
+    }
+
+    Ok(hooks)
+}
+
+/// Whether a mapping symbol starts ARM code or data (`$a`, `$d`) or Thumb code (`$t`).
+/// The assembler may append a suffix like `$t.1`.
+fn mapping_symbol_thumb(name: &str) -> Option<bool> {
+    match name.split_once('.').map_or(name, |(kind, _)| kind) {
+        "$a" | "$d" => Some(false),
+        "$t" => Some(true),
+        _ => None,
+    }
+}
+
+/// Instruction set of the code in the sections of an ELF, from the mapping symbols the
+/// assembler marks each switch between ARM code, Thumb code and data with.
+#[derive(Debug, Default)]
+pub struct CodeModes {
+    /// Start of each region as (section, address, thumb), sorted
+    regions: Vec<(SectionIndex, u32, bool)>,
+}
+
+impl CodeModes {
+    pub fn from_elf(elf: &object::File) -> Self {
+        let mut regions = elf
+            .symbols()
+            .filter_map(|sym| {
+                let thumb = mapping_symbol_thumb(sym.name().ok()?)?;
+                Some((sym.section_index()?, sym.address() as u32, thumb))
+            })
+            .collect::<Vec<_>>();
+        regions.sort_by_key(|(section, address, _)| (section.0, *address));
+
+        Self { regions }
+    }
+
+    fn is_thumb(&self, section: SectionIndex, address: u32) -> bool {
+        let end = self
+            .regions
+            .partition_point(|(s, a, _)| (s.0, *a) <= (section.0, address));
+        end > 0 && matches!(self.regions[end - 1], (s, _, true) if s == section)
+    }
+
+    /// Address of `sym` with bit 0 set if it is code in a Thumb region. Thumb functions have
+    /// it already, plain labels of assembly only get it here. Data symbols are left as is.
+    pub fn symbol_address<'data>(&self, sym: &impl ObjectSymbol<'data>) -> u32 {
+        let address = sym.address() as u32;
+
+        // Untyped symbols are labels, depending on the object version they are reported as
+        // either kind
+        match (sym.kind(), sym.section_index()) {
+            (SymbolKind::Text | SymbolKind::Label | SymbolKind::Unknown, Some(section))
+                if self.is_thumb(section, address) =>
+            {
+                address | 1
+            }
+            _ => address,
+        }
+    }
+}
+
+/// Finds the symbol hooks of the linked ELF, with the addresses of their symbols. Addresses
+/// in Thumb code have bit 0 set, see `CodeModes`.
+pub fn discover_symbol_hooks(
+    elf: &object::File,
+    prefix: &HookPrefix,
+) -> Result<Vec<(u32, HookInfo)>, Error> {
+    let modes = CodeModes::from_elf(elf);
+    let mut hooks = Vec::new();
+
+    for sym in elf.symbols() {
+        let Ok(name) = sym.name() else {
+            continue;
+        };
+
+        if let Some(hi) = hook_or_skip("symbol", name, HookInfo::from_symbol_str(name, prefix))? {
+            hooks.push((modes.symbol_address(&sym), hi));
+        }
+    }
+
+    Ok(hooks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hook::{symbol_safe::path_to_symbol_safe, HookKind, HookLocation, MetaParsingError};
+
+    /// Builds a minimal little endian ELF32 relocatable with empty progbits sections and
+    /// absolute symbols.
+    fn make_elf(sections: &[&str], symbols: &[(&str, u32)]) -> Vec<u8> {
+        let symbols = symbols
+            .iter()
+            .map(|(name, value)| (*name, *value, 0xFFF1))
+            .collect::<Vec<_>>();
+        make_elf_in(sections, &symbols)
+    }
+
+    /// Like `make_elf`, symbols are given as (name, value, section index). The sections are
+    /// numbered from 1.
+    fn make_elf_in(sections: &[&str], symbols: &[(&str, u32, u16)]) -> Vec<u8> {
+        fn push_str(table: &mut Vec<u8>, s: &str) -> u32 {
+            let offset = table.len() as u32;
+            table.extend_from_slice(s.as_bytes());
+            table.push(0);
+            offset
+        }
+
+        let mut shstrtab = vec![0];
+        let mut strtab = vec![0];
+        let mut symtab = vec![0u8; 16];
+
+        for (name, value, section) in symbols {
+            let name = push_str(&mut strtab, name);
+            symtab.extend_from_slice(&name.to_le_bytes());
+            symtab.extend_from_slice(&value.to_le_bytes());
+            symtab.extend_from_slice(&0u32.to_le_bytes());
+            symtab.extend_from_slice(&[0x10, 0]); // global, no type
+            symtab.extend_from_slice(&section.to_le_bytes());
+        }
+
+        // name, type, offset, size, link, entsize
+        let mut headers = vec![(0, 0, 0, 0, 0, 0)];
+        let mut data = Vec::new();
+        let data_start = 52;
+
+        for name in sections {
+            let name = push_str(&mut shstrtab, name);
+            headers.push((name, 1, data_start + data.len() as u32, 0, 0, 0));
+        }
+
+        let symtab_index = headers.len() as u32;
+        let name = push_str(&mut shstrtab, ".symtab");
+        headers.push((name, 2, 0, symtab.len() as u32, symtab_index + 1, 16));
+        let name = push_str(&mut shstrtab, ".strtab");
+        headers.push((name, 3, 0, strtab.len() as u32, 0, 0));
+        let name = push_str(&mut shstrtab, ".shstrtab");
+        headers.push((name, 3, 0, 0, 0, 0));
+        let shstrtab_index = headers.len() - 1;
+        headers[shstrtab_index].3 = shstrtab.len() as u32;
+
+        for (index, table) in [
+            (symtab_index as usize, &symtab),
+            (symtab_index as usize + 1, &strtab),
+            (shstrtab_index, &shstrtab),
+        ] {
+            headers[index].2 = data_start + data.len() as u32;
+            data.extend_from_slice(table);
+            data.resize((data.len() + 3) & !3, 0);
+        }
+
+        let shoff = data_start + data.len() as u32;
+
+        let mut elf = vec![0x7F, b'E', b'L', b'F', 1, 1, 1];
+        elf.resize(16, 0);
+        elf.extend_from_slice(&1u16.to_le_bytes()); // relocatable
+        elf.extend_from_slice(&40u16.to_le_bytes()); // arm
+        elf.extend_from_slice(&1u32.to_le_bytes());
+        elf.extend_from_slice(&[0; 8]); // entry, phoff
+        elf.extend_from_slice(&shoff.to_le_bytes());
+        elf.extend_from_slice(&0u32.to_le_bytes());
+        for v in [52u16, 0, 0, 40, headers.len() as u16, shstrtab_index as u16] {
+            elf.extend_from_slice(&v.to_le_bytes());
+        }
+        elf.extend_from_slice(&data);
+
+        for (name, kind, offset, size, link, entsize) in headers {
+            let info = if kind == 2 { 1 } else { 0 };
+            for v in [name, kind, 0, 0, offset, size, link, info, 4, entsize] {
+                elf.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+
+        elf
+    }
+
+    #[test]
+    fn test_discover_section_hooks() {
+        let file = path_to_symbol_safe("source/main.cpp");
+        let hook = format!(".__mw_hook_replace$0x100000${file}$12$0");
+        let data = make_elf(&[".text", &hook], &[]);
+        let obj = object::File::parse(data.as_slice()).unwrap();
+
+        assert_eq!(
+            discover_section_hooks(&obj, &HookPrefix::default()).unwrap(),
+            vec![(
+                hook.clone(),
+                HookInfo {
+                    kind: HookKind::Replace(0x100000),
+                    location: HookLocation {
+                        file: "source/main.cpp".into(),
+                        line: 12,
+                    },
+                    counter: 0,
+                }
+            )]
+        );
+
+        let table = format!(".__mw_hook_data$0x200000${file}$20$1");
+        let data = make_elf(&[&table], &[]);
+        let obj = object::File::parse(data.as_slice()).unwrap();
+        let sections = discover_section_hooks(&obj, &HookPrefix::default()).unwrap();
+        assert_eq!(sections.len(), 1);
+        assert_eq!(sections[0].1.kind, HookKind::Data(0x200000));
+
+        let data = make_elf(&[".__mw_hook_replace"], &[]);
+        let obj = object::File::parse(data.as_slice()).unwrap();
+        assert_eq!(
+            discover_section_hooks(&obj, &HookPrefix::default()),
+            Err(Error::InvalidMeta(
+                "section",
+                ".__mw_hook_replace".into(),
+                MetaParsingError::MissingArgument
+            ))
+        );
+    }
+
+    #[test]
+    fn test_discover_symbol_hooks() {
+        let file = path_to_symbol_safe("source/main.cpp");
+        let hook = format!("__mw_hook_symptr$0x100010${file}$3$1");
+        let data = make_elf(&[".text"], &[("func", 0x200000), (&hook, 0x200004)]);
+        let elf = object::File::parse(data.as_slice()).unwrap();
+
+        assert_eq!(
+            discover_symbol_hooks(&elf, &HookPrefix::default()).unwrap(),
+            vec![(
+                0x200004,
+                HookInfo {
+                    kind: HookKind::Symptr(0x100010),
+                    location: HookLocation {
+                        file: "source/main.cpp".into(),
+                        line: 3,
+                    },
+                    counter: 1,
+                }
+            )]
+        );
+
+        let hook = "__mw_hook_pre$0x100000$BADB32$1$0@0";
+        let data = make_elf(&[".text"], &[(hook, 0x200000)]);
+        let elf = object::File::parse(data.as_slice()).unwrap();
+        let e = discover_symbol_hooks(&elf, &HookPrefix::default()).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            format!("Parsing hook symbol \"{hook}\" failed: Invalid file: Invalid base32")
+        );
+    }
+
+    #[test]
+    fn test_discover_thumb_symbol_hooks() {
+        // Hook labels of assembly have no Thumb bit, the mapping symbols tell the mode
+        let file = path_to_symbol_safe("source/hooks.s");
+        let arm_hook = format!("__mw_hook_bl$0x100004${file}$3$0");
+        let thumb_hook = format!("__mw_hook_bl$0x100000${file}$9$1");
+        let data = make_elf_in(
+            &[".text", ".rodata"],
+            &[
+                ("$a", 0x200000, 1),
+                (&arm_hook, 0x200000, 1),
+                ("$t.1", 0x200010, 1),
+                (&thumb_hook, 0x200010, 1),
+                ("$d", 0x200020, 1),
+                ("table", 0x200020, 1),
+                ("other", 0x200100, 2),
+            ],
+        );
+        let elf = object::File::parse(data.as_slice()).unwrap();
+
+        let modes = CodeModes::from_elf(&elf);
+        let addresses = elf
+            .symbols()
+            .map(|sym| (sym.name().unwrap(), modes.symbol_address(&sym)))
+            .collect::<Vec<_>>();
+        assert!(addresses.contains(&("table", 0x200020)));
+        assert!(addresses.contains(&("other", 0x200100)));
+
+        let hooks = discover_symbol_hooks(&elf, &HookPrefix::default()).unwrap();
+        let [(arm_address, arm), (thumb_address, thumb)] = hooks.as_slice() else {
+            panic!("expected two hooks, got {hooks:?}");
+        };
+        assert_eq!(*arm_address, 0x200000);
+        assert_eq!(*thumb_address, 0x200011);
+
+        // The ARM code at 0x100000 calls the Thumb function with blx
+        let (HookKind::Branch(arm), HookKind::Branch(thumb)) = (&arm.kind, &thumb.kind) else {
+            panic!("expected branch hooks");
+        };
+        assert_eq!(arm.to_u32(*arm_address), Ok(0xEB03FFFD));
+        assert_eq!(thumb.to_u32(*thumb_address), Ok(0xFA040002));
+    }
+
+    #[test]
+    fn test_discover_custom_prefix() {
+        let prefix = HookPrefix {
+            section: ".__game_hook_".into(),
+            symbol: "__game_hook_".into(),
+        };
+        let file = path_to_symbol_safe("source/main.cpp");
+        let section = format!(".__game_hook_replace$0x100000${file}$1$0");
+        let symbol = format!("__game_hook_symptr$0x100010${file}$2$1@0");
+        let other = format!("__mw_hook_symptr$0x100020${file}$3$2@0");
+        let data = make_elf(&[&section], &[(&symbol, 0x200000), (&other, 0x200004)]);
+        let elf = object::File::parse(data.as_slice()).unwrap();
+
+        let sections = discover_section_hooks(&elf, &prefix).unwrap();
+        assert_eq!(sections.len(), 1);
+        assert_eq!(sections[0].1.kind, HookKind::Replace(0x100000));
+
+        let symbols = discover_symbol_hooks(&elf, &prefix).unwrap();
+        assert_eq!(symbols.len(), 1);
+        assert_eq!(symbols[0].0, 0x200000);
+        assert_eq!(symbols[0].1.kind, HookKind::Symptr(0x100010));
+
+        assert!(discover_section_hooks(&elf, &HookPrefix::default())
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -1,5 +1,5 @@
-use super::HookLocation;
 use super::symbol_safe;
+use super::HookLocation;
 
 #[derive(thiserror::Error, Debug, PartialEq)]
 pub enum MetaParsingError {
@@ -18,7 +18,7 @@ pub enum MetaParsingError {
     #[error("Missing counter")]
     MissingCounter,
 
-    #[error("Invalid file: \"{0}\"")]
+    #[error("Invalid file: {0}")]
     InvalidFile(symbol_safe::DecodeError),
 
     #[error("Invalid line: \"{0}\"")]
@@ -41,6 +41,48 @@ pub enum ParsingError {
 
     #[error("Invalid instruction condition: \"{0}\"")]
     InvalidCondition(String),
+
+    #[error("Invalid hex character '{0}' at index {1}")]
+    InvalidHexCharacter(char, usize),
+
+    #[error("Odd number of hex digits ({0}), must be a multiple of 2")]
+    OddHexLength(usize),
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum EncodingError {
+    #[error("Empty register list")]
+    EmptyRegisterList,
+
+    #[error("Register list 0x{0:04x} must not contain sp")]
+    RegisterListContainsSp(u16),
+
+    #[error("Register list 0x{0:04x} must not contain pc")]
+    RegisterListContainsPc(u16),
+
+    #[error("Register list 0x{1:04x} must not contain the base register r{0} with writeback")]
+    RegisterListContainsBase(u32, u16),
+
+    #[error("Base register r{0} must be one of r0-r14")]
+    InvalidBaseRegister(u32),
+
+    #[error("Branch address 0x{0:x} is not word aligned")]
+    UnalignedBranch(u32),
+
+    #[error("Branch destination 0x{1:x} is out of range from 0x{0:x}")]
+    BranchOutOfRange(u32, u32),
+
+    #[error("Branch from 0x{0:x} to Thumb code at 0x{1:x} must be an unconditional bl")]
+    ThumbBranch(u32, u32),
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum RelocationError {
+    #[error("Relocated 0x{0:08x} can't reach its target from 0x{1:x}")]
+    OutOfRange(u32, u32),
+
+    #[error("Instruction 0x{0:08x} reads pc and can't be relocated")]
+    Unsupported(u32),
 }
 
 #[derive(thiserror::Error, Debug, PartialEq)]
@@ -57,6 +99,9 @@ pub enum WriterError {
     #[error("Loader extra data address not set")]
     LoaderExtraAddressNotSet,
 
+    #[error("Loader overflow at 0x{0:x} with size 0x{1:x}")]
+    LoaderOverflow(u32, usize),
+
     #[error("Duplicate write at 0x{0:x} with size 0x{1:x}")]
     DuplicateWrite(u32, usize),
 }
@@ -69,6 +114,10 @@ pub enum Error {
     #[error("{0}")]
     MetaParsingError(#[from] MetaParsingError),
 
+    /// Hook kind ("symbol" or "section") and full name of a hook that failed to parse
+    #[error("Parsing hook {0} \"{1}\" failed: {2}")]
+    InvalidMeta(&'static str, String, MetaParsingError),
+
     #[error("{0}")]
     ParsingError(ParsingError, HookLocation),
 }
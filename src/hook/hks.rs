@@ -24,6 +24,12 @@ pub enum HksParseError {
 
     #[error("Invalid {0} value: {1}")]
     InvalidTypeValue(String, String),
+
+    #[error("Invalid address value, neither a number nor a known symbol: {0}")]
+    UnknownAddressSymbol(String),
+
+    #[error("Symbol \"{0}\" not found")]
+    UnknownSymbol(String),
 }
 
 #[derive(Debug, PartialEq)]
@@ -46,10 +52,19 @@ impl HksEntry {
         self.kv.keys().map(|s| s.as_str())
     }
 
+    /// Keys and values not consumed yet.
+    pub fn remaining(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.kv.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
     pub fn has(&self, key: &str) -> bool {
         self.kv.contains_key(key)
     }
 
+    pub fn peek(&self, key: &str) -> Option<&str> {
+        self.kv.get(key).map(|s| s.as_str())
+    }
+
     pub fn get(&mut self, key: &str) -> Result<String, HksParseError> {
         if let Some(value) = self.kv.remove(key) {
             return Ok(value);
@@ -68,11 +83,35 @@ impl HksEntry {
         }
     }
 
+    pub fn get_integer(&mut self, key: &str) -> Result<i32, HksParseError> {
+        let value = self.get(key)?;
+        value
+            .parse()
+            .map_err(|_| HksParseError::InvalidTypeValue("integer".into(), value))
+    }
+
     pub fn get_address(&mut self, key: &str) -> Result<u32, HksParseError> {
         let value = self.get(key)?;
         super::util::parse_address(value.as_str())
             .map_err(|_| HksParseError::InvalidTypeValue("address".into(), value.into()))
     }
+
+    /// Comma separated values, a trailing comma is allowed.
+    pub fn get_list(&mut self, key: &str) -> Result<Vec<String>, HksParseError> {
+        let value = self.get(key)?;
+        let values = value
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .collect::<Vec<_>>();
+
+        let trailing = values.len() > 1 && values.last().is_some_and(|s| s.is_empty());
+        let values = &values[..values.len() - trailing as usize];
+        if values.iter().any(|s| s.is_empty()) {
+            return Err(HksParseError::InvalidTypeValue("list".into(), value));
+        }
+
+        Ok(values.to_vec())
+    }
 }
 pub struct HksReader<T>
 where
@@ -95,6 +134,11 @@ where
         }
     }
 
+    /// Number of the last line read, the offending line after an error.
+    pub fn line(&self) -> usize {
+        self.line_i
+    }
+
     fn next_line(&mut self) -> Option<Result<String, std::io::Error>> {
         let r = self.reader_lines.next();
         if r.is_some() {
@@ -103,6 +147,17 @@ where
         r
     }
 
+    /// Whether `line` starts with a `<key>:`. Anything else, e.g. a `ns::func` symbol,
+    /// continues a value.
+    fn starts_key(line: &str) -> bool {
+        let line = line.trim_start();
+        let key_len = line
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(line.len());
+        let rest = &line[key_len..];
+        key_len > 0 && rest.starts_with(':') && !rest.starts_with("::")
+    }
+
     fn line_strip_comment_and_truncate_end(line: &mut String) {
         if let Some(comment_start) = line.find('#') {
             line.truncate(comment_start);
@@ -149,7 +204,8 @@ where
             return None;
         };
 
-        let mut kv = HashMap::new();
+        let mut kv: HashMap<String, String> = HashMap::new();
+        let mut last_key: Option<String> = None;
 
         loop {
             let Some(Ok(mut line)) = self.next_line() else {
@@ -172,6 +228,18 @@ where
                 break;
             }
 
+            // A value ending with a comma continues on the following line, e.g. long lists or
+            // patch data. Every line but the last of such a value has to end with a comma.
+            let continued = match Self::starts_key(&line) {
+                true => None,
+                false => last_key.as_ref().and_then(|key| kv.get_mut(key)),
+            };
+            if let Some(value) = continued.filter(|value| value.ends_with(',')) {
+                value.push(' ');
+                value.push_str(line.trim());
+                continue;
+            }
+
             let Some(split_i) = line.find(":") else {
                 return Some(Err(HksError::InvalidKeyValueLine(line)));
             };
@@ -186,7 +254,8 @@ where
                 return Some(Err(HksError::EmptyValue(line)));
             }
 
-            kv.insert(key, value);
+            kv.insert(key.clone(), value);
+            last_key = Some(key);
         }
 
         Some(Ok(HksEntry {
@@ -219,6 +288,26 @@ mod tests {
         };
     }
 
+    #[test]
+    fn test_read_crlf() {
+        let lf = "test: # comment\n    a: 1\n    b: 1:2\n\ntest2:\n    c: 3 # comment\n";
+        let crlf = lf.replace('\n', "\r\n");
+
+        let entries = |s: &str| {
+            HksReader::new(std::io::Cursor::new(s.to_string()))
+                .map(|e| e.unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(entries(&crlf), entries(lf));
+        assert_eq!(
+            entries(&crlf)[0].kv,
+            make_kv! {
+                "a" => "1",
+                "b" => "1:2",
+            }
+        );
+    }
+
     #[test]
     fn test_read() {
         let mut reader = HksReader::new(std::io::Cursor::new(
@@ -271,6 +360,61 @@ test3:
         assert!(reader.next().is_none());
     }
 
+    #[test]
+    fn test_read_list() {
+        let mut reader = HksReader::new(std::io::Cursor::new(
+            "test:\n    addrs: 0x100000,\n        0x100010 # comment\n    b: 1,\n    c: 2\n",
+        ));
+        let mut entry = reader.next().unwrap().unwrap();
+        assert_eq!(
+            entry.get_list("addrs"),
+            Ok(vec!["0x100000".to_string(), "0x100010".to_string()])
+        );
+        assert_eq!(entry.get_list("b"), Ok(vec!["1".to_string()]));
+        assert_eq!(entry.get_list("c"), Ok(vec!["2".to_string()]));
+
+        // Only a key at the start of a line ends the list
+        let mut reader = HksReader::new(std::io::Cursor::new(
+            "test:\n    addrs: 0x100000,\n        ns::func,\n        0x100010\n    sym: x\n",
+        ));
+        let mut entry = reader.next().unwrap().unwrap();
+        assert_eq!(
+            entry.get_list("addrs"),
+            Ok(vec![
+                "0x100000".to_string(),
+                "ns::func".to_string(),
+                "0x100010".to_string()
+            ])
+        );
+        assert_eq!(entry.get("sym"), Ok("x".to_string()));
+
+        let mut reader = HksReader::new(std::io::Cursor::new("test:\n    a: 1,,2\n"));
+        assert_eq!(
+            reader.next().unwrap().unwrap().get_list("a"),
+            Err(HksParseError::InvalidTypeValue(
+                "list".into(),
+                "1,,2".into()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_read_patch_data() {
+        let mut reader = HksReader::new(std::io::Cursor::new(
+            "patch:\n    type: patch\n    addr: 0x100000\n    data: DE AD,\n        BE EF,\n        0x01 0x02\n    extra: x\n",
+        ));
+        let mut entry = reader.next().unwrap().unwrap();
+        assert_eq!(entry.get("type"), Ok("patch".to_string()));
+        assert_eq!(entry.get_address("addr"), Ok(0x100000));
+        let data = entry.get("data").unwrap();
+        assert_eq!(
+            super::super::util::parse_hex_data(&data),
+            Ok(vec![0xDE, 0xAD, 0xBE, 0xEF, 0x01, 0x02])
+        );
+        assert_eq!(entry.get("extra"), Ok("x".to_string()));
+        assert!(entry.is_done());
+    }
+
     #[test]
     fn test_read_errors() {
         let mut reader = HksReader::new(std::io::Cursor::new(" a: 1"));
@@ -285,6 +429,13 @@ test3:
             HksError::InvalidKeyValueLine(" a".into())
         );
 
+        let mut reader = HksReader::new(std::io::Cursor::new("test:\n b: 1\n a\n"));
+        assert_eq!(
+            reader.next().unwrap().unwrap_err(),
+            HksError::InvalidKeyValueLine(" a".into())
+        );
+        assert_eq!(reader.line(), 3);
+
         let mut reader = HksReader::new(std::io::Cursor::new("test:\n :a\n"));
         assert_eq!(
             reader.next().unwrap().unwrap_err(),
@@ -1,6 +1,34 @@
 use super::error::*;
 use super::{HookKind, HookLocation, HookMeta};
 
+/// Name prefixes marking hook sections and symbols, configurable in case other code already
+/// uses the `__mw_` namespace.
+#[derive(Debug, PartialEq, Clone)]
+pub struct HookPrefix {
+    pub section: String,
+    pub symbol: String,
+}
+
+impl Default for HookPrefix {
+    fn default() -> Self {
+        Self {
+            section: ".__mw_hook_".into(),
+            symbol: "__mw_hook_".into(),
+        }
+    }
+}
+
+/// A hook declared with the macros of magwi.h, parsed from a section or symbol name.
+///
+/// Both names are `<prefix><kind>$<arg>$<file>$<line>$<counter>`:
+/// - `kind`: hook kind, e.g. `b`, `bleq`, `pre` or `replace`
+/// - `arg`: its argument, usually the hooked address like `0x201000`
+/// - `file`: path of the source, encoded by `symbol_safe::path_to_symbol_safe`
+/// - `line`: line of the macro
+/// - `counter`: value of `__COUNTER__`, tells apart multiple hooks on the same line
+///
+/// C sources name hook symbols through `__symver__`, which appends a version like `@0`.
+/// Everything from the first `@` on is ignored, no other part contains one.
 #[derive(Debug, PartialEq)]
 pub struct HookInfo {
     pub kind: HookKind,
@@ -27,28 +55,26 @@ impl HookInfo {
         })
     }
 
-    pub const SECTION_PREFIX: &'static str = ".__mw_hook_";
-
-    pub fn from_section_str(section_str: impl AsRef<str>) -> Result<Self, Error> {
-        let section_str = section_str.as_ref();
-
-        if section_str.starts_with(Self::SECTION_PREFIX) {
-            Self::from_str(&section_str[Self::SECTION_PREFIX.len()..])
-        } else {
-            Err(Error::InvalidPrefix)
+    pub fn from_section_str(
+        section_str: impl AsRef<str>,
+        prefix: &HookPrefix,
+    ) -> Result<Self, Error> {
+        match section_str.as_ref().strip_prefix(prefix.section.as_str()) {
+            Some(s) => Self::from_str(s),
+            None => Err(Error::InvalidPrefix),
         }
     }
 
-    pub const SYMBOL_PREFIX: &'static str = "__mw_hook_";
-
-    pub fn from_symbol_str(symbol_str: impl AsRef<str>) -> Result<Self, Error> {
-        let symbol_str = symbol_str.as_ref();
-
-        if symbol_str.starts_with(Self::SYMBOL_PREFIX) {
-            let end_index = symbol_str.rfind('@').unwrap_or_else(|| symbol_str.len());
-            Self::from_str(&symbol_str[Self::SYMBOL_PREFIX.len()..end_index])
-        } else {
-            Err(Error::InvalidPrefix)
+    pub fn from_symbol_str(
+        symbol_str: impl AsRef<str>,
+        prefix: &HookPrefix,
+    ) -> Result<Self, Error> {
+        match symbol_str.as_ref().strip_prefix(prefix.symbol.as_str()) {
+            Some(s) => {
+                let end_index = s.find('@').unwrap_or(s.len());
+                Self::from_str(&s[..end_index])
+            }
+            None => Err(Error::InvalidPrefix),
         }
     }
 }
@@ -70,7 +96,7 @@ mod tests {
         assert_eq!(
             HookInfo::from_str(format!("pre$0x1234${}$10$0", path_to_symbol_safe(&file))),
             Ok(HookInfo {
-                kind: HookKind::Pre(0x1234),
+                kind: HookKind::Pre(0x1234, None),
                 location: HookLocation { file, line: 10 },
                 counter: 0,
             })
@@ -80,7 +106,7 @@ mod tests {
         assert_eq!(
             HookInfo::from_str(format!("post$0x1234${}$10$1", path_to_symbol_safe(&file))),
             Ok(HookInfo {
-                kind: HookKind::Post(0x1234),
+                kind: HookKind::Post(0x1234, None),
                 location: HookLocation { file, line: 10 },
                 counter: 1,
             })
@@ -132,10 +158,10 @@ mod tests {
     fn test_hook_from_symbol() {
         let file = PathBuf::from("src/main.cpp");
         assert_eq!(
-            HookInfo::from_symbol_str(format!(
-                "__mw_hook_bl$0x00${}$10$0",
-                path_to_symbol_safe(&file)
-            )),
+            HookInfo::from_symbol_str(
+                format!("__mw_hook_bl$0x00${}$10$0", path_to_symbol_safe(&file)),
+                &HookPrefix::default()
+            ),
             Ok(HookInfo {
                 kind: HookKind::Branch(ArmBranch {
                     condition: ArmCondition::AL,
@@ -149,10 +175,10 @@ mod tests {
 
         let file = PathBuf::from("src/sub/test_file.s");
         assert_eq!(
-            HookInfo::from_symbol_str(format!(
-                "__mw_hook_bl$0x00${}$42$0@0",
-                path_to_symbol_safe(&file)
-            )),
+            HookInfo::from_symbol_str(
+                format!("__mw_hook_bl$0x00${}$42$0@0", path_to_symbol_safe(&file)),
+                &HookPrefix::default()
+            ),
             Ok(HookInfo {
                 kind: HookKind::Branch(ArmBranch {
                     condition: ArmCondition::AL,
@@ -164,17 +190,63 @@ mod tests {
             })
         );
 
-        assert_eq!(HookInfo::from_symbol_str("xyz"), Err(Error::InvalidPrefix));
+        assert_eq!(
+            HookInfo::from_symbol_str("xyz", &HookPrefix::default()),
+            Err(Error::InvalidPrefix)
+        );
+    }
+
+    #[test]
+    fn test_hook_from_macro_symbol() {
+        // What `mw_b(0x201000)` expands to, twice on the same line
+        let file = PathBuf::from("source/regions/main.c");
+        let symbol = |counter| {
+            format!(
+                "__mw_hook_b$0x201000${}$12${counter}@0",
+                path_to_symbol_safe(&file)
+            )
+        };
+        let expected = |counter| HookInfo {
+            kind: HookKind::Branch(ArmBranch {
+                condition: ArmCondition::AL,
+                link: false,
+                from_addr: 0x201000,
+            }),
+            location: HookLocation {
+                file: file.clone(),
+                line: 12,
+            },
+            counter,
+        };
+
+        for counter in [3, 4] {
+            assert_eq!(
+                HookInfo::from_symbol_str(symbol(counter), &HookPrefix::default()),
+                Ok(expected(counter))
+            );
+        }
+        // Default version
+        assert_eq!(
+            HookInfo::from_symbol_str(symbol(3).replace('@', "@@"), &HookPrefix::default()),
+            Ok(expected(3))
+        );
+        assert_eq!(
+            HookInfo::from_symbol_str(
+                format!("__mw_hook_b$0x201000${}$12@0", path_to_symbol_safe(&file)),
+                &HookPrefix::default()
+            ),
+            Err(Error::MetaParsingError(MetaParsingError::MissingCounter))
+        );
     }
 
     #[test]
     fn test_hook_from_section() {
         let file = PathBuf::from("src/main.cpp");
         assert_eq!(
-            HookInfo::from_section_str(format!(
-                ".__mw_hook_bl$0x00${}$10$0",
-                path_to_symbol_safe(&file)
-            )),
+            HookInfo::from_section_str(
+                format!(".__mw_hook_bl$0x00${}$10$0", path_to_symbol_safe(&file)),
+                &HookPrefix::default()
+            ),
             Ok(HookInfo {
                 kind: HookKind::Branch(ArmBranch {
                     condition: ArmCondition::AL,
@@ -185,6 +257,37 @@ mod tests {
                 counter: 0
             })
         );
-        assert_eq!(HookInfo::from_section_str("xyz"), Err(Error::InvalidPrefix));
+        assert_eq!(
+            HookInfo::from_section_str("xyz", &HookPrefix::default()),
+            Err(Error::InvalidPrefix)
+        );
+    }
+
+    #[test]
+    fn test_hook_custom_prefix() {
+        let prefix = HookPrefix {
+            section: ".__game_hook_".into(),
+            symbol: "__game_hook_".into(),
+        };
+        let file = PathBuf::from("src/main.cpp");
+        let hook = format!("pre$0x1234${}$10$0", path_to_symbol_safe(&file));
+        let expected = HookInfo {
+            kind: HookKind::Pre(0x1234, None),
+            location: HookLocation { file, line: 10 },
+            counter: 0,
+        };
+
+        assert_eq!(
+            HookInfo::from_symbol_str(format!("__game_hook_{hook}@0"), &prefix).as_ref(),
+            Ok(&expected)
+        );
+        assert_eq!(
+            HookInfo::from_section_str(format!(".__game_hook_{hook}"), &prefix).as_ref(),
+            Ok(&expected)
+        );
+        assert_eq!(
+            HookInfo::from_symbol_str(format!("__mw_hook_{hook}"), &prefix),
+            Err(Error::InvalidPrefix)
+        );
     }
 }
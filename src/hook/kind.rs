@@ -1,24 +1,41 @@
 use super::error::*;
 use super::arm::ArmBranch;
 use super::util::parse_address;
+use super::writer::HookExtraPos;
 
 #[derive(Debug, PartialEq)]
 pub enum HookKind {
-    Pre(u32),
-    Post(u32),
+    /// The trampoline is placed in the loader or at the tail depending on the address,
+    /// unless a position is forced with a `_loader` or `_tail` suffix.
+    Pre(u32, Option<HookExtraPos>),
+    Post(u32, Option<HookExtraPos>),
     Branch(ArmBranch),
     Replace(u32),
-    Symptr(u32)
+    /// Section placed at the address like `Replace`, holding data instead of code.
+    Data(u32),
+    Symptr(u32),
+    /// Branches from the function entry at the address to the hook, which may resume the
+    /// original function through a `DetourOriginal` pointer.
+    Detour(u32),
+    /// Pointer set to the trampoline resuming the original function of a `Detour`.
+    DetourOriginal(u32),
 }
 
 impl HookKind {
     pub fn from_str(kind_str: &str, arg_str: &str) -> Result<Self, ParsingError> {
         let kind_str_lowercase = kind_str.to_ascii_lowercase();
         match kind_str_lowercase.as_str() {
-            "pre" => Ok(HookKind::Pre(parse_address(arg_str)?)),
-            "post" => Ok(HookKind::Post(parse_address(arg_str)?)),
+            "pre" => Ok(HookKind::Pre(parse_address(arg_str)?, None)),
+            "pre_loader" => Ok(HookKind::Pre(parse_address(arg_str)?, Some(HookExtraPos::Loader))),
+            "pre_tail" => Ok(HookKind::Pre(parse_address(arg_str)?, Some(HookExtraPos::Tail))),
+            "post" => Ok(HookKind::Post(parse_address(arg_str)?, None)),
+            "post_loader" => Ok(HookKind::Post(parse_address(arg_str)?, Some(HookExtraPos::Loader))),
+            "post_tail" => Ok(HookKind::Post(parse_address(arg_str)?, Some(HookExtraPos::Tail))),
             "replace" => Ok(HookKind::Replace(parse_address(arg_str)?)),
+            "data" => Ok(HookKind::Data(parse_address(arg_str)?)),
             "symptr" => Ok(HookKind::Symptr(parse_address(arg_str)?)),
+            "detour" => Ok(HookKind::Detour(parse_address(arg_str)?)),
+            "detour_original" => Ok(HookKind::DetourOriginal(parse_address(arg_str)?)),
             _ => {
                 let branch = ArmBranch::from_str(&kind_str_lowercase, arg_str).map_err(|e| {
                     match e {
@@ -42,11 +59,31 @@ mod tests {
     fn test_hook_kind() {
         assert_eq!(
             HookKind::from_str("pre", "0x1234"),
-            Ok(HookKind::Pre(0x1234))
+            Ok(HookKind::Pre(0x1234, None))
         );
         assert_eq!(
             HookKind::from_str("post", "0x1234"),
-            Ok(HookKind::Post(0x1234))
+            Ok(HookKind::Post(0x1234, None))
+        );
+        assert_eq!(
+            HookKind::from_str("pre_tail", "0x1234"),
+            Ok(HookKind::Pre(0x1234, Some(HookExtraPos::Tail)))
+        );
+        assert_eq!(
+            HookKind::from_str("post_loader", "0x1234"),
+            Ok(HookKind::Post(0x1234, Some(HookExtraPos::Loader)))
+        );
+        assert_eq!(
+            HookKind::from_str("data", "0x1234"),
+            Ok(HookKind::Data(0x1234))
+        );
+        assert_eq!(
+            HookKind::from_str("detour", "0x1234"),
+            Ok(HookKind::Detour(0x1234))
+        );
+        assert_eq!(
+            HookKind::from_str("detour_original", "0x1234"),
+            Ok(HookKind::DetourOriginal(0x1234))
         );
         assert_eq!(
             HookKind::from_str("bleq", "0x1234"),
@@ -0,0 +1,543 @@
+use crate::json::Json;
+
+use super::util::levenshtein;
+
+/// Problems with the set of keys of an hks entry.
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum HksKeyError {
+    #[error("Unknown key \"{0}\"")]
+    UnknownKey(String),
+
+    #[error("Unknown key \"{0}\", did you mean \"{1}\"?")]
+    UnknownKeySuggestion(String, &'static str),
+
+    #[error("Missing required key \"{0}\"")]
+    MissingKey(&'static str),
+
+    #[error("Missing one of the keys \"{}\"", .0.join("\", \""))]
+    MissingOneOf(&'static [&'static str]),
+
+    #[error("Only one of the keys \"{}\" may be given", .0.join("\", \""))]
+    ConflictingKeys(&'static [&'static str]),
+
+    #[error("Invalid value \"{1}\" for key \"{0}\", expected {2}")]
+    InvalidValue(&'static str, String, String),
+}
+
+/// Value type of an hks key.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HksValueType {
+    Address,
+    /// Comma separated addresses or symbol names
+    AddressList,
+    Bool,
+    Integer,
+    Symbol,
+    HexData,
+    Version,
+    /// Free text
+    Text,
+    Enum(&'static [&'static str]),
+}
+
+impl HksValueType {
+    /// Whether `value` has this type. Addresses and symbols are resolved later, as they may
+    /// name symbols of the linked ELF.
+    pub fn accepts(self, value: &str) -> bool {
+        match self {
+            HksValueType::Bool => matches!(value, "true" | "false"),
+            HksValueType::Integer => value.parse::<i32>().is_ok(),
+            HksValueType::HexData => super::util::parse_hex_data(value).is_ok(),
+            HksValueType::Version => value.parse::<crate::version::Version>().is_ok(),
+            HksValueType::Enum(values) => values.contains(&value),
+            HksValueType::Address
+            | HksValueType::AddressList
+            | HksValueType::Symbol
+            | HksValueType::Text => true,
+        }
+    }
+
+    /// Describes the accepted values in error messages.
+    fn describe(self) -> String {
+        match self {
+            HksValueType::Bool => "true or false".to_string(),
+            HksValueType::Integer => "an integer".to_string(),
+            HksValueType::HexData => "hex bytes".to_string(),
+            HksValueType::Version => "major[.minor[.patch]]".to_string(),
+            HksValueType::Enum(values) => format!("one of \"{}\"", values.join("\", \"")),
+            HksValueType::Address => "an address".to_string(),
+            HksValueType::AddressList => "a list of addresses".to_string(),
+            HksValueType::Symbol => "a symbol".to_string(),
+            HksValueType::Text => "text".to_string(),
+        }
+    }
+
+    fn to_json(self) -> Json {
+        match self {
+            HksValueType::Address => Json::from("address"),
+            HksValueType::AddressList => Json::from("address list"),
+            HksValueType::Bool => Json::from("bool"),
+            HksValueType::Integer => Json::from("integer"),
+            HksValueType::Symbol => Json::from("symbol"),
+            HksValueType::HexData => Json::from("hex"),
+            HksValueType::Version => Json::from("version"),
+            HksValueType::Text => Json::from("text"),
+            HksValueType::Enum(values) => {
+                Json::object([("enum", Json::array(values.iter().map(|v| Json::from(*v))))])
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct HksKeySpec {
+    pub name: &'static str,
+    pub value_type: HksValueType,
+    pub required: bool,
+    pub description: &'static str,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct HksTypeSpec {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub description: &'static str,
+    pub keys: &'static [HksKeySpec],
+    /// Groups of keys of which exactly one has to be present.
+    pub one_of: &'static [&'static [&'static str]],
+}
+
+impl HksTypeSpec {
+    pub fn key(&self, name: &str) -> Option<&'static HksKeySpec> {
+        COMMON_KEYS.iter().chain(self.keys).find(|k| k.name == name)
+    }
+
+    /// Known key closest to `name`, if it is close enough to likely be a typo.
+    fn suggest_key(&self, name: &str) -> Option<&'static str> {
+        COMMON_KEYS
+            .iter()
+            .chain(self.keys)
+            .map(|k| (levenshtein(name, k.name), k.name))
+            .filter(|(distance, key)| *distance <= 2 && *distance < key.len())
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, key)| key)
+    }
+
+    /// Checks the keys of an entry against this spec.
+    /// Returns all problems found, unknown keys first in the order given.
+    pub fn validate<'a>(&self, keys: impl IntoIterator<Item = &'a str>) -> Vec<HksKeyError> {
+        let keys = keys.into_iter().collect::<Vec<_>>();
+        let mut errors = Vec::new();
+
+        for key in &keys {
+            if self.key(key).is_none() {
+                errors.push(match self.suggest_key(key) {
+                    Some(suggestion) => {
+                        HksKeyError::UnknownKeySuggestion(key.to_string(), suggestion)
+                    }
+                    None => HksKeyError::UnknownKey(key.to_string()),
+                });
+            }
+        }
+
+        for spec in COMMON_KEYS.iter().chain(self.keys) {
+            if spec.required && !keys.contains(&spec.name) {
+                errors.push(HksKeyError::MissingKey(spec.name));
+            }
+        }
+
+        for group in self.one_of {
+            match group.iter().filter(|k| keys.contains(k)).count() {
+                0 => errors.push(HksKeyError::MissingOneOf(group)),
+                1 => {}
+                _ => errors.push(HksKeyError::ConflictingKeys(group)),
+            }
+        }
+
+        errors
+    }
+
+    /// Checks the values of an entry against the types of their keys, ordered by key.
+    /// Unknown keys are left to `validate`.
+    pub fn validate_values<'a>(
+        &self,
+        values: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> Vec<HksKeyError> {
+        let mut values = values.into_iter().collect::<Vec<_>>();
+        values.sort();
+
+        values
+            .into_iter()
+            .filter_map(|(key, value)| {
+                let spec = self.key(key)?;
+                (!spec.value_type.accepts(value)).then(|| {
+                    HksKeyError::InvalidValue(
+                        spec.name,
+                        value.to_string(),
+                        spec.value_type.describe(),
+                    )
+                })
+            })
+            .collect()
+    }
+
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("name", Json::from(self.name)),
+            (
+                "aliases",
+                Json::array(self.aliases.iter().map(|a| Json::from(*a))),
+            ),
+            ("description", Json::from(self.description)),
+            ("keys", keys_to_json(self.keys)),
+            (
+                "one_of",
+                Json::array(
+                    self.one_of
+                        .iter()
+                        .map(|g| Json::array(g.iter().map(|k| Json::from(*k)))),
+                ),
+            ),
+        ])
+    }
+}
+
+fn keys_to_json(keys: &[HksKeySpec]) -> Json {
+    Json::array(keys.iter().map(|k| {
+        Json::object([
+            ("name", Json::from(k.name)),
+            ("type", k.value_type.to_json()),
+            ("required", Json::from(k.required)),
+            ("description", Json::from(k.description)),
+        ])
+    }))
+}
+
+const KEY_ADDR: HksKeySpec = HksKeySpec {
+    name: "addr",
+    value_type: HksValueType::Address,
+    required: true,
+    description: "Address the hook is applied at, or a symbol name",
+};
+
+const KEY_ADDRS: HksKeySpec = HksKeySpec {
+    name: "addrs",
+    value_type: HksValueType::AddressList,
+    required: true,
+    description: "Comma separated addresses the hook is applied at, or symbol names. A trailing comma continues the list on the next line",
+};
+
+const KEY_LINK: HksKeySpec = HksKeySpec {
+    name: "link",
+    value_type: HksValueType::Bool,
+    required: true,
+    description: "Emit bl instead of b",
+};
+
+const KEY_FUNC: HksKeySpec = HksKeySpec {
+    name: "func",
+    value_type: HksValueType::Symbol,
+    required: false,
+    description: "Symbol to branch to",
+};
+
+const KEY_DEST: HksKeySpec = HksKeySpec {
+    name: "dest",
+    value_type: HksValueType::Address,
+    required: false,
+    description: "Address to branch to, or a symbol name",
+};
+
+const KEY_DEST_OFFSET: HksKeySpec = HksKeySpec {
+    name: "offset",
+    value_type: HksValueType::Address,
+    required: false,
+    description: "Added to the branch destination, e.g. to skip the prologue of func",
+};
+
+/// Keys accepted by every hook type.
+pub const COMMON_KEYS: &[HksKeySpec] = &[
+    HksKeySpec {
+        name: "type",
+        value_type: HksValueType::Enum(&[
+            "branch",
+            "softbranch",
+            "patch",
+            "symbol",
+            "nop_list",
+            "branch_list",
+            "base",
+            "require",
+        ]),
+        required: true,
+        description: "Hook type",
+    },
+    HksKeySpec {
+        name: "name",
+        value_type: HksValueType::Text,
+        required: false,
+        description: "Name of the hook shown in error messages",
+    },
+    HksKeySpec {
+        name: "desc",
+        value_type: HksValueType::Text,
+        required: false,
+        description: "Description of the hook, shown in error messages if there is no name",
+    },
+];
+
+pub const HKS_TYPES: &[HksTypeSpec] = &[
+    HksTypeSpec {
+        name: "branch",
+        aliases: &[],
+        description: "Replaces the instruction at addr with a branch",
+        keys: &[
+            KEY_ADDR,
+            KEY_LINK,
+            KEY_FUNC,
+            KEY_DEST,
+            KEY_DEST_OFFSET,
+        ],
+        one_of: &[&["func", "dest"]],
+    },
+    HksTypeSpec {
+        name: "softbranch",
+        aliases: &["soft_branch"],
+        description: "Calls a function before or after the original instruction at addr",
+        keys: &[
+            KEY_ADDR,
+            HksKeySpec {
+                name: "opcode",
+                value_type: HksValueType::Enum(&["pre", "post"]),
+                required: true,
+                description: "Position of the original instruction relative to the call",
+            },
+            HksKeySpec {
+                name: "section",
+                value_type: HksValueType::Enum(&["loader", "tail"]),
+                required: false,
+                description: "Places the trampoline in the loader or at the tail of the code instead of choosing by address",
+            },
+            HksKeySpec {
+                name: "priority",
+                value_type: HksValueType::Integer,
+                required: false,
+                description: "Order of the calls at the same addr, lower runs first. Defaults to 0, ties run by file and line",
+            },
+            KEY_FUNC,
+            KEY_DEST,
+            KEY_DEST_OFFSET,
+        ],
+        one_of: &[&["func", "dest"]],
+    },
+    HksTypeSpec {
+        name: "patch",
+        aliases: &[],
+        description: "Writes raw bytes at addr",
+        keys: &[
+            KEY_ADDR,
+            HksKeySpec {
+                name: "data",
+                value_type: HksValueType::HexData,
+                required: true,
+                description: "Bytes to write as hex string, lines ending with a comma continue on the next line",
+            },
+        ],
+        one_of: &[],
+    },
+    HksTypeSpec {
+        name: "symbol",
+        aliases: &["symptr", "sym_ptr"],
+        description: "Writes the address of a symbol at addr",
+        keys: &[
+            KEY_ADDR,
+            HksKeySpec {
+                name: "sym",
+                value_type: HksValueType::Symbol,
+                required: true,
+                description: "Symbol whose address is written",
+            },
+        ],
+        one_of: &[],
+    },
+    HksTypeSpec {
+        name: "nop_list",
+        aliases: &[],
+        description: "Replaces the instruction at every address of addrs with a nop",
+        keys: &[KEY_ADDRS],
+        one_of: &[],
+    },
+    HksTypeSpec {
+        name: "branch_list",
+        aliases: &[],
+        description: "Replaces the instruction at every address of addrs with a branch to the same destination",
+        keys: &[KEY_ADDRS, KEY_LINK, KEY_FUNC, KEY_DEST, KEY_DEST_OFFSET],
+        one_of: &[&["func", "dest"]],
+    },
+    HksTypeSpec {
+        name: "base",
+        aliases: &[],
+        description: "Offset added to addr, addrs and dest of the following entries in the file, but not to symbol addresses",
+        keys: &[HksKeySpec {
+            name: "offset",
+            value_type: HksValueType::Address,
+            required: true,
+            description: "Offset to add",
+        }],
+        one_of: &[],
+    },
+    HksTypeSpec {
+        name: "require",
+        aliases: &[],
+        description: "Rejects the file if magwi is older than the given version",
+        keys: &[HksKeySpec {
+            name: "version",
+            value_type: HksValueType::Version,
+            required: true,
+            description: "Minimum magwi version as major[.minor[.patch]]",
+        }],
+        one_of: &[],
+    },
+];
+
+/// Finds the spec of a hook type by its name or one of its aliases.
+pub fn find_type(name: &str) -> Option<&'static HksTypeSpec> {
+    HKS_TYPES
+        .iter()
+        .find(|t| t.name == name || t.aliases.contains(&name))
+}
+
+/// Describes the hks grammar for editor tooling.
+pub fn to_json() -> Json {
+    Json::object([
+        ("common_keys", keys_to_json(COMMON_KEYS)),
+        ("types", Json::array(HKS_TYPES.iter().map(|t| t.to_json()))),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_type() {
+        assert_eq!(find_type("branch").unwrap().name, "branch");
+        assert_eq!(find_type("soft_branch").unwrap().name, "softbranch");
+        assert_eq!(find_type("sym_ptr").unwrap().name, "symbol");
+        assert_eq!(find_type("xyz"), None);
+
+        let branch = find_type("branch").unwrap();
+        assert_eq!(branch.key("type").unwrap().name, "type");
+        assert_eq!(branch.key("link").unwrap().value_type, HksValueType::Bool);
+        assert_eq!(branch.key("data"), None);
+    }
+
+    #[test]
+    fn test_validate() {
+        let branch = find_type("branch").unwrap();
+
+        assert_eq!(branch.validate(["type", "addr", "link", "func"]), vec![]);
+        assert_eq!(
+            branch.validate(["type", "addr", "link", "func", "offset"]),
+            vec![]
+        );
+        assert_eq!(
+            branch.validate(["type", "name", "desc", "addr", "link", "func"]),
+            vec![]
+        );
+        assert_eq!(
+            branch.validate(["type", "addr", "link", "fucn"]),
+            vec![
+                HksKeyError::UnknownKeySuggestion("fucn".into(), "func"),
+                HksKeyError::MissingOneOf(&["func", "dest"]),
+            ]
+        );
+        assert_eq!(
+            branch.validate(["type", "addr", "func", "dest", "xyzzy"]),
+            vec![
+                HksKeyError::UnknownKey("xyzzy".into()),
+                HksKeyError::MissingKey("link"),
+                HksKeyError::ConflictingKeys(&["func", "dest"]),
+            ]
+        );
+
+        let patch = find_type("patch").unwrap();
+        assert_eq!(
+            patch.validate(["type", "adr"]),
+            vec![
+                HksKeyError::UnknownKeySuggestion("adr".into(), "addr"),
+                HksKeyError::MissingKey("addr"),
+                HksKeyError::MissingKey("data"),
+            ]
+        );
+        assert_eq!(
+            HksKeyError::MissingOneOf(&["func", "dest"]).to_string(),
+            "Missing one of the keys \"func\", \"dest\""
+        );
+    }
+
+    #[test]
+    fn test_validate_values() {
+        let softbranch = find_type("softbranch").unwrap();
+        assert_eq!(
+            softbranch.validate_values([
+                ("addr", "my_site"),
+                ("opcode", "pre"),
+                ("priority", "-1"),
+                ("func", "my_function"),
+            ]),
+            vec![]
+        );
+        assert_eq!(
+            softbranch.validate_values([
+                ("priority", "high"),
+                ("opcode", "middle"),
+                ("xyzzy", "1"),
+            ]),
+            vec![
+                HksKeyError::InvalidValue(
+                    "opcode",
+                    "middle".into(),
+                    "one of \"pre\", \"post\"".into()
+                ),
+                HksKeyError::InvalidValue("priority", "high".into(), "an integer".into()),
+            ]
+        );
+
+        let branch = find_type("branch").unwrap();
+        assert_eq!(
+            branch.validate_values([("link", "yes")]),
+            vec![HksKeyError::InvalidValue(
+                "link",
+                "yes".into(),
+                "true or false".into()
+            )]
+        );
+        assert_eq!(
+            find_type("patch")
+                .unwrap()
+                .validate_values([("data", "0g")])[0]
+                .to_string(),
+            "Invalid value \"0g\" for key \"data\", expected hex bytes"
+        );
+    }
+
+    #[test]
+    fn test_common_type_enum_complete() {
+        let HksValueType::Enum(types) = COMMON_KEYS[0].value_type else {
+            panic!("type key must be an enum");
+        };
+        assert_eq!(
+            types.to_vec(),
+            HKS_TYPES.iter().map(|t| t.name).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_to_json() {
+        let json = to_json().to_string();
+        assert!(json.contains("\"name\": \"softbranch\""));
+        assert!(json.contains("\"soft_branch\""));
+        assert!(json.contains("\"one_of\""));
+    }
+}
@@ -9,6 +9,60 @@ pub fn parse_address(s: &str) -> Result<u32, ParsingError> {
     .map_err(|_| ParsingError::InvalidAddress(s.to_string()))
 }
 
+/// Decodes hex data such as `DEADBEEF`, `DE AD BE EF` or `0xDE,0xAD`.
+/// Tokens are separated by whitespace or commas and may have a `0x` prefix.
+/// Error indices refer to characters of the input string.
+pub fn parse_hex_data(s: &str) -> Result<Vec<u8>, ParsingError> {
+    let mut digits = Vec::new();
+    let mut chars = s.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        if c.is_whitespace() || c == ',' {
+            continue;
+        }
+
+        let token_start = i == 0 || {
+            let prev = s[..i].chars().next_back().unwrap();
+            prev.is_whitespace() || prev == ','
+        };
+
+        if token_start && c == '0' && matches!(chars.peek(), Some((_, 'x' | 'X'))) {
+            chars.next();
+            continue;
+        }
+
+        match c.to_digit(16) {
+            Some(d) => digits.push(d as u8),
+            None => return Err(ParsingError::InvalidHexCharacter(c, s[..i].chars().count())),
+        }
+    }
+
+    if digits.len() % 2 != 0 {
+        return Err(ParsingError::OddHexLength(digits.len()));
+    }
+
+    Ok(digits.chunks_exact(2).map(|d| d[0] << 4 | d[1]).collect())
+}
+
+/// Edit distance between two strings, used for "did you mean" suggestions.
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev_diag = row[0];
+        row[0] = i + 1;
+
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { prev_diag } else { prev_diag + 1 };
+            prev_diag = row[j + 1];
+            row[j + 1] = cost.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+
+    row[b.len()]
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -43,4 +97,41 @@ mod tests {
             Err(ParsingError::InvalidAddress("1234x".to_string()))
         );
     }
+
+    #[test]
+    fn test_parse_hex_data() {
+        assert_eq!(parse_hex_data(""), Ok(vec![]));
+        assert_eq!(parse_hex_data("DEADbeef"), Ok(vec![0xDE, 0xAD, 0xBE, 0xEF]));
+        assert_eq!(
+            parse_hex_data("DE AD BE EF"),
+            Ok(vec![0xDE, 0xAD, 0xBE, 0xEF])
+        );
+        assert_eq!(parse_hex_data("0xDE,0xAD"), Ok(vec![0xDE, 0xAD]));
+        assert_eq!(parse_hex_data("0XDE, 0xAD"), Ok(vec![0xDE, 0xAD]));
+        assert_eq!(
+            parse_hex_data("00 01\t02 03\n04 05\r\n06 07\n"),
+            Ok(vec![0, 1, 2, 3, 4, 5, 6, 7])
+        );
+        assert_eq!(parse_hex_data("00"), Ok(vec![0x00]));
+        assert_eq!(parse_hex_data("DE A"), Err(ParsingError::OddHexLength(3)));
+        assert_eq!(
+            parse_hex_data("DE AG"),
+            Err(ParsingError::InvalidHexCharacter('G', 4))
+        );
+        assert_eq!(
+            parse_hex_data("DE0xAD"),
+            Err(ParsingError::InvalidHexCharacter('x', 3))
+        );
+    }
+
+    #[test]
+    fn test_levenshtein() {
+        assert_eq!(levenshtein("", ""), 0);
+        assert_eq!(levenshtein("func", "func"), 0);
+        assert_eq!(levenshtein("fucn", "func"), 2);
+        assert_eq!(levenshtein("fun", "func"), 1);
+        assert_eq!(levenshtein("funcs", "func"), 1);
+        assert_eq!(levenshtein("dest", "data"), 3);
+        assert_eq!(levenshtein("", "addr"), 4);
+    }
 }
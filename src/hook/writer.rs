@@ -1,5 +1,6 @@
 use std::usize;
 use std::collections::BTreeMap;
+use std::ops::Range;
 
 use super::error::*;
 
@@ -9,20 +10,59 @@ pub enum HookExtraPos {
     Tail,
 }
 
+/// Instruction set of the code in an extra block, which decides its alignment.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum HookExtraCode {
+    Arm,
+    // The trampolines are ARM code, only tests place Thumb blocks so far
+    #[cfg_attr(not(test), allow(dead_code))]
+    Thumb,
+}
+
+impl HookExtraCode {
+    fn alignment(self) -> u32 {
+        match self {
+            HookExtraCode::Arm => 4,
+            HookExtraCode::Thumb => 2,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum HookWriteReason {
     Misc,
     _Code,
     _Loader,
-    _Hook(Vec<super::HookLocation>),
+    /// Hook of the given kind, defined at the given locations.
+    Hook(&'static str, Vec<super::HookLocation>),
+}
+
+/// A write that overlapped earlier writes, recorded while the duplicate check is disabled.
+#[derive(Debug, PartialEq, Clone)]
+pub struct HookOverlap {
+    pub address: u32,
+    pub size: u32,
+    pub reason: HookWriteReason,
+    /// Overlapped writes as (address, size, reason)
+    pub earlier: Vec<(u32, u32, HookWriteReason)>,
 }
 
 pub struct HookWriter {
     base_address: u32,
     loader_extra_address: Option<u32>,
+    loader_end_address: Option<u32>,
     buffer: Vec<u8>,
     duplicate_write_check: bool,
-    write_reasons: BTreeMap<u32, (u32, HookWriteReason)>,
+    /// Instruction set of the extra block the buffer ends with, if any
+    tail_code: Option<HookExtraCode>,
+    fill: u32,
+    /// Sizes and reasons of the writes by address, overlapped ones included
+    write_reasons: BTreeMap<u32, Vec<(u32, HookWriteReason)>>,
+    overlaps: Vec<HookOverlap>,
+}
+
+fn align_up(value: u32, alignment: u32) -> u32 {
+    value.div_ceil(alignment) * alignment
 }
 
 impl HookWriter {
@@ -30,12 +70,35 @@ impl HookWriter {
         Self {
             base_address,
             loader_extra_address: None,
+            loader_end_address: None,
             buffer,
             duplicate_write_check: true,
+            tail_code: None,
+            fill: 0,
             write_reasons: BTreeMap::new(),
+            overlaps: Vec::new(),
         }
     }
 
+    /// Controls whether writes overlapping earlier writes are rejected. Defaults to true,
+    /// otherwise they are applied and recorded, so all of them can be reported at once.
+    pub fn with_duplicate_check(mut self, check: bool) -> Self {
+        self.duplicate_write_check = check;
+        self
+    }
+
+    /// Sets the word padding is filled with, e.g. 0xDEADBEEF to make stray writes into it
+    /// visible in memory dumps. Defaults to 0.
+    pub fn with_fill(mut self, fill: u32) -> Self {
+        self.fill = fill;
+        self
+    }
+
+    /// Writes that overlapped earlier ones while the duplicate check was disabled, in order.
+    pub fn overlaps(&self) -> &[HookOverlap] {
+        &self.overlaps
+    }
+
     pub fn data(&self) -> &[u8] {
         &self.buffer
     }
@@ -52,6 +115,17 @@ impl HookWriter {
         self.loader_extra_address = Some(address);
     }
 
+    /// Sets the end of the loader region. Extra blocks placed in the loader must not exceed it.
+    pub fn set_loader_end_address(&mut self, address: u32) {
+        self.loader_end_address = Some(address);
+    }
+
+    /// Returns the space left for extra blocks in the loader region, if its bounds are known.
+    pub fn loader_space_remaining(&self) -> Option<u32> {
+        let extra_address = align_up(self.loader_extra_address?, HookExtraCode::Arm.alignment());
+        Some(self.loader_end_address?.saturating_sub(extra_address))
+    }
+
     pub fn read_mut(&self, address: u32, data: &mut [u8]) -> Result<(), WriterError> {
         if address < self.base_address {
             return Err(WriterError::OutOfBoundsRead(address, data.len()));
@@ -75,19 +149,64 @@ impl HookWriter {
         Ok(data)
     }
 
-    fn find_duplicate_write(&self, address: u32, size: u32) -> Option<&HookWriteReason> {
-        let mut iter = self.write_reasons.range(..address + size);
+    #[cfg(test)]
+    pub fn read_u8(&self, address: u32) -> Result<u8, WriterError> {
+        self.read(address).map(u8::from_le_bytes)
+    }
 
-        if let Some((check_address, (check_size, check_reason))) = iter.next_back() {
-            if *check_address + *check_size as u32 > address {
-                return Some(check_reason);
-            }
-        }
+    #[cfg(test)]
+    pub fn read_u16(&self, address: u32) -> Result<u16, WriterError> {
+        self.read(address).map(u16::from_le_bytes)
+    }
 
-        None
+    /// Reads a word, e.g. an instruction. Fails if any of its bytes is outside the buffer.
+    pub fn read_u32(&self, address: u32) -> Result<u32, WriterError> {
+        self.read(address).map(u32::from_le_bytes)
+    }
+
+    /// Returns the recorded writes starting in `range` as (address, size, reason), sorted by
+    /// address, writes to the same address in order.
+    fn records(
+        &self,
+        range: impl std::ops::RangeBounds<u32>,
+    ) -> impl Iterator<Item = (u32, u32, &HookWriteReason)> {
+        self.write_reasons
+            .range(range)
+            .flat_map(|(write_address, records)| {
+                records
+                    .iter()
+                    .map(|(size, reason)| (*write_address, *size, reason))
+            })
+    }
+
+    /// Returns the recorded writes overlapping `size` bytes at `address`. Any write starting
+    /// before the end may reach into the range, not only the closest one.
+    fn overlapping_writes(
+        &self,
+        address: u32,
+        size: u32,
+    ) -> impl Iterator<Item = (u32, u32, &HookWriteReason)> {
+        self.records(..address + size)
+            .filter(move |(write_address, write_size, _)| {
+                *write_address as u64 + *write_size as u64 > address as u64
+            })
+    }
+
+    /// Returns all recorded writes covering `address` as (address, size, reason).
+    pub fn writes_at(&self, address: u32) -> impl Iterator<Item = (u32, u32, &HookWriteReason)> {
+        self.overlapping_writes(address, 1)
+    }
+
+    /// Returns all recorded writes sorted by address as (address, size, reason).
+    pub fn writes(&self) -> impl Iterator<Item = (u32, u32, &HookWriteReason)> {
+        self.records(..)
     }
 
     pub fn write(&mut self, address: u32, data: impl AsRef<[u8]>) -> Result<(), WriterError> {
+        self.write_with_reason(address, data, HookWriteReason::Misc)
+    }
+
+    pub fn write_with_reason(&mut self, address: u32, data: impl AsRef<[u8]>, reason: HookWriteReason) -> Result<(), WriterError> {
         let data = data.as_ref();
 
         if address < self.base_address {
@@ -100,61 +219,160 @@ impl HookWriter {
             return Err(WriterError::OutOfBoundsWrite(address, data.len()));
         }
 
-        if self.duplicate_write_check {
-            if let Some(_write_reason) = self.find_duplicate_write(address, data.len() as u32) {
+        let earlier = self
+            .overlapping_writes(address, data.len() as u32)
+            .map(|(write_address, size, reason)| (write_address, size, reason.clone()))
+            .collect::<Vec<_>>();
+        if !earlier.is_empty() {
+            if self.duplicate_write_check {
                 return Err(WriterError::DuplicateWrite(address, data.len()));
             }
+
+            self.overlaps.push(HookOverlap {
+                address,
+                size: data.len() as u32,
+                reason: reason.clone(),
+                earlier,
+            });
         }
 
+        log::trace!("write 0x{:x} size 0x{:x} ({:?})", address, data.len(), reason);
+
         self.buffer[offset..offset + data.as_ref().len()].copy_from_slice(data.as_ref());
-        self.write_reasons.insert(address, (data.len() as u32, HookWriteReason::Misc));
+        self.write_reasons
+            .entry(address)
+            .or_default()
+            .push((data.len() as u32, reason));
 
         Ok(())
     }
 
     pub fn write_end(&mut self, data: impl AsRef<[u8]>) -> Result<(), WriterError> {
         self.buffer.extend_from_slice(data.as_ref());
+        self.tail_code = None;
         Ok(())
     }
 
-    pub fn write_extra<F: FnOnce(&mut HookWriter, &mut HookWriter) -> ()>(
+    /// Places an extra block of ARM code written by `write_fn` and returns its address range.
+    /// Nothing is placed if `write_fn` fails, though it may already have written to `self`.
+    pub fn write_extra<E, F>(&mut self, pos: HookExtraPos, write_fn: F) -> Result<Range<u32>, E>
+    where
+        E: From<WriterError>,
+        F: FnOnce(&mut HookWriter, &mut HookWriter) -> Result<(), E>,
+    {
+        self.write_extra_code(pos, HookExtraCode::Arm, write_fn)
+    }
+
+    /// Like `write_extra`, for a block of `code`. ARM blocks are word aligned, Thumb blocks
+    /// halfword aligned.
+    pub fn write_extra_code<E, F>(
         &mut self,
         pos: HookExtraPos,
+        code: HookExtraCode,
         write_fn: F,
-    ) -> Result<(), WriterError> {
+    ) -> Result<Range<u32>, E>
+    where
+        E: From<WriterError>,
+        F: FnOnce(&mut HookWriter, &mut HookWriter) -> Result<(), E>,
+    {
         let address = match pos {
-            HookExtraPos::Loader => self
-                .loader_extra_address
-                .ok_or(WriterError::LoaderExtraAddressNotSet)?,
-            HookExtraPos::Tail => self.base_address + self.buffer.len() as u32,
+            HookExtraPos::Loader => align_up(
+                self.loader_extra_address
+                    .ok_or(WriterError::LoaderExtraAddressNotSet)?,
+                code.alignment(),
+            ),
+            HookExtraPos::Tail => {
+                // Fill up to the aligned start, the padding is never executed. After Thumb code
+                // it is Thumb NOPs instead, as disassemblers read it as part of that code.
+                let end_address = self.end_address();
+                let tail_code = self.tail_code;
+                let address = align_up(end_address, code.alignment());
+                self.resize_until(address)?;
+                if tail_code == Some(HookExtraCode::Thumb) {
+                    self.fill_thumb_nops(end_address..address);
+                }
+                address
+            }
         };
 
         let mut w = HookWriter::new(address, Vec::new());
-        write_fn(self, &mut w);
+        write_fn(self, &mut w)?;
 
         let data = w.buffer;
 
         match pos {
             HookExtraPos::Loader => {
+                if let Some(loader_end_address) = self.loader_end_address {
+                    if address as u64 + data.len() as u64 > loader_end_address as u64 {
+                        return Err(WriterError::LoaderOverflow(address, data.len()).into());
+                    }
+                }
                 self.write(address, &data)?;
                 self.loader_extra_address = Some(address + data.len() as u32);
             }
-            HookExtraPos::Tail => self.write_end(&data)?,
+            HookExtraPos::Tail => {
+                self.write_end(&data)?;
+                self.tail_code = Some(code);
+            }
         }
 
-        Ok(())
+        Ok(address..address + data.len() as u32)
+    }
+
+    /// Fills `range` with the fill word, placed as if it was written to aligned addresses.
+    fn fill(&mut self, range: Range<u32>) {
+        let fill = self.fill.to_le_bytes();
+        for address in range {
+            let offset = (address - self.base_address) as usize;
+            self.buffer[offset] = fill[address as usize % fill.len()];
+        }
     }
 
+    /// Fills the halfword aligned part of `range` with Thumb NOPs.
+    fn fill_thumb_nops(&mut self, range: Range<u32>) {
+        let nop = super::arm::make_thumb_nop_u16().to_le_bytes();
+        for address in (align_up(range.start, 2)..range.end).step_by(2) {
+            if address + 2 > range.end {
+                break;
+            }
+            let offset = (address - self.base_address) as usize;
+            self.buffer[offset..offset + 2].copy_from_slice(&nop);
+        }
+    }
+
+    /// Grows or shrinks the buffer to end at `until_address`, growth is padding and filled.
     pub fn resize_until(&mut self, until_address: u32) -> Result<(), WriterError> {
+        let end_address = self.end_address();
+        self.resize_zeroed_until(until_address)?;
+        if until_address > end_address {
+            self.fill(end_address..until_address);
+        }
+        Ok(())
+    }
+
+    /// Like `resize_until`, but zero fills, for memory the code expects to be zeroed like bss.
+    pub fn resize_zeroed_until(&mut self, until_address: u32) -> Result<(), WriterError> {
         if until_address < self.base_address {
             return Err(WriterError::ResizeBelowBaseAddress(until_address));
         }
 
         let buf_size = until_address as usize - self.base_address as usize;
         self.buffer.resize(buf_size, 0);
+        self.tail_code = None;
 
         Ok(())
     }
+
+    /// Fills the loader region after the extra blocks placed in it. Call after the last one.
+    /// With the default fill of 0 the region is left as is.
+    pub fn fill_loader_slack(&mut self) {
+        if self.fill == 0 {
+            return;
+        }
+        if let (Some(start), Some(end)) = (self.loader_extra_address, self.loader_end_address) {
+            self.fill(start..end.min(self.end_address()));
+        }
+    }
 }
 
 #[cfg(test)]
@@ -201,9 +419,36 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_read_typed() {
+        let writer = HookWriter::new(0x1000, vec![0x00, 0x01, 0x02, 0x03, 0x04, 0x05]);
+
+        assert_eq!(writer.read_u8(0x1005), Ok(0x05));
+        assert_eq!(writer.read_u16(0x1004), Ok(0x0504));
+        assert_eq!(writer.read_u32(0x1002), Ok(0x05040302));
+
+        // Straddling the end of the buffer
+        assert_eq!(
+            writer.read_u8(0x1006),
+            Err(WriterError::OutOfBoundsRead(0x1006, 1))
+        );
+        assert_eq!(
+            writer.read_u16(0x1005),
+            Err(WriterError::OutOfBoundsRead(0x1005, 2))
+        );
+        assert_eq!(
+            writer.read_u32(0x1003),
+            Err(WriterError::OutOfBoundsRead(0x1003, 4))
+        );
+        assert_eq!(
+            writer.read_u32(0x0FFE),
+            Err(WriterError::OutOfBoundsRead(0x0FFE, 4))
+        );
+    }
+
     #[test]
     fn test_write() {
-        let mut writer = HookWriter::new(0x1000, vec![0x00; 4]);
+        let mut writer = HookWriter::new(0x1000, vec![0x00; 4]).with_duplicate_check(false);
 
         writer.write(0x1000, &[0x01]).unwrap();
         assert_eq!(writer.read::<1>(0x1000).unwrap(), [0x01]);
@@ -269,6 +514,99 @@ mod test {
             writer.write(0x1000, &[0x01, 0x02]).unwrap_err(),
             WriterError::DuplicateWrite(0x1000, 2)
         );
+
+        assert!(writer.overlaps().is_empty());
+    }
+
+    #[test]
+    fn test_duplicate_write_allowed() {
+        let mut writer = HookWriter::new(0x1000, vec![0x00; 4]).with_duplicate_check(false);
+        writer.write(0x1001, [0x01; 2]).unwrap();
+        writer.write(0x1003, [0x02]).unwrap();
+        assert!(writer.overlaps().is_empty());
+
+        writer.write(0x1000, [0x03, 0x04]).unwrap();
+        assert_eq!(writer.read::<4>(0x1000).unwrap(), [0x03, 0x04, 0x01, 0x02]);
+
+        let reason = HookWriteReason::Hook("patch", Vec::new());
+        writer
+            .write_with_reason(0x1000, [0x05; 4], reason.clone())
+            .unwrap();
+        assert_eq!(
+            writer.overlaps(),
+            [
+                HookOverlap {
+                    address: 0x1000,
+                    size: 2,
+                    reason: HookWriteReason::Misc,
+                    earlier: vec![(0x1001, 2, HookWriteReason::Misc)],
+                },
+                HookOverlap {
+                    address: 0x1000,
+                    size: 4,
+                    reason,
+                    earlier: vec![
+                        (0x1000, 2, HookWriteReason::Misc),
+                        (0x1001, 2, HookWriteReason::Misc),
+                        (0x1003, 1, HookWriteReason::Misc),
+                    ],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_overlaps_beyond_closest_write() {
+        let mut writer = HookWriter::new(0x1000, vec![0x00; 16]).with_duplicate_check(false);
+        writer.write(0x1000, [0x01; 8]).unwrap();
+        writer.write(0x1002, [0x02; 2]).unwrap();
+        // The closest earlier write ends before it, the first one still covers it
+        writer.write(0x1005, [0x03; 2]).unwrap();
+        writer.write(0x1002, [0x04; 4]).unwrap();
+
+        let earlier = |overlap: &HookOverlap| {
+            overlap
+                .earlier
+                .iter()
+                .map(|(address, size, _)| (*address, *size))
+                .collect::<Vec<_>>()
+        };
+        let overlaps = writer.overlaps();
+        assert_eq!(overlaps.len(), 3);
+        assert_eq!(earlier(&overlaps[0]), vec![(0x1000, 8)]);
+        assert_eq!(earlier(&overlaps[1]), vec![(0x1000, 8)]);
+        assert_eq!(
+            earlier(&overlaps[2]),
+            vec![(0x1000, 8), (0x1002, 2), (0x1005, 2)]
+        );
+
+        // Both writes to 0x1002 are kept
+        assert_eq!(
+            writer
+                .writes_at(0x1003)
+                .map(|(address, size, _)| (address, size))
+                .collect::<Vec<_>>(),
+            vec![(0x1000, 8), (0x1002, 2), (0x1002, 4)]
+        );
+    }
+
+    #[test]
+    fn test_writes_at() {
+        let mut writer = HookWriter::new(0x1000, vec![0x00; 8]);
+        writer.write(0x1000, [0x01; 2]).unwrap();
+        writer
+            .write_with_reason(0x1004, [0x02; 4], HookWriteReason::Hook("patch", vec![]))
+            .unwrap();
+
+        assert_eq!(
+            writer.writes_at(0x1001).collect::<Vec<_>>(),
+            vec![(0x1000, 2, &HookWriteReason::Misc)]
+        );
+        assert_eq!(writer.writes_at(0x1002).count(), 0);
+        assert_eq!(
+            writer.writes_at(0x1007).collect::<Vec<_>>(),
+            vec![(0x1004, 4, &HookWriteReason::Hook("patch", vec![]))]
+        );
     }
 
     #[test]
@@ -287,9 +625,7 @@ mod test {
 
         assert_eq!(
             writer
-                .write_extra(HookExtraPos::Loader, |_, w| {
-                    w.write_end(&[0x01]).unwrap();
-                })
+                .write_extra(HookExtraPos::Loader, |_, w| w.write_end(&[0x01]))
                 .unwrap_err(),
             WriterError::LoaderExtraAddressNotSet
         );
@@ -297,23 +633,134 @@ mod test {
         writer.set_loader_extra_address(0x1002);
         writer
             .write_extra(HookExtraPos::Loader, |_, w| {
-                w.write_end(&[0x01, 0x02]).unwrap();
+                assert_eq!(w.base_address(), 0x1004);
+                w.write_end(&[0x01, 0x02])
             })
             .unwrap();
         assert_eq!(
             writer.read::<6>(0x1000).unwrap(),
-            [0x00, 0x00, 0x01, 0x02, 0x00, 0x00]
+            [0x00, 0x00, 0x00, 0x00, 0x01, 0x02]
         );
 
+        assert_eq!(
+            writer
+                .write_extra(HookExtraPos::Tail, |_, w| {
+                    assert_eq!(w.base_address(), 0x1008);
+                    w.write_end([0x03, 0x04])
+                })
+                .unwrap(),
+            0x1008..0x100A
+        );
+        assert_eq!(
+            writer.read::<10>(0x1000).unwrap(),
+            [0x00, 0x00, 0x00, 0x00, 0x01, 0x02, 0x00, 0x00, 0x03, 0x04]
+        );
+    }
+
+    #[test]
+    fn test_write_extra_loader_overflow() {
+        let mut writer = HookWriter::new(0x1000, vec![0x00; 0x20]);
+        writer.set_loader_extra_address(0x1001);
+        writer.set_loader_end_address(0x1010);
+        assert_eq!(writer.loader_space_remaining(), Some(0xC));
+
+        writer
+            .write_extra(HookExtraPos::Loader, |_, w| w.write_end([0x01; 8]))
+            .unwrap();
+        assert_eq!(writer.loader_space_remaining(), Some(0x4));
+
+        assert_eq!(
+            writer
+                .write_extra(HookExtraPos::Loader, |_, w| w.write_end([0x02; 8]))
+                .unwrap_err(),
+            WriterError::LoaderOverflow(0x100C, 8)
+        );
+        assert_eq!(writer.read::<4>(0x100C).unwrap(), [0x00; 4]);
+
+        writer
+            .write_extra(HookExtraPos::Loader, |_, w| w.write_end([0x03; 4]))
+            .unwrap();
+        assert_eq!(writer.loader_space_remaining(), Some(0));
+    }
+
+    #[test]
+    fn test_write_extra_error() {
+        let mut writer = HookWriter::new(0x1000, vec![0x00; 0x8]);
+        writer.set_loader_extra_address(0x1000);
+
+        assert_eq!(
+            writer.write_extra(HookExtraPos::Tail, |_, w| {
+                w.write_end([0x01; 4])?;
+                w.write(0x2000, [0x02])
+            }),
+            Err(WriterError::OutOfBoundsWrite(0x2000, 1))
+        );
+        assert_eq!(writer.end_address(), 0x1008);
+
+        assert_eq!(
+            writer.write_extra(HookExtraPos::Loader, |_, w| {
+                w.write_end([0x01; 4])?;
+                Err(WriterError::LoaderExtraAddressNotSet)
+            }),
+            Err(WriterError::LoaderExtraAddressNotSet)
+        );
+        assert_eq!(writer.read::<4>(0x1000).unwrap(), [0x00; 4]);
+        assert_eq!(writer.loader_extra_address, Some(0x1000));
+    }
+
+    #[test]
+    fn test_write_extra_tail_alignment() {
+        let mut writer = HookWriter::new(0x1000, vec![0xAA; 0x5]);
+
         writer
             .write_extra(HookExtraPos::Tail, |_, w| {
-                w.write_end(&[0x03, 0x04]).unwrap();
+                assert_eq!(w.base_address(), 0x1008);
+                w.write_end(&[0x01, 0x02, 0x03])
+            })
+            .unwrap();
+        assert_eq!(writer.end_address(), 0x100B);
+        assert_eq!(writer.read::<3>(0x1005).unwrap(), [0x00; 3]);
+        assert_eq!(writer.read::<3>(0x1008).unwrap(), [0x01, 0x02, 0x03]);
+
+        writer
+            .write_extra_code(HookExtraPos::Tail, HookExtraCode::Thumb, |_, w| {
+                assert_eq!(w.base_address(), 0x100C);
+                w.write_end(&[0x04])
+            })
+            .unwrap();
+        assert_eq!(writer.read::<2>(0x100B).unwrap(), [0x00, 0x04]);
+    }
+
+    #[test]
+    fn test_write_extra_thumb_nop_padding() {
+        let mut writer = HookWriter::new(0x1000, vec![0xAA; 0x4]).with_fill(0xDEADBEEF);
+
+        writer
+            .write_extra_code(HookExtraPos::Tail, HookExtraCode::Thumb, |_, w| {
+                w.write_end([0x01, 0x02])
             })
             .unwrap();
         assert_eq!(
-            writer.read::<8>(0x1000).unwrap(),
-            [0x00, 0x00, 0x01, 0x02, 0x00, 0x00, 0x03, 0x04]
+            writer
+                .write_extra(HookExtraPos::Tail, |_, w| {
+                    assert_eq!(w.base_address(), 0x1008);
+                    w.write_end([0x03; 4])
+                })
+                .unwrap(),
+            0x1008..0x100C
         );
+        // The padding after the Thumb block is a Thumb NOP
+        assert_eq!(writer.read_u16(0x1006).unwrap(), 0x46C0);
+
+        // After ARM code and data it is the fill
+        writer.write_end([0x04]).unwrap();
+        writer
+            .write_extra_code(HookExtraPos::Tail, HookExtraCode::Thumb, |_, w| {
+                assert_eq!(w.base_address(), 0x100E);
+                w.write_end([0x05, 0x06])
+            })
+            .unwrap();
+        assert_eq!(writer.read::<1>(0x100D).unwrap(), [0xBE]);
     }
 
     #[test]
@@ -351,4 +798,43 @@ mod test {
         );
         assert_eq!(writer.read::<4>(0x1000).unwrap(), [0xAA; 4]);
     }
+
+    #[test]
+    fn test_fill() {
+        let mut writer = HookWriter::new(0x1000, vec![0xAA; 0x10]).with_fill(0xDEADBEEF);
+        writer.set_loader_extra_address(0x1006);
+        writer.set_loader_end_address(0x100C);
+
+        writer
+            .write_extra(HookExtraPos::Loader, |_, w| w.write_end([0x11; 2]))
+            .unwrap();
+        writer.fill_loader_slack();
+        assert_eq!(
+            writer.read::<0x10>(0x1000).unwrap(),
+            [
+                0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0x11, 0x11, 0xAD, 0xDE, 0xAA, 0xAA,
+                0xAA, 0xAA
+            ]
+        );
+
+        // Words at aligned addresses read as the fill word
+        writer.resize_until(0x1011).unwrap();
+        writer
+            .write_extra(HookExtraPos::Tail, |_, w| w.write_end([0x22; 4]))
+            .unwrap();
+        assert_eq!(writer.read::<3>(0x1011).unwrap(), [0xBE, 0xAD, 0xDE]);
+        assert_eq!(writer.read::<4>(0x1014).unwrap(), [0x22; 4]);
+        writer.resize_until(0x1020).unwrap();
+        assert_eq!(u32::from_le_bytes(writer.read(0x101C).unwrap()), 0xDEADBEEF);
+
+        writer.resize_zeroed_until(0x1024).unwrap();
+        assert_eq!(writer.read::<4>(0x1020).unwrap(), [0; 4]);
+
+        // Without a fill the loader keeps its original bytes
+        let mut writer = HookWriter::new(0x1000, vec![0xAA; 0x10]);
+        writer.set_loader_extra_address(0x1006);
+        writer.set_loader_end_address(0x100C);
+        writer.fill_loader_slack();
+        assert_eq!(writer.data(), [0xAA; 0x10]);
+    }
 }
@@ -0,0 +1,158 @@
+use std::path::Path;
+
+/// A single gitignore-like pattern.
+/// `*` and `?` match within a path component, a `**` component matches any number of
+/// components. Patterns without a `/` match a name at any depth, others match the path
+/// relative to the source directory. A trailing `/` only matches directories.
+#[derive(Debug, PartialEq, Clone)]
+struct Pattern {
+    components: Vec<String>,
+    anchored: bool,
+    dir_only: bool,
+}
+
+impl Pattern {
+    fn new(s: &str) -> Self {
+        let (s, dir_only) = match s.strip_suffix('/') {
+            Some(s) => (s, true),
+            None => (s, false),
+        };
+        let anchored = s.contains('/');
+        let s = s.strip_prefix('/').unwrap_or(s);
+
+        Self {
+            components: s.split('/').map(str::to_string).collect(),
+            anchored,
+            dir_only,
+        }
+    }
+
+    fn matches(&self, components: &[&str], is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+
+        match self.anchored {
+            true => match_components(&self.components, components),
+            false => components
+                .last()
+                .is_some_and(|name| match_component(&self.components[0], name)),
+        }
+    }
+}
+
+fn match_components(pattern: &[String], components: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => components.is_empty(),
+        Some((first, rest)) if first == "**" => {
+            (0..=components.len()).any(|i| match_components(rest, &components[i..]))
+        }
+        Some((first, rest)) => match components.split_first() {
+            Some((component, components)) => {
+                match_component(first, component) && match_components(rest, components)
+            }
+            None => false,
+        },
+    }
+}
+
+fn match_component(pattern: &str, name: &str) -> bool {
+    fn match_chars(pattern: &[char], name: &[char]) -> bool {
+        match pattern.split_first() {
+            None => name.is_empty(),
+            Some(('*', rest)) => (0..=name.len()).any(|i| match_chars(rest, &name[i..])),
+            Some(('?', rest)) => !name.is_empty() && match_chars(rest, &name[1..]),
+            Some((c, rest)) => name.first() == Some(c) && match_chars(rest, &name[1..]),
+        }
+    }
+
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+    match_chars(&pattern, &name)
+}
+
+/// Paths excluded from source discovery.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct IgnoreList {
+    patterns: Vec<Pattern>,
+}
+
+impl IgnoreList {
+    pub fn new(patterns: &[String]) -> Self {
+        Self {
+            patterns: patterns
+                .iter()
+                .map(|p| p.trim())
+                .filter(|p| !p.is_empty())
+                .map(Pattern::new)
+                .collect(),
+        }
+    }
+
+    /// `path` is relative to the source directory.
+    pub fn is_ignored(&self, path: impl AsRef<Path>, is_dir: bool) -> bool {
+        let components = path
+            .as_ref()
+            .iter()
+            .filter_map(|c| c.to_str())
+            .collect::<Vec<_>>();
+
+        self.patterns.iter().any(|p| p.matches(&components, is_dir))
+    }
+
+    /// Whether the file at `path` or any directory containing it is ignored, for filtering
+    /// paths that were found without this list.
+    pub fn is_file_ignored(&self, path: impl AsRef<Path>) -> bool {
+        let path = path.as_ref();
+        path.ancestors()
+            .skip(1)
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .any(|dir| self.is_ignored(dir, true))
+            || self.is_ignored(path, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_ignored() {
+        let ignore = IgnoreList::new(&[
+            "*.bak.c".into(),
+            "vendor/".into(),
+            "/lib/test_?.c".into(),
+            "gen/**/*.s".into(),
+            " ".into(),
+        ]);
+
+        assert!(ignore.is_ignored("a.bak.c", false));
+        assert!(ignore.is_ignored("sub/a.bak.c", false));
+        assert!(!ignore.is_ignored("a.c", false));
+
+        assert!(ignore.is_ignored("vendor", true));
+        assert!(ignore.is_ignored("sub/vendor", true));
+        assert!(!ignore.is_ignored("vendor", false));
+
+        assert!(ignore.is_ignored("lib/test_a.c", false));
+        assert!(!ignore.is_ignored("lib/test_ab.c", false));
+        assert!(!ignore.is_ignored("sub/lib/test_a.c", false));
+
+        assert!(ignore.is_ignored("gen/a.s", false));
+        assert!(ignore.is_ignored("gen/x/y/a.s", false));
+        assert!(!ignore.is_ignored("gen/a.c", false));
+
+        assert!(!IgnoreList::default().is_ignored("a.c", false));
+    }
+
+    #[test]
+    fn test_is_file_ignored() {
+        let ignore = IgnoreList::new(&["regions/eur/".into(), "*.bak.c".into()]);
+
+        assert!(ignore.is_file_ignored("regions/eur/a.c"));
+        assert!(ignore.is_file_ignored("regions/eur/sub/a.c"));
+        assert!(ignore.is_file_ignored("sub/a.bak.c"));
+        assert!(!ignore.is_file_ignored("regions/usa/a.c"));
+        assert!(!ignore.is_file_ignored("regions/eur"));
+    }
+}
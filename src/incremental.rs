@@ -0,0 +1,115 @@
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Inputs of a build stage. The outputs are up to date if they are newer than every input
+/// file and were built with the same options, which also covers added or removed inputs.
+pub struct StageInputs {
+    pub files: Vec<PathBuf>,
+    pub options: String,
+}
+
+fn mtime(path: impl AsRef<Path>) -> Option<SystemTime> {
+    std::fs::metadata(path).ok()?.modified().ok()
+}
+
+impl StageInputs {
+    /// `output` is the oldest output, `stamp_path` holds the options of the last build.
+    pub fn up_to_date(&self, output: impl AsRef<Path>, stamp_path: impl AsRef<Path>) -> bool {
+        let Some(output_mtime) = mtime(output) else {
+            return false;
+        };
+
+        if std::fs::read_to_string(stamp_path).ok().as_deref() != Some(self.options.as_str()) {
+            return false;
+        }
+
+        self.files
+            .iter()
+            .all(|file| mtime(file).is_some_and(|t| t <= output_mtime))
+    }
+
+    pub fn save(&self, stamp_path: impl AsRef<Path>) -> std::io::Result<()> {
+        std::fs::write(stamp_path, &self.options)
+    }
+
+    /// Makefile style dependency rule of `outputs` on the input files, like the compiler's
+    /// `.d` files.
+    pub fn dep_rule(&self, outputs: &[&Path]) -> String {
+        fn escape(path: &Path) -> String {
+            path.to_string_lossy().replace(' ', "\\ ")
+        }
+
+        let mut rule = outputs
+            .iter()
+            .map(|output| escape(output))
+            .collect::<Vec<_>>()
+            .join(" ");
+        rule.push(':');
+
+        for file in &self.files {
+            rule.push_str(" \\\n  ");
+            rule.push_str(&escape(file));
+        }
+
+        rule.push('\n');
+        rule
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use filetime::set_file_mtime;
+
+    #[test]
+    fn test_up_to_date() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let input = tempdir.path().join("a.hks");
+        let output = tempdir.path().join("code.bin");
+        let stamp = tempdir.path().join("link");
+
+        let t2 = SystemTime::now();
+        let t1 = t2 - std::time::Duration::from_secs(1);
+
+        let inputs = StageInputs {
+            files: vec![input.clone()],
+            options: "gc".into(),
+        };
+
+        std::fs::write(&input, "").unwrap();
+        set_file_mtime(&input, t1.into()).unwrap();
+        assert!(!inputs.up_to_date(&output, &stamp));
+
+        std::fs::write(&output, "").unwrap();
+        set_file_mtime(&output, t2.into()).unwrap();
+        assert!(!inputs.up_to_date(&output, &stamp));
+
+        inputs.save(&stamp).unwrap();
+        assert!(inputs.up_to_date(&output, &stamp));
+
+        let changed = StageInputs {
+            files: vec![input.clone()],
+            options: "no-gc".into(),
+        };
+        assert!(!changed.up_to_date(&output, &stamp));
+
+        set_file_mtime(&input, (t2 + std::time::Duration::from_secs(1)).into()).unwrap();
+        assert!(!inputs.up_to_date(&output, &stamp));
+
+        std::fs::remove_file(&input).unwrap();
+        assert!(!inputs.up_to_date(&output, &stamp));
+    }
+
+    #[test]
+    fn test_dep_rule() {
+        let inputs = StageInputs {
+            files: vec!["build/out.elf".into(), "hooks/my hooks.hks".into()],
+            options: String::new(),
+        };
+
+        assert_eq!(
+            inputs.dep_rule(&[Path::new("build/code.bin"), Path::new("build/exheader.bin")]),
+            "build/code.bin build/exheader.bin: \\\n  build/out.elf \\\n  hooks/my\\ hooks.hks\n"
+        );
+    }
+}
@@ -0,0 +1,32 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" fn on_sigint(_: libc::c_int) {
+    // Only async-signal-safe calls here, the build polls the flag.
+    // A second Ctrl-C gets the default handler and exits immediately.
+    INTERRUPTED.store(true, Ordering::SeqCst);
+    unsafe {
+        libc::signal(libc::SIGINT, libc::SIG_DFL);
+    }
+}
+
+/// Installs a Ctrl-C handler that only records the interrupt, so the build can stop at
+/// the next safe point instead of leaving half-written outputs behind.
+/// Queued compile jobs are dropped, jobs already running finish first. Their compiler
+/// processes are in the same process group and usually exit on the Ctrl-C themselves.
+/// Only implemented for unix, on Windows Ctrl-C still terminates the build immediately.
+pub fn install() {
+    #[cfg(unix)]
+    unsafe {
+        libc::signal(
+            libc::SIGINT,
+            on_sigint as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+    }
+}
+
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
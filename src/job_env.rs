@@ -1,40 +1,142 @@
+use super::ignore::IgnoreList;
 use super::jobs::{Job, JobKind};
+use super::sha256::sha256;
 use enum_map::EnumMap;
+use std::ffi::OsString;
 use std::path::PathBuf;
 
 use std::process::Command;
+use std::time::Duration;
 use crate::hook::symbol_safe::path_to_symbol_safe;
 
+/// Attempts to start the compiler. Virus scanners on Windows briefly lock freshly written
+/// files, which makes spawning fail with access denied.
+const SPAWN_ATTEMPTS: u32 = 3;
+/// Delay before the second attempt, doubled for every further one
+const SPAWN_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Windows ERROR_SHARING_VIOLATION
+#[cfg(windows)]
+const SHARING_VIOLATION: i32 = 32;
+
+fn is_transient_spawn_error(e: &std::io::Error) -> bool {
+    #[cfg(windows)]
+    if e.raw_os_error() == Some(SHARING_VIOLATION) {
+        return true;
+    }
+
+    e.kind() == std::io::ErrorKind::PermissionDenied
+}
+
 
 pub struct JobEnv<'a> {
     pub cwd: PathBuf,
-    pub compiler: EnumMap<JobKind, &'a str>,
+    pub compiler: EnumMap<JobKind, PathBuf>,
     pub flags: EnumMap<JobKind, Vec<&'a str>>,
+    /// Flags selecting the CPU, the linker is passed the same
+    pub cpu_flags: Vec<String>,
+    /// Flags of `MAGWI_FLAGS_*`, last so they override the others
+    pub env_flags: EnumMap<JobKind, Vec<String>>,
+    /// Define the symbol safe source path is passed in
+    pub filename_define: String,
+    /// Only checks the syntax, no object or dependency file is written
+    pub syntax_only: bool,
+    /// Sources built as Thumb code, relative to the source directory
+    pub thumb: IgnoreList,
 }
 
 impl JobEnv<'_> {
-        pub fn execute_job(&self, job: &Job) -> Result<(), std::io::Error> {
+    /// Flags `job` is compiled with, everything but the input, output and dependency file.
+    pub fn job_flags(&self, job: &Job) -> Vec<OsString> {
+        let mut flags = self.flags[job.kind]
+            .iter()
+            .map(OsString::from)
+            .chain(self.cpu_flags.iter().map(OsString::from))
+            .chain(self.env_flags[job.kind].iter().map(OsString::from))
+            .collect::<Vec<_>>();
+        flags.push(
+            format!(
+                "-D{}={}",
+                self.filename_define,
+                path_to_symbol_safe(&job.src_path)
+            )
+            .into(),
+        );
+
+        let thumb = job
+            .src_path
+            .strip_prefix("source")
+            .is_ok_and(|path| self.thumb.is_ignored(path, false));
+        if thumb {
+            flags.push("-mthumb".into());
+        }
+
+        flags
+    }
+
+    /// Hash of the compiler and flags `job` is built with, stored next to its object.
+    pub fn flags_hash(&self, job: &Job) -> String {
+        let mut data = self.compiler[job.kind].as_os_str().to_os_string();
+        for flag in self.job_flags(job) {
+            data.push("\0");
+            data.push(flag);
+        }
+
+        sha256(data.as_encoded_bytes())
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect()
+    }
+
+    /// Compiles `job`, returns the compiler's warnings on success.
+    pub fn execute_job(&self, job: &Job) -> Result<String, std::io::Error> {
         if !job.build_required() {
-            return Ok(());
+            return Ok(String::new());
         }
 
         std::fs::create_dir_all(job.obj_path.parent().unwrap()).unwrap();
         std::fs::create_dir_all(job.dep_path.parent().unwrap()).unwrap();
 
-        let compiler = self.compiler[job.kind];
-
-        let output = Command::new(compiler)
-            .current_dir(&self.cwd)
-            .arg("-MMD")
-            .arg("-MF")
-            .arg(&job.dep_path)
-            .args(&self.flags[job.kind])
-            .arg(format!("-D__mw_symbol_safe_filename={}", path_to_symbol_safe(&job.src_path)))
-            .arg("-c")
-            .arg(&job.src_path)
-            .arg("-o")
-            .arg(&job.obj_path)
-            .output()?;
+        let compiler = &self.compiler[job.kind];
+
+        let mut command = Command::new(compiler);
+        command.current_dir(&self.cwd);
+
+        if !self.syntax_only {
+            command.arg("-MMD").arg("-MF").arg(&job.dep_path);
+        }
+
+        command.args(self.job_flags(job));
+
+        if self.syntax_only {
+            command.arg("-fsyntax-only").arg(&job.src_path);
+        } else {
+            command.arg("-c").arg(&job.src_path).arg("-o").arg(&job.obj_path);
+        }
+
+        // Compile errors are reported by the exit status, only failing to run the compiler is retried
+        let mut attempt = 1;
+        let output = loop {
+            match command.output() {
+                Ok(output) => break output,
+                Err(e) if attempt < SPAWN_ATTEMPTS && is_transient_spawn_error(&e) => {
+                    std::thread::sleep(SPAWN_RETRY_DELAY * 2u32.pow(attempt - 1));
+                    attempt += 1;
+                }
+                Err(e) => {
+                    return Err(std::io::Error::new(
+                        e.kind(),
+                        format!(
+                            "Running {} for {} failed after {} attempt(s): {}",
+                            compiler.display(),
+                            job.src_path.display(),
+                            attempt,
+                            e
+                        ),
+                    ));
+                }
+            }
+        };
 
         if !output.status.success() {
             return Err(std::io::Error::new(
@@ -43,6 +145,72 @@ impl JobEnv<'_> {
             ));
         }
 
-        Ok(())
+        if !self.syntax_only {
+            std::fs::write(job.flags_path(), self.flags_hash(job))?;
+        }
+
+        Ok(String::from_utf8_lossy(&output.stderr).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jobs::BuildReason;
+
+    #[test]
+    fn test_is_transient_spawn_error() {
+        use std::io::{Error, ErrorKind};
+
+        assert!(is_transient_spawn_error(&Error::from(ErrorKind::PermissionDenied)));
+        assert!(!is_transient_spawn_error(&Error::from(ErrorKind::NotFound)));
+    }
+
+    #[test]
+    fn test_flags_changed() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let src_path = tempdir.path().join("a.c");
+        let obj_path = tempdir.path().join("a.c.o");
+        std::fs::write(&src_path, "").unwrap();
+        std::fs::write(&obj_path, "").unwrap();
+        std::fs::write(tempdir.path().join("a.c.d"), "").unwrap();
+
+        let mut job = Job {
+            kind: JobKind::C,
+            src_path,
+            obj_path,
+            dep_path: tempdir.path().join("a.c.d"),
+            build_reason: None,
+        };
+        let mut env = JobEnv {
+            cwd: tempdir.path().into(),
+            compiler: EnumMap::from_fn(|_| PathBuf::from("arm-none-eabi-gcc")),
+            flags: EnumMap::from_fn(|_| vec!["-O3"]),
+            cpu_flags: Vec::new(),
+            env_flags: EnumMap::default(),
+            filename_define: "__mw_symbol_safe_filename".into(),
+            syntax_only: false,
+            thumb: IgnoreList::default(),
+        };
+
+        job.update_build_reason(&env.flags_hash(&job));
+        assert_eq!(job.build_reason, Some(BuildReason::FlagsChanged));
+
+        std::fs::write(job.flags_path(), env.flags_hash(&job)).unwrap();
+        job.update_build_reason(&env.flags_hash(&job));
+        assert_eq!(job.build_reason, None);
+
+        env.flags[JobKind::C].push("-g");
+        job.update_build_reason(&env.flags_hash(&job));
+        assert_eq!(job.build_reason, Some(BuildReason::FlagsChanged));
+
+        env.flags[JobKind::C].pop();
+        job.update_build_reason(&env.flags_hash(&job));
+        assert_eq!(job.build_reason, None);
+
+        // e.g. MAGWI_FLAGS_C
+        env.env_flags[JobKind::C].push("-O2".into());
+        job.update_build_reason(&env.flags_hash(&job));
+        assert_eq!(job.build_reason, Some(BuildReason::FlagsChanged));
     }
 }
@@ -1,4 +1,7 @@
-use std::path::{Path, PathBuf, StripPrefixError};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use super::ignore::IgnoreList;
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum BuildReason {
@@ -9,6 +12,33 @@ pub enum BuildReason {
     DependencyNewer,
     DependencyMissing,
     NoDependencyFile,
+    /// The object was built with a different compiler or flags, or by a version that didn't
+    /// record them
+    FlagsChanged,
+}
+
+impl BuildReason {
+    pub fn name(self) -> &'static str {
+        match self {
+            BuildReason::Forced => "forced",
+            BuildReason::ObjMissing => "obj_missing",
+            BuildReason::SrcMissing => "src_missing",
+            BuildReason::SrcNewer => "src_newer",
+            BuildReason::DependencyNewer => "dependency_newer",
+            BuildReason::DependencyMissing => "dependency_missing",
+            BuildReason::NoDependencyFile => "no_dependency_file",
+            BuildReason::FlagsChanged => "flags_changed",
+        }
+    }
+}
+
+/// Dependency paths of a make style .d file written by the compiler, without the targets.
+/// Works with LF and CRLF line endings.
+fn dep_paths(dep_file: &str) -> impl Iterator<Item = &str> {
+    dep_file
+        .lines()
+        .flat_map(|line| line.split_ascii_whitespace())
+        .filter(|part| *part != "\\" && !part.ends_with(':'))
 }
 
 fn dep_requires_rebuild(
@@ -23,25 +53,17 @@ fn dep_requires_rebuild(
         return Some(BuildReason::NoDependencyFile);
     };
 
-    for line in dep_file.lines() {
-        for part in line.trim().split_ascii_whitespace() {
-            let part = part.trim();
-
-            if part == "\\" || part.ends_with(":") {
-                continue;
-            }
-
-            let Ok(part_meta) = std::fs::metadata(part) else {
-                return Some(BuildReason::DependencyMissing);
-            };
+    for part in dep_paths(&dep_file) {
+        let Ok(part_meta) = std::fs::metadata(part) else {
+            return Some(BuildReason::DependencyMissing);
+        };
 
-            let Ok(part_time) = part_meta.modified() else {
-                return Some(BuildReason::DependencyMissing);
-            };
+        let Ok(part_time) = part_meta.modified() else {
+            return Some(BuildReason::DependencyMissing);
+        };
 
-            if part_time > obj_time {
-                return Some(BuildReason::DependencyNewer);
-            }
+        if part_time > obj_time {
+            return Some(BuildReason::DependencyNewer);
         }
     }
 
@@ -56,6 +78,14 @@ pub enum JobKind {
 }
 
 impl JobKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::C => "c",
+            Self::CPP => "cpp",
+            Self::ASM => "asm",
+        }
+    }
+
     fn from_ext(ext: &str) -> Option<Self> {
         let ext = ext.to_ascii_lowercase();
         match ext.as_str() {
@@ -79,7 +109,15 @@ pub struct Job {
 }
 
 impl Job {
-    fn calc_build_reason(&self) -> Option<BuildReason> {
+    /// Sidecar of the object holding the hash of the flags it was built with.
+    pub fn flags_path(&self) -> PathBuf {
+        let mut path = self.obj_path.clone().into_os_string();
+        path.push(".flags");
+        path.into()
+    }
+
+    /// `flags_hash` is the hash of the flags the job would be built with now.
+    fn calc_build_reason(&self, flags_hash: &str) -> Option<BuildReason> {
         let Ok(src_meta) = std::fs::metadata(&self.src_path) else {
             return Some(BuildReason::SrcMissing);
         };
@@ -98,12 +136,16 @@ impl Job {
             return Some(BuildReason::SrcNewer);
         }
 
+        if std::fs::read_to_string(self.flags_path()).ok().as_deref() != Some(flags_hash) {
+            return Some(BuildReason::FlagsChanged);
+        }
+
         dep_requires_rebuild(obj_time, &self.dep_path)
     }
 
     #[allow(dead_code)]
-    pub fn update_build_reason(&mut self) {
-        self.build_reason = self.calc_build_reason();
+    pub fn update_build_reason(&mut self, flags_hash: &str) {
+        self.build_reason = self.calc_build_reason(flags_hash);
     }
 
     pub fn build_required(&self) -> bool {
@@ -111,16 +153,26 @@ impl Job {
     }
 }
 
+#[derive(Debug, thiserror::Error)]
+pub enum JobsError {
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Source {0} is not inside the source directory {1}")]
+    OutsideSource(PathBuf, PathBuf),
+}
+
 fn path_replace_prefix_add_suffix(
     path: impl AsRef<Path>,
     from: impl AsRef<Path>,
     to: impl AsRef<Path>,
     suffix: &str,
-) -> Result<PathBuf, StripPrefixError> {
+) -> Result<PathBuf, JobsError> {
+    let (path, from) = (path.as_ref(), from.as_ref());
     let mut buf = path
-        .as_ref()
         .strip_prefix(from)
-        .map(|p| to.as_ref().join(p))?
+        .map(|p| to.as_ref().join(p))
+        .map_err(|_| JobsError::OutsideSource(path.to_path_buf(), from.to_path_buf()))?
         .into_os_string();
     buf.push(suffix);
     Ok(buf.into())
@@ -132,26 +184,54 @@ fn find_jobs_impl(
     obj_path: impl AsRef<Path>,
     dep_path: impl AsRef<Path>,
     recursive: bool,
-) -> std::io::Result<Vec<Job>> {
+    ignore: &IgnoreList,
+    visited: &mut HashSet<PathBuf>,
+) -> Result<Vec<Job>, JobsError> {
     let mut jobs = Vec::new();
 
     for entry in std::fs::read_dir(current_src_path)? {
         let entry = entry?;
-        let entry_type = entry.file_type()?;
+        let mut entry_type = entry.file_type()?;
         let entry_path = entry.path();
 
+        // Symlinks are followed, dangling ones skipped
+        if entry_type.is_symlink() {
+            match std::fs::metadata(&entry_path) {
+                Ok(metadata) => entry_type = metadata.file_type(),
+                Err(_) => continue,
+            }
+        }
+
+        // Editor scratch files and directories like .git
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+
+        if let Ok(rel_path) = entry_path.strip_prefix(src_path.as_ref()) {
+            if ignore.is_ignored(rel_path, entry_type.is_dir()) {
+                continue;
+            }
+        }
+
         if recursive && entry_type.is_dir() {
+            // Symlinks can form cycles, so every directory is only walked once
+            if !visited.insert(entry_path.canonicalize()?) {
+                continue;
+            }
+
             let mut sub_jobs = find_jobs_impl(
                 &entry_path,
                 src_path.as_ref(),
                 obj_path.as_ref(),
                 dep_path.as_ref(),
                 recursive,
+                ignore,
+                visited,
             )?;
             jobs.append(&mut sub_jobs);
         } else if entry_type.is_file() {
-            if let Some(ext) = entry_path.extension() {
-                if let Some(kind) = JobKind::from_ext(ext.to_str().unwrap()) {
+            if let Some(ext) = entry_path.extension().and_then(|ext| ext.to_str()) {
+                if let Some(kind) = JobKind::from_ext(ext) {
                     let job = Job {
                         kind,
                         src_path: entry_path.clone(),
@@ -160,15 +240,13 @@ fn find_jobs_impl(
                             src_path.as_ref(),
                             obj_path.as_ref(),
                             ".o",
-                        )
-                        .expect("replacing src prefix should always work"),
+                        )?,
                         dep_path: path_replace_prefix_add_suffix(
                             &entry_path,
                             src_path.as_ref(),
                             dep_path.as_ref(),
                             ".d",
-                        )
-                        .expect("replacing src prefix should always work"),
+                        )?,
                         build_reason: Some(BuildReason::Forced),
                     };
 
@@ -181,18 +259,25 @@ fn find_jobs_impl(
     Ok(jobs)
 }
 
+/// Finds the sources in `src_path`, skipping dotfiles, dot-directories and ignored paths.
+/// Symlinks are followed, a directory linked more than once is only searched the first time.
 pub fn find_jobs(
     src_path: impl AsRef<Path>,
     obj_path: impl AsRef<Path>,
     dep_path: impl AsRef<Path>,
     recursive: bool,
-) -> std::io::Result<Vec<Job>> {
+    ignore: &IgnoreList,
+) -> Result<Vec<Job>, JobsError> {
+    let mut visited = HashSet::from([src_path.as_ref().canonicalize()?]);
+
     find_jobs_impl(
         src_path.as_ref(),
         src_path.as_ref(),
         obj_path,
         dep_path,
         recursive,
+        ignore,
+        &mut visited,
     )
 }
 
@@ -230,6 +315,7 @@ mod tests {
         set_file_mtime("obj/a.c.o", t2.into()).unwrap();
         std::fs::write("dep/a.c.d", "src/a.c: src/a1.h \\\n src/a2.h \\\n src/a3.h").unwrap();
         set_file_mtime("dep/a.c.d", t2.into()).unwrap();
+        std::fs::write("obj/a.c.o.flags", "flags").unwrap();
 
         // Rebuild: No obj file
         std::fs::write("dep/b.cpp.d", "").unwrap();
@@ -250,6 +336,7 @@ mod tests {
         set_file_mtime("src/sub/d.c", t1.into()).unwrap();
         std::fs::write("obj/sub/d.c.o", "").unwrap();
         set_file_mtime("obj/sub/d.c.o", t2.into()).unwrap();
+        std::fs::write("obj/sub/d.c.o.flags", "flags").unwrap();
         std::fs::write(
             "dep/sub/d.c.d",
             "src/sub/d.c: src/sub/d1.h \\\n src/sub/d2.h \\\n src/sub/d3.h",
@@ -295,16 +382,18 @@ mod tests {
             build_reason: Some(BuildReason::DependencyNewer),
         };
 
-        let mut jobs = find_jobs("src", "obj", "dep", false).unwrap();
-        jobs.iter_mut().for_each(|job| job.update_build_reason());
+        let mut jobs = find_jobs("src", "obj", "dep", false, &IgnoreList::default()).unwrap();
+        jobs.iter_mut()
+            .for_each(|job| job.update_build_reason("flags"));
         jobs.sort_by(|a, b| a.src_path.cmp(&b.src_path));
         assert_eq!(jobs.len(), 3);
         assert_eq!(jobs[0], job_a);
         assert_eq!(jobs[1], job_b);
         assert_eq!(jobs[2], job_c);
 
-        let mut jobs = find_jobs("src", "obj", "dep", true).unwrap();
-        jobs.iter_mut().for_each(|job| job.update_build_reason());
+        let mut jobs = find_jobs("src", "obj", "dep", true, &IgnoreList::default()).unwrap();
+        jobs.iter_mut()
+            .for_each(|job| job.update_build_reason("flags"));
         jobs.sort_by(|a, b| a.src_path.cmp(&b.src_path));
         assert_eq!(jobs.len(), 4);
         assert_eq!(jobs[0], job_a);
@@ -312,4 +401,95 @@ mod tests {
         assert_eq!(jobs[2], job_c);
         assert_eq!(jobs[3], job_d);
     }
+
+    #[test]
+    fn test_find_jobs_ignore() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let src = tempdir.path().join("src");
+
+        for path in [
+            "a.c",
+            ".#a.c",
+            ".git/b.c",
+            "vendor/c.c",
+            "sub/d.cpp",
+            "sub/scratch.c",
+        ] {
+            let path = src.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+
+        let ignore = IgnoreList::new(&["vendor/".into(), "scratch.c".into()]);
+        let mut jobs = find_jobs(&src, tempdir.path().join("obj"), "dep", true, &ignore).unwrap();
+        jobs.sort_by(|a, b| a.src_path.cmp(&b.src_path));
+
+        assert_eq!(
+            jobs.iter().map(|j| j.src_path.clone()).collect::<Vec<_>>(),
+            vec![src.join("a.c"), src.join("sub/d.cpp")]
+        );
+        assert_eq!(jobs[1].obj_path, tempdir.path().join("obj/sub/d.cpp.o"));
+    }
+
+    #[test]
+    fn test_dep_paths() {
+        let lf = "build/obj/main.cpp.o: source/main.cpp \\\n include/a.h \\\n  include/b.h\n\ninclude/a.h:\n";
+        let expected = vec!["source/main.cpp", "include/a.h", "include/b.h"];
+
+        assert_eq!(dep_paths(lf).collect::<Vec<_>>(), expected);
+        assert_eq!(
+            dep_paths(&lf.replace('\n', "\r\n")).collect::<Vec<_>>(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_path_replace_prefix_add_suffix() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let src = tempdir.path().join("source");
+
+        assert_eq!(
+            path_replace_prefix_add_suffix(src.join("sub/a.c"), &src, "build/obj", ".o").unwrap(),
+            PathBuf::from("build/obj/sub/a.c.o")
+        );
+        assert!(matches!(
+            path_replace_prefix_add_suffix("source/a.c", &src, "build/obj", ".o"),
+            Err(JobsError::OutsideSource(path, from)) if path == Path::new("source/a.c") && from == src
+        ));
+
+        std::fs::create_dir_all(src.join("sub")).unwrap();
+        std::fs::write(src.join("sub/a.c"), "").unwrap();
+        let jobs = find_jobs(&src, "build/obj", "build/dep", true, &IgnoreList::default()).unwrap();
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(jobs[0].src_path, src.join("sub/a.c"));
+        assert_eq!(jobs[0].obj_path, PathBuf::from("build/obj/sub/a.c.o"));
+        assert_eq!(jobs[0].dep_path, PathBuf::from("build/dep/sub/a.c.d"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_jobs_symlinks() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let src = tempdir.path().join("src");
+        let shared = tempdir.path().join("shared");
+
+        std::fs::create_dir_all(&src).unwrap();
+        std::fs::create_dir_all(shared.join("sub")).unwrap();
+        std::fs::write(src.join("a.c"), "").unwrap();
+        std::fs::write(shared.join("sub/b.c"), "").unwrap();
+
+        std::os::unix::fs::symlink(&shared, src.join("lib")).unwrap();
+        std::os::unix::fs::symlink(&src, shared.join("sub/loop")).unwrap();
+        std::os::unix::fs::symlink(shared.join("sub/b.c"), src.join("c.c")).unwrap();
+        std::os::unix::fs::symlink(tempdir.path().join("missing"), src.join("d.c")).unwrap();
+
+        let mut jobs = find_jobs(&src, "obj", "dep", true, &IgnoreList::default()).unwrap();
+        jobs.sort_by(|a, b| a.src_path.cmp(&b.src_path));
+
+        assert_eq!(
+            jobs.iter().map(|j| j.src_path.clone()).collect::<Vec<_>>(),
+            vec![src.join("a.c"), src.join("c.c"), src.join("lib/sub/b.c")]
+        );
+        assert_eq!(jobs[2].obj_path, PathBuf::from("obj/lib/sub/b.c.o"));
+    }
 }
@@ -0,0 +1,133 @@
+use std::fmt::{Display, Write};
+
+/// Minimal JSON value used for machine-readable output.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(i64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn object<K: Into<String>>(entries: impl IntoIterator<Item = (K, Json)>) -> Self {
+        Json::Object(entries.into_iter().map(|(k, v)| (k.into(), v)).collect())
+    }
+
+    pub fn array(values: impl IntoIterator<Item = Json>) -> Self {
+        Json::Array(values.into_iter().collect())
+    }
+
+    fn write_indented(&self, f: &mut impl Write, indent: usize) -> std::fmt::Result {
+        const INDENT: &str = "  ";
+
+        match self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(b) => write!(f, "{b}"),
+            Json::Number(n) => write!(f, "{n}"),
+            Json::String(s) => write_escaped(f, s),
+            Json::Array(values) if values.is_empty() => f.write_str("[]"),
+            Json::Array(values) => {
+                f.write_str("[\n")?;
+                for (i, value) in values.iter().enumerate() {
+                    f.write_str(&INDENT.repeat(indent + 1))?;
+                    value.write_indented(f, indent + 1)?;
+                    f.write_str(if i + 1 < values.len() { ",\n" } else { "\n" })?;
+                }
+                write!(f, "{}]", INDENT.repeat(indent))
+            }
+            Json::Object(entries) if entries.is_empty() => f.write_str("{}"),
+            Json::Object(entries) => {
+                f.write_str("{\n")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    f.write_str(&INDENT.repeat(indent + 1))?;
+                    write_escaped(f, key)?;
+                    f.write_str(": ")?;
+                    value.write_indented(f, indent + 1)?;
+                    f.write_str(if i + 1 < entries.len() { ",\n" } else { "\n" })?;
+                }
+                write!(f, "{}}}", INDENT.repeat(indent))
+            }
+        }
+    }
+}
+
+fn write_escaped(f: &mut impl Write, s: &str) -> std::fmt::Result {
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+impl Display for Json {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.write_indented(f, 0)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Self {
+        Json::Bool(value)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(value: &str) -> Self {
+        Json::String(value.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Self {
+        Json::String(value)
+    }
+}
+
+impl From<u32> for Json {
+    fn from(value: u32) -> Self {
+        Json::Number(value as i64)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(value: usize) -> Self {
+        Json::Number(value as i64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        assert_eq!(Json::Null.to_string(), "null");
+        assert_eq!(Json::from(true).to_string(), "true");
+        assert_eq!(Json::Number(-42).to_string(), "-42");
+        assert_eq!(
+            Json::from("a\"b\\c\nd\u{1}").to_string(),
+            r#""a\"b\\c\nd\u0001""#
+        );
+        assert_eq!(Json::array([]).to_string(), "[]");
+        assert_eq!(Json::object::<&str>([]).to_string(), "{}");
+        assert_eq!(
+            Json::object([
+                ("a", Json::from(1u32)),
+                ("b", Json::array([Json::from("x"), Json::Null])),
+            ])
+            .to_string(),
+            "{\n  \"a\": 1,\n  \"b\": [\n    \"x\",\n    null\n  ]\n}"
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use std::ops::Range;
+
+use super::exheader::{self, CodeSection, Exheader};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Segment {
+    Text,
+    Rodata,
+    Data,
+    Bss,
+}
+
+impl std::fmt::Display for Segment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Segment::Text => ".text",
+            Segment::Rodata => ".rodata",
+            Segment::Data => ".data",
+            Segment::Bss => ".bss",
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum LayoutError {
+    #[error("Address 0x{0:x} is outside of the code segments")]
+    Unmapped(u32),
+
+    #[error("Range 0x{0:x} with size 0x{1:x} crosses from {2} into {3}")]
+    CrossesSegments(u32, u32, Segment, Segment),
+
+    #[error("Address 0x{0:x} is in .bss, which has no data in code.bin")]
+    InBss(u32),
+}
+
+/// Address ranges of the original segments.
+/// code.bin holds text, rodata and data back to back at their load addresses, so any
+/// address in them maps directly into the writer buffer. Bss only exists at runtime.
+#[derive(Debug, PartialEq)]
+pub struct Layout {
+    segments: Vec<(Range<u32>, Segment)>,
+}
+
+fn page_range(section: &CodeSection) -> Range<u32> {
+    section.address..section.address + section.num_pages * exheader::PAGE_SIZE
+}
+
+impl Layout {
+    pub fn new(segments: Vec<(Range<u32>, Segment)>) -> Self {
+        Self { segments }
+    }
+
+    pub fn from_exheader(eh: &Exheader) -> Self {
+        let sci = &eh.info.sci;
+        let data = page_range(&sci.data_section);
+
+        Self::new(vec![
+            (page_range(&sci.text_section), Segment::Text),
+            (page_range(&sci.rodata_section), Segment::Rodata),
+            (data.clone(), Segment::Data),
+            (data.end..data.end + sci.bss_size, Segment::Bss),
+        ])
+    }
+
+    pub fn classify(&self, address: u32) -> Option<Segment> {
+        self.segments
+            .iter()
+            .find(|(range, _)| range.contains(&address))
+            .map(|(_, segment)| *segment)
+    }
+
+    /// Checks that a write of `size` bytes at `address` stays within one segment backed by
+    /// code.bin and returns that segment.
+    pub fn check_write(&self, address: u32, size: u32) -> Result<Segment, LayoutError> {
+        let start = self
+            .classify(address)
+            .ok_or(LayoutError::Unmapped(address))?;
+
+        if start == Segment::Bss {
+            return Err(LayoutError::InBss(address));
+        }
+
+        if size > 1 {
+            let last = address + size - 1;
+            let end = self.classify(last).ok_or(LayoutError::Unmapped(last))?;
+
+            if end != start {
+                return Err(LayoutError::CrossesSegments(address, size, start, end));
+            }
+        }
+
+        Ok(start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout() -> Layout {
+        Layout::new(vec![
+            (0x100000..0x102000, Segment::Text),
+            (0x102000..0x103000, Segment::Rodata),
+            (0x103000..0x104000, Segment::Data),
+            (0x104000..0x104100, Segment::Bss),
+        ])
+    }
+
+    #[test]
+    fn test_classify() {
+        let layout = layout();
+        assert_eq!(layout.classify(0x0FFFFF), None);
+        assert_eq!(layout.classify(0x100000), Some(Segment::Text));
+        assert_eq!(layout.classify(0x101FFF), Some(Segment::Text));
+        assert_eq!(layout.classify(0x102000), Some(Segment::Rodata));
+        assert_eq!(layout.classify(0x103FFF), Some(Segment::Data));
+        assert_eq!(layout.classify(0x1040FF), Some(Segment::Bss));
+        assert_eq!(layout.classify(0x104100), None);
+    }
+
+    #[test]
+    fn test_check_write() {
+        let layout = layout();
+        assert_eq!(layout.check_write(0x100000, 4), Ok(Segment::Text));
+        assert_eq!(layout.check_write(0x102FFC, 4), Ok(Segment::Rodata));
+        assert_eq!(layout.check_write(0x103000, 0), Ok(Segment::Data));
+        assert_eq!(
+            layout.check_write(0x101FFE, 4),
+            Err(LayoutError::CrossesSegments(
+                0x101FFE,
+                4,
+                Segment::Text,
+                Segment::Rodata
+            ))
+        );
+        assert_eq!(
+            layout.check_write(0x104000, 4),
+            Err(LayoutError::InBss(0x104000))
+        );
+        assert_eq!(
+            layout.check_write(0x0FFFFC, 4),
+            Err(LayoutError::Unmapped(0x0FFFFC))
+        );
+        assert_eq!(
+            layout.check_write(0x1040FE, 4),
+            Err(LayoutError::InBss(0x1040FE))
+        );
+    }
+}
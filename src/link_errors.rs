@@ -0,0 +1,110 @@
+use std::path::Path;
+
+use super::symbol_map;
+
+/// A "multiple definition" error of ld, with the locations as printed by the linker.
+#[derive(Debug, PartialEq)]
+pub struct MultipleDefinition<'a> {
+    pub symbol: &'a str,
+    pub location: &'a str,
+    pub first_location: &'a str,
+}
+
+/// Parses a "multiple definition of `symbol'; <location>: first defined here" line of ld.
+/// Depending on the locale, ld quotes the symbol with either ASCII or typographic quotes.
+pub fn parse_multiple_definition(line: &str) -> Option<MultipleDefinition<'_>> {
+    let (location, rest) = line.split_once("multiple definition of ")?;
+    let rest = rest.strip_prefix(['`', '‘', '\''])?;
+    let end = rest.rfind(['\'', '’'])?;
+    let symbol = &rest[..end];
+    let close_len = rest[end..].chars().next()?.len_utf8();
+    let first_location = rest[end + close_len..]
+        .strip_prefix("; ")?
+        .strip_suffix("first defined here")?;
+
+    Some(MultipleDefinition {
+        symbol,
+        location,
+        first_location,
+    })
+}
+
+/// Finds the source of the object referenced by a linker location. Object paths are matched
+/// as printed, the longest match wins so `a.o` does not shadow `ba.o`.
+fn find_source<'a>(location: &str, objects: &[(&Path, &'a Path)]) -> Option<&'a Path> {
+    objects
+        .iter()
+        .map(|(obj_path, src_path)| (obj_path.to_string_lossy(), *src_path))
+        .filter(|(obj_path, _)| location.contains(obj_path.as_ref()))
+        .max_by_key(|(obj_path, _)| obj_path.len())
+        .map(|(_, src_path)| src_path)
+}
+
+/// Rewrites a "multiple definition" linker error into a message naming the sources defining
+/// the symbol. `objects` maps the object paths passed to the linker to their sources.
+pub fn describe_multiple_definition(line: &str, objects: &[(&Path, &Path)]) -> Option<String> {
+    let definition = parse_multiple_definition(line)?;
+    let first_src = find_source(definition.first_location, objects)?;
+    let src = find_source(definition.location, objects)?;
+    let symbol =
+        symbol_map::demangle(definition.symbol).unwrap_or_else(|| definition.symbol.to_string());
+
+    Some(format!(
+        "`{symbol}` defined in both {} and {}",
+        first_src.display(),
+        src.display()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINE: &str = "ld: build/obj/b.cpp.o:b.cpp:(.text+0x0): multiple definition of `my_hook'; build/obj/a.cpp.o:a.cpp:(.text+0x0): first defined here";
+
+    #[test]
+    fn test_parse_multiple_definition() {
+        assert_eq!(
+            parse_multiple_definition(LINE),
+            Some(MultipleDefinition {
+                symbol: "my_hook",
+                location: "ld: build/obj/b.cpp.o:b.cpp:(.text+0x0): ",
+                first_location: "build/obj/a.cpp.o:a.cpp:(.text+0x0): ",
+            })
+        );
+        assert_eq!(
+            parse_multiple_definition(
+                "ld: b.o: multiple definition of ‘_Z3foov’; a.o: first defined here"
+            )
+            .map(|d| d.symbol),
+            Some("_Z3foov")
+        );
+        assert_eq!(
+            parse_multiple_definition("ld: undefined reference to `my_hook'"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_describe_multiple_definition() {
+        let objects = [
+            (Path::new("build/obj/a.cpp.o"), Path::new("source/a.cpp")),
+            (Path::new("build/obj/b.cpp.o"), Path::new("source/b.cpp")),
+            (Path::new("build/obj/ab.cpp.o"), Path::new("source/ab.cpp")),
+        ];
+
+        assert_eq!(
+            describe_multiple_definition(LINE, &objects).as_deref(),
+            Some("`my_hook` defined in both source/a.cpp and source/b.cpp")
+        );
+        assert_eq!(
+            describe_multiple_definition(
+                "ld: build/obj/b.cpp.o: multiple definition of `_Z3foov'; build/obj/ab.cpp.o: first defined here",
+                &objects
+            )
+            .as_deref(),
+            Some("`foo()` defined in both source/ab.cpp and source/b.cpp")
+        );
+        assert_eq!(describe_multiple_definition(LINE, &objects[2..]), None);
+    }
+}
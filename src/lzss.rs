@@ -0,0 +1,119 @@
+//! Decompression of the backwards LZSS used for compressed ExeFS .code.
+//!
+//! The data is decompressed from its end towards its start, in place. A footer stores the size
+//! of the compressed region and how much larger the decompressed data is; everything before
+//! the compressed region is stored as is.
+
+/// Size of the footer fields, padding may precede them.
+const FOOTER_SIZE: usize = 8;
+
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum LzssError {
+    #[error("Compressed data is truncated")]
+    Truncated,
+
+    #[error("Compressed data is corrupted")]
+    Corrupted,
+}
+
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, LzssError> {
+    let footer = data
+        .len()
+        .checked_sub(FOOTER_SIZE)
+        .map(|start| &data[start..])
+        .ok_or(LzssError::Truncated)?;
+    let bounds = u32::from_le_bytes(footer[..4].try_into().unwrap());
+    let extra_size = u32::from_le_bytes(footer[4..].try_into().unwrap()) as usize;
+
+    let header_size = (bounds >> 24) as usize;
+    let compressed_size = (bounds & 0xFFFFFF) as usize;
+    if header_size < FOOTER_SIZE || header_size > compressed_size || compressed_size > data.len() {
+        return Err(LzssError::Corrupted);
+    }
+
+    let mut out = data.to_vec();
+    out.resize(data.len() + extra_size, 0);
+
+    let stop = data.len() - compressed_size;
+    let mut src = data.len() - header_size;
+    let mut dst = out.len();
+
+    while src > stop {
+        src -= 1;
+        let mut control = data[src];
+
+        for _ in 0..8 {
+            if src <= stop {
+                break;
+            }
+
+            if control & 0x80 != 0 {
+                if src < stop + 2 {
+                    return Err(LzssError::Corrupted);
+                }
+                src -= 2;
+                let token = u16::from_le_bytes([data[src], data[src + 1]]) as usize;
+                let size = (token >> 12) + 3;
+                let offset = (token & 0xFFF) + 2;
+
+                if dst < size {
+                    return Err(LzssError::Corrupted);
+                }
+                for _ in 0..size {
+                    let byte = *out.get(dst + offset).ok_or(LzssError::Corrupted)?;
+                    dst -= 1;
+                    out[dst] = byte;
+                }
+            } else {
+                if dst == 0 {
+                    return Err(LzssError::Corrupted);
+                }
+                src -= 1;
+                dst -= 1;
+                out[dst] = data[src];
+            }
+
+            control <<= 1;
+        }
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compressed(body: &[u8], extra_size: u32) -> Vec<u8> {
+        let mut data = b"XY".to_vec();
+        data.extend_from_slice(body);
+        let bounds = (FOOTER_SIZE as u32) << 24 | (body.len() + FOOTER_SIZE) as u32;
+        data.extend_from_slice(&bounds.to_le_bytes());
+        data.extend_from_slice(&extra_size.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn test_decompress() {
+        // Read backwards: three literals, then 18 bytes copied from 3 bytes ahead
+        let data = compressed(&[0x00, 0xF0, b'a', b'b', b'c', 0x10], 7);
+        let mut expected = b"XY".to_vec();
+        expected.extend(b"abc".repeat(7));
+        assert_eq!(decompress(&data), Ok(expected));
+    }
+
+    #[test]
+    fn test_decompress_invalid() {
+        assert_eq!(decompress(&[0; 4]), Err(LzssError::Truncated));
+
+        // Compressed region larger than the data
+        let mut data = compressed(&[0x00], 0);
+        let footer = data.len() - FOOTER_SIZE;
+        data[footer] = 0x20;
+        assert_eq!(decompress(&data), Err(LzssError::Corrupted));
+
+        // Back reference past the end of the data
+        let data = compressed(&[0xFF, 0xF0, 0x80], 2);
+        assert_eq!(decompress(&data), Err(LzssError::Corrupted));
+    }
+}
@@ -1,35 +1,55 @@
 use std::collections::HashMap;
-use std::io::prelude::*;
-use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::{io::Write, path::PathBuf, process::Command, vec};
 
 use binrw::{BinReaderExt, BinWriterExt};
 use enum_map::enum_map;
 use object::read::*;
+use object::SectionKind;
 
 use super::{
+    args::{Args, EmptyObjects, Profile},
+    config,
+    exefs::Exefs,
     exheader::{self, Exheader},
-    hook::{self, HookExtraPos, HookInfo, HookKind, HookLocation, HookWriter},
+    gc,
+    hook::{
+        self,
+        hks::{HksEntry, HksParseError},
+        HookExtraPos, HookInfo, HookKind, HookLocation, HookPrefix, HookWriteReason, HookWriter,
+    },
+    ignore::IgnoreList,
+    incremental, interrupt,
     job_env::JobEnv,
-    jobs::{find_jobs, Job, JobKind},
+    jobs::{self, find_jobs, BuildReason, Job, JobKind},
+    json::Json,
+    layout, link_errors, lzss,
+    ncch::Ncch,
+    observer::BuildObserver,
+    output, symbol_cache, symbol_map,
+    timing::Timings,
+    toolchain::Toolchain,
+    version,
     worker_pool::{TaskResult, WorkerPool},
+    APP_NAME, APP_VERSION,
 };
 
-#[derive(Debug)]
-struct PrePostEntry {
-    extra_pos: HookExtraPos,
-    pre: Vec<(u32, HookLocation)>,
-    post: Vec<(u32, HookLocation)>,
-}
-
 #[derive(Debug, thiserror::Error)]
 pub enum MakeError {
-    #[error("Compilation Failed")]
+    #[error("{0}")]
+    Fatal(String),
+
+    #[error("Compilation failed")]
     CompilationFailed,
 
-    #[error("Linking Failed")]
+    #[error("Linking failed")]
     LinkingFailed,
 
+    #[error("Patched code differs from the reference in {0} ranges")]
+    VerifyFailed(usize),
+
+    #[error("Interrupted")]
+    Interrupted,
+
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
@@ -39,332 +59,4585 @@ pub enum MakeError {
     #[error("Object parsing error: {0}")]
     Object(#[from] object::read::Error),
 
-    #[error("Hook error: {0}")]
+    #[error("{0}: {1}{source_line}", source_line = SourceLine(.0))]
     HookLocation(HookLocation, String),
 
-    #[error("Hook error: {0}")]
+    #[error("{0}")]
     Hook(#[from] hook::Error),
+
+    /// Failed write of a trampoline, reported with the location of its hook by the caller
+    #[error("{0}")]
+    Writer(#[from] hook::WriterError),
+
+    #[error("{0}")]
+    Config(#[from] config::ConfigError),
+
+    #[error("Failed to find jobs: {0}")]
+    Jobs(#[from] jobs::JobsError),
 }
 
 pub type MakeResult<T> = core::result::Result<T, MakeError>;
 
-struct Make {
-    project_path: PathBuf,
-    writer: HookWriter,
-    exheader: Exheader,
-    jobs: Vec<Job>,
-    loader_address: u32,
-    loader_max_size: u32,
-    custom_text_address: u32,
-    pre_post_entries: Vec<PrePostEntry>,
-    symtab_index: HashMap<String, u32>,
+/// Formats the source line of a location on its own line, or nothing if it can't be read.
+pub struct SourceLine<'a>(pub &'a HookLocation);
+
+impl std::fmt::Display for SourceLine<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Some(index) = (self.0.line as usize).checked_sub(1) else {
+            return Ok(());
+        };
+
+        let Ok(source) = std::fs::read_to_string(&self.0.file) else {
+            return Ok(());
+        };
+
+        match source.lines().nth(index) {
+            Some(line) => write!(f, "\n    {} | {}", self.0.line, line),
+            None => Ok(()),
+        }
+    }
+}
+
+macro_rules! fatal_error {
+    ($($arg:tt)*) => {
+        return Err(MakeError::Fatal(format!($($arg)*)))
+    };
 }
 
 macro_rules! hook_error {
-    ($loc:expr, $($arg:tt)*) => {
-        return Err(MakeError::HookLocation($loc, format!($($arg)*)));
+    ($location:expr, $($arg:tt)*) => {
+        return Err(hook_err!($location, $($arg)*))
     };
 }
 
-impl Make {
-    pub fn new(project_path: impl AsRef<Path>) -> MakeResult<Self> {
-        let project_path = project_path.as_ref().to_path_buf();
-        std::env::set_current_dir(&project_path)?;
+/// Creates the error `hook_error!` returns, for use in closures.
+macro_rules! hook_err {
+    ($location:expr, $($arg:tt)*) => {
+        MakeError::HookLocation(($location).clone(), format!($($arg)*))
+    };
+}
 
-        let writer = HookWriter::new(0x100000, std::fs::read("original/code.bin")?);
+/// Number of compile jobs listed by `--time`.
+const SLOWEST_JOB_COUNT: usize = 5;
 
-        let exheader: Exheader = std::fs::File::open("original/exheader.bin")?.read_ne()?;
+/// Reports parsing errors with a location at the hook's source.
+fn hook_discovery_error(e: hook::Error) -> MakeError {
+    match e {
+        hook::Error::ParsingError(e, location) => MakeError::HookLocation(location, e.to_string()),
+        e => MakeError::Hook(e),
+    }
+}
 
-        let loader_address =
-            exheader.info.sci.text_section.address + exheader.info.sci.text_section.size;
-        let loader_max_size = exheader.info.sci.text_section.num_pages * exheader::PAGE_SIZE
-            - exheader.info.sci.text_section.size;
-        let custom_text_address = exheader.info.sci.data_section.address
-            + exheader.info.sci.data_section.num_pages * exheader::PAGE_SIZE
-            + exheader.info.sci.bss_size;
+fn check_interrupted() -> MakeResult<()> {
+    match interrupt::interrupted() {
+        true => Err(MakeError::Interrupted),
+        false => Ok(()),
+    }
+}
 
-        let jobs = find_jobs("source", "build/obj", "build/dep", true)?;
+fn print_step(step: usize, name: &str) {
+    const NUM_STEPS: usize = 4;
+    output::status!(
+        "{} {}",
+        console::style(format!("[{step}/{NUM_STEPS}]")).bold(),
+        console::style(name).cyan().bold(),
+    );
+}
 
-        Ok(Self {
-            project_path,
-            writer,
-            exheader,
-            jobs,
-            loader_address,
-            loader_max_size,
-            custom_text_address,
-            pre_post_entries: Vec::new(),
-            symtab_index: HashMap::new(),
-        })
+/// Load address of code.bin, usually 0x100000.
+fn calc_code_base(eh: &Exheader) -> u32 {
+    eh.info.sci.text_section.address
+}
+
+fn calc_loader_address(eh: &Exheader) -> u32 {
+    eh.info.sci.text_section.address + eh.info.sci.text_section.size
+}
+
+fn calc_loader_max_size(eh: &Exheader) -> u32 {
+    eh.info.sci.text_section.num_pages * exheader::PAGE_SIZE - eh.info.sci.text_section.size
+}
+
+/// Size code.bin needs to hold text, rodata and data.
+fn calc_min_code_size(eh: &Exheader) -> u32 {
+    (eh.info.sci.data_section.address + eh.info.sci.data_section.size)
+        .saturating_sub(calc_code_base(eh))
+}
+
+fn calc_custom_text_address(eh: &Exheader) -> u32 {
+    eh.info.sci.data_section.address
+        + eh.info.sci.data_section.num_pages * exheader::PAGE_SIZE
+        + eh.info.sci.bss_size
+}
+
+/// Declares the loader as part of the text section and, unless the appended code is loaded
+/// separately, extends the data section up to `code_end`.
+///
+/// The former bss becomes initialized data. That is equivalent as long as code.bin holds zeros
+/// for it: the custom text starts after the original bss and the writer zero fills everything
+/// up to it, and hooks can't write to bss.
+fn patch_exheader(eh: &mut Exheader, code_end: Option<u32>) {
+    let sci = &mut eh.info.sci;
+    sci.text_section.size = sci.text_section.num_pages * exheader::PAGE_SIZE;
+
+    if let Some(code_end) = code_end {
+        sci.data_section.size = code_end - sci.data_section.address;
+        sci.data_section.num_pages = exheader::page_count(sci.data_section.size);
+        sci.bss_size = 0;
     }
+}
 
-    pub fn run(&mut self) -> MakeResult<()> {
-        self.compile()?;
-        self.pre_link()?;
-        self.link()?;
-        self.sym_hooks()?;
-        self.patch_exheader()?;
-        Ok(())
+/// Reads an address key of an hks entry relative to the file's current base.
+/// The value may also name a symbol, whose address is absolute and not offset by the base.
+fn get_hks_address(
+    h: &mut HksEntry,
+    key: &str,
+    base: u32,
+    symtab_index: &HashMap<String, u32>,
+) -> std::result::Result<u32, HksParseError> {
+    resolve_hks_address(h.get(key)?, base, symtab_index)
+}
+
+fn resolve_hks_address(
+    value: String,
+    base: u32,
+    symtab_index: &HashMap<String, u32>,
+) -> std::result::Result<u32, HksParseError> {
+    if let Ok(address) = hook::parse_address(&value) {
+        return Ok(address.wrapping_add(base));
     }
 
-    fn compile(&mut self) -> MakeResult<()> {
-        let job_env = std::sync::Arc::from(JobEnv {
-            cwd: self.project_path.clone(),
-            compiler: enum_map! {
-                JobKind::C   => "arm-none-eabi-gcc",
-                JobKind::CPP => "arm-none-eabi-g++",
-                JobKind::ASM => "arm-none-eabi-gcc",
-            },
-            flags: enum_map! {
-                JobKind::C   => vec![
-                    "-iquote", "include", "-isystem", "include/sys", "-isystem", "include/sys/clib",
-                    "-march=armv6k+fp", "-mtune=mpcore", "-mfloat-abi=hard", "-mtp=soft",
-                    "-fdiagnostics-color", "-Wall", "-O3", "-mword-relocations", "-fshort-wchar", "-fomit-frame-pointer", "-ffunction-sections", "-nostdinc"
-                ],
-                JobKind::CPP => vec![
-                    "-iquote", "include", "-isystem", "include/sys", "-isystem", "include/sys/clib",
-                    "-march=armv6k+fp", "-mtune=mpcore", "-mfloat-abi=hard", "-mtp=soft",
-                    "-fdiagnostics-color", "-Wall", "-O3", "-mword-relocations", "-fshort-wchar", "-fomit-frame-pointer", "-ffunction-sections", "-nostdinc",
-                    "-fno-exceptions", "-fno-rtti"
-                ],
-                JobKind::ASM => vec![
-                    "-iquote", "include", "-isystem", "include/sys", "-isystem", "include/sys/clib",
-                    "-march=armv6k+fp", "-mtune=mpcore", "-mfloat-abi=hard", "-mtp=soft",
-                    "-fdiagnostics-color", "-x", "assembler-with-cpp"
-                ],
-            },
-        });
+    symtab_index
+        .get(value.as_str())
+        .copied()
+        .ok_or(HksParseError::UnknownAddressSymbol(value))
+}
 
-        self.jobs.iter_mut().for_each(|job| {
-            job.update_build_reason();
-        });
+/// Expands the `addrs` of a `nop_list` or `branch_list` entry into one instruction per
+/// address, a branch to `branch` as (link, destination) if given, otherwise a nop. Errors
+/// name the index of the address they are about.
+fn expand_hks_list(
+    addrs: Vec<String>,
+    base: u32,
+    symtab_index: &HashMap<String, u32>,
+    layout: &layout::Layout,
+    branch: Option<(bool, u32)>,
+) -> std::result::Result<Vec<(u32, u32)>, String> {
+    addrs
+        .into_iter()
+        .enumerate()
+        .map(|(i, value)| {
+            let address = resolve_hks_address(value, base, symtab_index)
+                .map_err(|e| format!("addrs[{i}]: {e}"))?;
+            let segment = layout
+                .check_write(address, 4)
+                .map_err(|e| format!("addrs[{i}]: {e}"))?;
+            log::trace!("hks write 0x{:x} in {}", address, segment);
 
-        let todo_jobs: Vec<&Job> = self
-            .jobs
-            .iter()
-            .filter(|job| job.build_required())
-            .collect();
+            let instruction = match branch {
+                Some((link, to_address)) => hook::arm::make_branch_u32(
+                    link,
+                    address,
+                    to_address,
+                    hook::arm::ArmCondition::AL,
+                )
+                .map_err(|e| format!("addrs[{i}]: {e}"))?,
+                None => hook::arm::make_nop_u32(),
+            };
+            Ok((address, instruction))
+        })
+        .collect()
+}
 
-        let pb_root = indicatif::MultiProgress::new();
+/// Reads the destination of a branch hook, either the symbol `func` or the address `dest`,
+/// plus the optional `offset` into it.
+fn get_hks_branch_dest(
+    h: &mut HksEntry,
+    base: u32,
+    symtab_index: &HashMap<String, u32>,
+) -> std::result::Result<u32, HksParseError> {
+    let dest = if h.has("func") {
+        let sym = h.get("func")?;
+        symtab_index
+            .get(sym.as_str())
+            .copied()
+            .ok_or(HksParseError::UnknownSymbol(sym))?
+    } else {
+        get_hks_address(h, "dest", base, symtab_index)?
+    };
 
-        let pb = indicatif::ProgressBar::new(todo_jobs.len() as u64);
-        pb.set_style(
-            indicatif::ProgressStyle::with_template(
-                "{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {pos}/{len} ({eta})",
-            )
-            .expect("Progress style template should be valid")
-            .progress_chars("=>."),
-        );
-        pb_root.add(pb.clone());
-        pb.inc(0);
-
-        let spinner_style = indicatif::style::ProgressStyle::default_spinner()
-            .template("{spinner:.green} {msg}")
-            .expect("Progress style template should be valid");
-
-        let num_workers = num_cpus::get();
-        let spinners = (0..num_workers)
-            .map(|_| {
-                let pb = pb_root.add(indicatif::ProgressBar::new_spinner());
-                pb.set_style(spinner_style.clone());
-                pb.set_message(format!("waiting..."));
-                pb
-            })
-            .collect::<Vec<_>>();
+    let offset = match h.has("offset") {
+        true => h.get_address("offset")?,
+        false => 0,
+    };
+
+    Ok(dest.wrapping_add(offset))
+}
+
+/// Symbols referenced by the entries of the hks file at `path` that are not in `symtab_index`,
+/// with their locations. Entries that fail to parse are left to the hook pass to report.
+fn unresolved_hks_symbols(
+    path: &std::path::Path,
+    entries: impl IntoIterator<Item = std::result::Result<HksEntry, hook::hks::HksError>>,
+    symtab_index: &HashMap<String, u32>,
+) -> Vec<(HookLocation, String)> {
+    let mut unresolved = Vec::new();
 
-        let mut pool = WorkerPool::new(num_workers);
+    for mut h in entries.into_iter().flatten() {
+        for value in hks_symbol_values(&mut h) {
+            if !symtab_index.contains_key(&value) {
+                let location = HookLocation {
+                    file: path.to_path_buf(),
+                    line: h.line() as u32,
+                };
+                unresolved.push((location, value));
+            }
+        }
+    }
 
-        for job in todo_jobs {
-            let pb = pb.clone();
-            let spinners = spinners.clone();
-            let job = job.clone();
-            let job_env: std::sync::Arc<JobEnv<'_>> = job_env.clone();
+    unresolved
+}
 
-            pool.submit_task(move |thread_idx| {
-                let spinner = &spinners[thread_idx];
-                spinner.enable_steady_tick(std::time::Duration::from_millis(100));
-                spinner.set_message(job.src_path.display().to_string());
+/// Values of an hks entry that name symbols. Consumes `addrs`, so it is only for entries
+/// that aren't applied afterwards.
+fn hks_symbol_values(h: &mut HksEntry) -> Vec<String> {
+    let addrs = h.get_list("addrs").unwrap_or_default();
+    let values = ["addr", "dest", "func", "sym"]
+        .into_iter()
+        .filter_map(|key| Some((key, h.peek(key)?.to_string())))
+        .chain(addrs.into_iter().map(|value| ("addrs", value)));
 
-                match job_env.execute_job(&job) {
-                    Ok(_) => {
-                        pb.inc(1);
-                        TaskResult::Ok
-                    }
-                    Err(e) => {
-                        pb.println(e.to_string());
-                        TaskResult::Terminate
-                    }
+    values
+        // Only addresses may be given as numbers
+        .filter(|(key, value)| {
+            !(matches!(*key, "addr" | "addrs" | "dest") && hook::parse_address(value).is_ok())
+        })
+        .map(|(_, value)| value)
+        .collect()
+}
+
+/// Address, size and associated data of a range.
+type SizedRange<T> = (u32, u64, T);
+
+/// Sorts `ranges` and returns the first pair that overlaps.
+fn find_overlap<T>(ranges: &mut [SizedRange<T>]) -> Option<(&SizedRange<T>, &SizedRange<T>)> {
+    ranges.sort_by_key(|(address, _, _)| *address);
+    ranges
+        .windows(2)
+        .find(|w| w[0].0 as u64 + w[0].1 > w[1].0 as u64)
+        .map(|w| (&w[0], &w[1]))
+}
+
+fn format_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Prints the original and final bytes at `address`, the writes covering it and the
+/// trampoline of a pre/post hook at or in it.
+fn print_explanation(
+    address: u32,
+    original_code: &[u8],
+    writer: &HookWriter,
+    trampolines: &HashMap<u32, std::ops::Range<u32>>,
+) {
+    println!(
+        "{}",
+        console::style(format!("Explain 0x{address:08x}:")).bold()
+    );
+
+    let original = (address.checked_sub(writer.base_address()))
+        .and_then(|offset| original_code.get(offset as usize..offset as usize + 4));
+    match original {
+        Some(bytes) => println!("  original: {}", format_bytes(bytes)),
+        None => println!("  original: not in original code"),
+    }
+
+    match writer.read::<4>(address) {
+        Ok(bytes) => println!("     final: {}", format_bytes(&bytes)),
+        Err(_) => println!("     final: not in output"),
+    }
+
+    let mut written = false;
+    for (write_address, size, reason) in writer.writes_at(address) {
+        written = true;
+        match reason {
+            HookWriteReason::Hook(kind, locations) => {
+                println!("  {kind} hook writing 0x{size:x} bytes at 0x{write_address:08x}");
+                for location in locations {
+                    println!("    from {location}");
                 }
-            });
+            }
+            _ => println!("  write of 0x{size:x} bytes at 0x{write_address:08x} ({reason:?})"),
+        }
+    }
+    if !written {
+        println!("  not touched by any hook");
+    }
+
+    for (site, range) in trampolines {
+        if *site != address && !range.contains(&address) {
+            continue;
+        }
+
+        print_trampoline(writer, *site, range);
+    }
+}
+
+fn print_instruction(writer: &HookWriter, address: u32) {
+    let Ok(instruction) = writer.read_u32(address) else {
+        return;
+    };
+    println!(
+        "    0x{address:08x}: {instruction:08x}  {}",
+        hook::arm::disassemble_u32(instruction, address)
+    );
+}
+
+/// Prints the jump at the hooked address followed by the trampoline it leads to.
+fn print_trampoline(writer: &HookWriter, site: u32, range: &std::ops::Range<u32>) {
+    println!(
+        "  trampoline for 0x{site:08x} at 0x{:08x}..0x{:08x}:",
+        range.start, range.end
+    );
+    print_instruction(writer, site);
+    println!("    ...");
+    for instruction_address in range.clone().step_by(4) {
+        print_instruction(writer, instruction_address);
+    }
+}
+
+/// Formats every hook write on its own line, sorted by address so changes show up in diffs.
+/// Branches are disassembled and pointers show their value.
+fn format_manifest(writer: &HookWriter) -> String {
+    let mut manifest = String::new();
+
+    for (address, size, reason) in writer.writes() {
+        let HookWriteReason::Hook(kind, locations) = reason else {
+            continue;
+        };
+
+        let value = writer.read_u32(address).ok().filter(|_| size == 4);
+        let detail = match (*kind, value) {
+            ("branch" | "pre/post" | "detour", Some(value)) => {
+                hook::arm::disassemble_u32(value, address)
+            }
+            ("symptr" | "detour_original", Some(value)) => format!("-> 0x{value:08x}"),
+            _ => format!("0x{size:x} bytes"),
+        };
+        let locations = locations
+            .iter()
+            .map(|location| location.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let line = format!("0x{address:08x}  {kind:<15}  {detail:<20}  {locations}");
+        manifest.push_str(line.trim_end());
+        manifest.push('\n');
+    }
+
+    manifest
+}
+
+/// Side by side hexdump of the original and patched bytes of every region of the original code
+/// that hooks wrote to, with the hooks responsible. Adjacent and overlapping writes form one
+/// region and regions are sorted by address, so the dump can be committed and diffed.
+fn format_hexdiff(writer: &HookWriter, original: &[u8]) -> String {
+    const ROW_SIZE: usize = 8;
+
+    let base_address = writer.base_address();
+    let original_end = base_address + original.len() as u32;
+
+    let mut regions: Vec<(std::ops::Range<u32>, Vec<&HookWriteReason>)> = Vec::new();
+    for (address, size, reason) in writer.writes() {
+        if address >= original_end {
+            continue;
         }
+        let end = (address + size).min(original_end);
 
-        let pool_result = pool.wait();
+        match regions.last_mut() {
+            Some((range, reasons)) if address <= range.end => {
+                range.end = range.end.max(end);
+                reasons.push(reason);
+            }
+            _ => regions.push((address..end, vec![reason])),
+        }
+    }
 
-        pb.finish_and_clear();
-        for spinner in spinners {
-            spinner.finish_and_clear();
+    let patched = &writer.data()[..original.len()];
+    let mut hexdiff = String::new();
+    for (range, reasons) in regions {
+        let offsets = (range.start - base_address) as usize..(range.end - base_address) as usize;
+        if original[offsets.clone()] == patched[offsets.clone()] {
+            continue;
         }
-        pb_root.clear().ok();
 
-        if pool_result != TaskResult::Ok {
-            return Err(MakeError::CompilationFailed);
+        hexdiff.push_str(&format!("0x{:08x}..0x{:08x}\n", range.start, range.end));
+        for reason in reasons {
+            match reason {
+                HookWriteReason::Hook(kind, locations) => {
+                    let locations = locations
+                        .iter()
+                        .map(|location| location.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    hexdiff.push_str(&format!("  {kind} hook at {locations}\n"));
+                }
+                _ => hexdiff.push_str(&format!("  {reason:?} write\n")),
+            }
         }
 
-        Ok(())
+        for start in offsets.clone().step_by(ROW_SIZE) {
+            let row = start..(start + ROW_SIZE).min(offsets.end);
+            let marker = if original[row.clone()] == patched[row.clone()] {
+                ' '
+            } else {
+                '*'
+            };
+            hexdiff.push_str(&format!(
+                "{marker} 0x{:08x}  {:<width$}  |  {}\n",
+                base_address + row.start as u32,
+                format_bytes(&original[row.clone()]),
+                format_bytes(&patched[row]),
+                width = ROW_SIZE * 3 - 1,
+            ));
+        }
+        hexdiff.push('\n');
     }
 
-    fn pre_link(&mut self) -> MakeResult<()> {
-        let mut linker_file = std::fs::File::create("build/linker.ld")?;
+    hexdiff
+}
 
-        linker_file.write("SECTIONS\n{\n    /* Hook Generated Sections */\n".as_bytes())?;
+/// Address ranges in which `data` differs from `reference`, both loaded at `base_address`.
+/// Bytes past the end of the shorter one differ as well.
+fn diff_ranges(data: &[u8], reference: &[u8], base_address: u32) -> Vec<std::ops::Range<u32>> {
+    let mut ranges: Vec<std::ops::Range<u32>> = Vec::new();
 
-        for job in &self.jobs {
-            let elf_data = std::fs::read(&job.obj_path)?;
-            let elf_file = object::File::parse(elf_data.as_slice())?;
+    for offset in 0..data.len().max(reference.len()) {
+        if data.get(offset) == reference.get(offset) {
+            continue;
+        }
 
-            for section in elf_file.sections() {
-                let Ok(name) = section.name() else {
-                    continue;
-                };
+        let address = base_address + offset as u32;
+        match ranges.last_mut() {
+            Some(range) if range.end == address => range.end += 1,
+            _ => ranges.push(address..address + 1),
+        }
+    }
 
-                match HookInfo::from_section_str(name) {
-                    Ok(hi) => {
-                        match hi.kind {
-                            HookKind::Replace(repl_addr) => {
-                                linker_file
-                                    .write(
-                                        format!("    {name} 0x{repl_addr:x} : {{ *({name}); }}\n")
-                                            .as_bytes(),
-                                    )
-                                    .unwrap();
-                            }
-                            // Invalid kinds are discarded
-                            _ => {
-                                hook_error!(hi.location, "Invalid hook kind for section hook");
-                            }
-                        }
-                    }
-                    Err(hook::Error::InvalidPrefix) => {}
-                    Err(hook::Error::ParsingError(e, loc)) => {
-                        hook_error!(loc, "{}", e);
-                    }
+    ranges
+}
+
+fn print_mismatches(writer: &HookWriter, ranges: &[std::ops::Range<u32>]) {
+    println!(
+        "{}",
+        console::style(format!("Verify: {} differing ranges", ranges.len())).bold()
+    );
+
+    for range in ranges {
+        println!(
+            "  0x{:08x}..0x{:08x} (0x{:x} bytes)",
+            range.start,
+            range.end,
+            range.len()
+        );
 
-                    Err(e) => {
-                        return Err(e.into());
+        let mut written = false;
+        for (write_address, size, reason) in writer.writes() {
+            if write_address >= range.end
+                || write_address as u64 + size as u64 <= range.start as u64
+            {
+                continue;
+            }
+
+            written = true;
+            match reason {
+                HookWriteReason::Hook(kind, locations) => {
+                    println!("    {kind} hook writing 0x{size:x} bytes at 0x{write_address:08x}");
+                    for location in locations {
+                        println!("      from {location}");
                     }
                 }
+                _ => {
+                    println!("    write of 0x{size:x} bytes at 0x{write_address:08x} ({reason:?})")
+                }
             }
         }
+        if !written {
+            println!("    not touched by any hook");
+        }
+    }
+}
+
+/// Path next to `path` that an output is written to before it is renamed into place.
+fn tmp_path(path: impl AsRef<std::path::Path>) -> PathBuf {
+    let mut tmp = path.as_ref().as_os_str().to_owned();
+    tmp.push(".tmp");
+    tmp.into()
+}
+
+/// Writes a file so that `path` only ever holds the complete previous or the complete new
+/// contents, even if the build is interrupted.
+fn write_atomic(path: impl AsRef<std::path::Path>, data: impl AsRef<[u8]>) -> std::io::Result<()> {
+    let tmp = tmp_path(&path);
+    std::fs::write(&tmp, data)?;
+    std::fs::rename(tmp, path)
+}
+
+/// Finds a hook that already wrote to the instruction at `address` as
+/// (write address, kind, location). A trampoline displacing the instruction would relocate
+/// the written one instead of the original.
+fn find_displaced_write(
+    writer: &HookWriter,
+    address: u32,
+) -> Option<(u32, &'static str, &HookLocation)> {
+    find_hook_write(writer, address..address + 4)
+}
+
+/// Finds the first hook that wrote to `range` as (write address, kind, location).
+fn find_hook_write(
+    writer: &HookWriter,
+    range: std::ops::Range<u32>,
+) -> Option<(u32, &'static str, &HookLocation)> {
+    range
+        .flat_map(|address| writer.writes_at(address))
+        .find_map(|(write_address, _, reason)| match reason {
+            HookWriteReason::Hook(kind, locations) => {
+                Some((write_address, *kind, locations.first()?))
+            }
+            _ => None,
+        })
+}
+
+/// Finds the location of a branch hook written to the instruction at `address`. It replaces
+/// the instruction like a pre/post hook at the same address would, so only one of them can
+/// be applied.
+fn find_branch_conflict(writer: &HookWriter, address: u32) -> Option<&HookLocation> {
+    writer
+        .writes_at(address)
+        .find_map(|(write_address, _, reason)| match reason {
+            HookWriteReason::Hook("branch", locations) if write_address == address => {
+                locations.first()
+            }
+            _ => None,
+        })
+}
+
+fn describe_write(reason: &HookWriteReason) -> String {
+    match reason {
+        HookWriteReason::Hook(kind, locations) => format!(
+            "{kind} hook at {}",
+            locations
+                .iter()
+                .map(|location| location.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        _ => "code placed by magwi".to_string(),
+    }
+}
 
-        linker_file.write(
+/// Lists every overlapping write with the writes it overlaps, one per line.
+fn format_overlaps(overlaps: &[hook::HookOverlap]) -> String {
+    overlaps
+        .iter()
+        .map(|overlap| {
             format!(
-                "\n    .mw_loader_text 0x{:x} : {{ *(.mw_loader_text); *(.mw_loader_text.*); }}\n",
-                self.loader_address
+                "  0x{:08x}..0x{:08x}: {} overlaps {}",
+                overlap.address,
+                overlap.address + overlap.size,
+                describe_write(&overlap.reason),
+                overlap
+                    .earlier
+                    .iter()
+                    .map(|(_, _, reason)| describe_write(reason))
+                    .collect::<Vec<_>>()
+                    .join(" and ")
             )
-            .as_bytes(),
-        )?;
-        linker_file.write(format!("    .text 0x{:x} :\n", self.custom_text_address).as_bytes())?;
-        linker_file.write(LINKER_SCRIPT_SECTIONS.as_bytes())?;
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Whether an object with the given (kind, size) sections contributes neither code nor data,
+/// e.g. because its whole source is disabled by `#ifdef`.
+fn is_empty_object(sections: impl IntoIterator<Item = (SectionKind, u64)>) -> bool {
+    !sections.into_iter().any(|(kind, size)| {
+        size > 0
+            && matches!(
+                kind,
+                SectionKind::Text
+                    | SectionKind::Data
+                    | SectionKind::ReadOnlyData
+                    | SectionKind::ReadOnlyDataWithRel
+                    | SectionKind::ReadOnlyString
+                    | SectionKind::UninitializedData
+                    | SectionKind::Tls
+                    | SectionKind::UninitializedTls
+            )
+    })
+}
+
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum PlanError {
+    #[error("Pre/post hooks for 0x{0:x} are in different sections")]
+    PrePostSections(u32),
+
+    #[error("Multiple detour hooks for 0x{0:x}, the other one is at {1}")]
+    MultipleDetours(u32, HookLocation),
+
+    #[error("Hooked address 0x{0:x} is not word aligned, ARM instructions are 4 byte aligned")]
+    Unaligned(u32),
+
+    #[error("Symbol \"{0}\" not found")]
+    UnknownSymbol(String),
+
+    #[error(transparent)]
+    Layout(#[from] layout::LayoutError),
+
+    #[error(transparent)]
+    Encoding(#[from] hook::EncodingError),
+}
+
+/// A hook write, applied to the code on commit.
+#[derive(Debug, PartialEq)]
+struct PlannedWrite {
+    address: u32,
+    bytes: Vec<u8>,
+    kind: &'static str,
+    locations: Vec<HookLocation>,
+}
 
-        linker_file.write("}\n".as_bytes()).unwrap();
+/// Pre/post calls of one hooked address, placed in a single trampoline.
+#[derive(Debug, PartialEq)]
+struct PrePostEntry {
+    extra_pos: HookExtraPos,
+    /// Calls as (priority, destination, location)
+    pre: Vec<(i32, u32, HookLocation)>,
+    post: Vec<(i32, u32, HookLocation)>,
+}
+
+/// Detour hook of one hooked address and the pointers to its trampoline.
+#[derive(Debug, PartialEq, Default)]
+struct DetourEntry {
+    hook: Option<(u32, HookLocation)>,
+    originals: Vec<(u32, HookLocation)>,
+}
+
+/// Everything the hooks of a target write, gathered before anything is applied. The writes
+/// are committed first, the trampolines of pre/post and detour hooks by hooked address after
+/// the custom code is in place.
+///
+/// Besides the hks files and symbol hooks, hooks can be added from code with
+/// [`HookPlan::add_branch`], [`HookPlan::add_patch`] and [`HookPlan::add_symptr`], which go
+/// through the same range checks, overlaps are reported on commit.
+#[derive(Debug, Default)]
+pub struct HookPlan {
+    writes: Vec<PlannedWrite>,
+    pre_post: HashMap<u32, PrePostEntry>,
+    detours: HashMap<u32, DetourEntry>,
+}
+
+impl HookPlan {
+    fn write(
+        &mut self,
+        address: u32,
+        bytes: impl AsRef<[u8]>,
+        kind: &'static str,
+        locations: Vec<HookLocation>,
+    ) {
+        self.writes.push(PlannedWrite {
+            address,
+            bytes: bytes.as_ref().to_vec(),
+            kind,
+            locations,
+        });
+    }
 
+    /// Checks that a write of `size` bytes at `address` stays inside the code segments.
+    fn check_write(
+        layout: &layout::Layout,
+        address: u32,
+        size: usize,
+    ) -> std::result::Result<(), PlanError> {
+        let segment = layout.check_write(address, size as u32)?;
+        log::trace!("planned write 0x{:x} in {}", address, segment);
         Ok(())
     }
 
-    fn link(&self) -> MakeResult<()> {
-        let mut output = Command::new("arm-none-eabi-g++")
-            .current_dir(&self.project_path)
-            .args(vec![
-                "-nodefaultlibs",
-                "-nostartfiles",
-                "-march=armv6k+fp",
-                "-mtune=mpcore",
-                "-mfloat-abi=hard",
-                "-mtp=soft",
-                "-T",
-                "symbols.ld",
-                "-T",
-                "build/linker.ld",
-                "-Wl,-Map=build/out.map",
-                "-fdiagnostics-color",
-            ])
-            .args(self.jobs.iter().map(|job| &job.obj_path))
-            .arg("-o")
-            .arg("build/out.elf")
-            .output()?;
+    /// Adds a branch at `from_address` to `to_address`, a BL(X) if `link` is set.
+    pub fn add_branch(
+        &mut self,
+        layout: &layout::Layout,
+        from_address: u32,
+        to_address: u32,
+        link: bool,
+        condition: hook::arm::ArmCondition,
+        location: HookLocation,
+    ) -> std::result::Result<(), PlanError> {
+        Self::check_write(layout, from_address, 4)?;
+        let branch = hook::arm::make_branch_u32(link, from_address, to_address, condition)?;
+        self.write(from_address, branch.to_le_bytes(), "branch", vec![location]);
+        Ok(())
+    }
 
-        let err = String::from_utf8_lossy(&output.stderr);
-        if !err.is_empty() {
-            println!("{}", err);
-        }
-        if !output.status.success() {
-            return Err(MakeError::LinkingFailed);
-        }
+    /// Adds a write of `bytes` at `address`.
+    pub fn add_patch(
+        &mut self,
+        layout: &layout::Layout,
+        address: u32,
+        bytes: impl AsRef<[u8]>,
+        location: HookLocation,
+    ) -> std::result::Result<(), PlanError> {
+        Self::check_write(layout, address, bytes.as_ref().len())?;
+        self.write(address, bytes, "patch", vec![location]);
+        Ok(())
+    }
 
+    /// Adds a pointer to `symbol`, looked up in `symtab_index`, at `address`.
+    pub fn add_symptr(
+        &mut self,
+        layout: &layout::Layout,
+        address: u32,
+        symbol: &str,
+        symtab_index: &HashMap<String, u32>,
+        location: HookLocation,
+    ) -> std::result::Result<(), PlanError> {
+        let symbol_address = symtab_index
+            .get(symbol)
+            .ok_or_else(|| PlanError::UnknownSymbol(symbol.to_string()))?;
+        Self::check_write(layout, address, 4)?;
+        self.write(
+            address,
+            symbol_address.to_le_bytes(),
+            "symptr",
+            vec![location],
+        );
         Ok(())
     }
 
-    fn sym_hooks(&mut self) -> MakeResult<()> {
-        let elf_data = std::fs::read("build/out.elf")?;
-        let elf_file = object::File::parse(elf_data.as_slice())?;
+    /// Adds a call given as (priority, destination, location) before or after the instruction
+    /// at `from_address`.
+    fn add_pre_post(
+        &mut self,
+        from_address: u32,
+        extra_pos: HookExtraPos,
+        pre: bool,
+        call: (i32, u32, HookLocation),
+    ) -> std::result::Result<(), PlanError> {
+        if !from_address.is_multiple_of(4) {
+            return Err(PlanError::Unaligned(from_address));
+        }
 
-        let Some(symtab) = elf_file.symbol_table() else {
-            return Ok(());
-        };
+        let entry = self
+            .pre_post
+            .entry(from_address)
+            .or_insert_with(|| PrePostEntry {
+                extra_pos,
+                pre: Vec::new(),
+                post: Vec::new(),
+            });
 
-        for sym in symtab.symbols() {
-            let Ok(name) = sym.name() else {
-                continue;
-            };
+        if extra_pos != entry.extra_pos {
+            return Err(PlanError::PrePostSections(from_address));
+        }
 
-            let address = sym.address() as u32;
+        match pre {
+            true => entry.pre.push(call),
+            false => entry.post.push(call),
+        }
+        Ok(())
+    }
 
-            self.symtab_index.insert(name.into(), address);
-            if let Ok(demangled_sym) = cpp_demangle::Symbol::new(name) {
-                self.symtab_index.insert(demangled_sym.to_string(), address);
-            }
+    fn add_detour(
+        &mut self,
+        from_address: u32,
+        hook_address: u32,
+        location: HookLocation,
+    ) -> std::result::Result<(), PlanError> {
+        if !from_address.is_multiple_of(4) {
+            return Err(PlanError::Unaligned(from_address));
         }
 
+        let entry = self.detours.entry(from_address).or_default();
+        if let Some((_, other)) = &entry.hook {
+            return Err(PlanError::MultipleDetours(from_address, other.clone()));
+        }
+        entry.hook = Some((hook_address, location));
         Ok(())
     }
 
-    fn patch_exheader(&mut self) -> MakeResult<()> {
-        self.exheader.info.sci.text_section.size =
-            self.exheader.info.sci.text_section.num_pages * exheader::PAGE_SIZE;
-        self.exheader.info.sci.data_section.size =
-            self.writer.end_address() - self.exheader.info.sci.data_section.address;
-        self.exheader.info.sci.data_section.num_pages =
-            exheader::page_count(self.exheader.info.sci.data_section.size);
-        self.exheader.info.sci.bss_size = 0;
+    fn add_detour_original(
+        &mut self,
+        from_address: u32,
+        pointer_address: u32,
+        location: HookLocation,
+    ) {
+        self.detours
+            .entry(from_address)
+            .or_default()
+            .originals
+            .push((pointer_address, location));
+    }
+}
+
+/// Applies the planned writes in order. Writes outside the code fail with the location of
+/// their hook, overlaps are recorded by the writer.
+fn commit_writes(writer: &mut HookWriter, writes: &[PlannedWrite]) -> MakeResult<()> {
+    for write in writes {
+        writer
+            .write_with_reason(
+                write.address,
+                &write.bytes,
+                HookWriteReason::Hook(write.kind, write.locations.clone()),
+            )
+            .map_err(|e| match write.locations.first() {
+                Some(location) => hook_err!(location, "{}", e),
+                None => e.into(),
+            })?;
+    }
+    Ok(())
+}
 
-        std::fs::OpenOptions::new()
-            .create(true)
-            .write(true)
-            .open("build/exheader.bin")?
-            .write_le(&self.exheader)?;
+/// Orders the calls of a pre/post trampoline given as (priority, destination, location).
+/// Lower priorities run first, equal ones by file and line, so the order doesn't depend on
+/// the order the hooks were found in.
+fn sort_pre_post_calls(calls: &mut [(i32, u32, HookLocation)]) {
+    calls.sort_by(|(a_priority, _, a), (b_priority, _, b)| {
+        (a_priority, &a.file, a.line).cmp(&(b_priority, &b.file, b.line))
+    });
+}
 
-        Ok(())
+fn strip_priorities(calls: &[(i32, u32, HookLocation)]) -> Vec<(u32, HookLocation)> {
+    calls
+        .iter()
+        .map(|(_, dest_addr, location)| (*dest_addr, location.clone()))
+        .collect()
+}
+
+/// Writes the branch at `from_address` to the trampoline in `extra_writer`, which calls the
+/// pre hooks, runs the relocated original instruction, calls the post hooks and branches back.
+fn write_pre_post_trampoline(
+    writer: &mut HookWriter,
+    extra_writer: &mut HookWriter,
+    from_address: u32,
+    pre: &[(u32, HookLocation)],
+    post: &[(u32, HookLocation)],
+) -> MakeResult<()> {
+    let (_, first_location) = pre.iter().chain(post).next().unwrap();
+
+    let original_instruction = writer.read_u32(from_address).map_err(|e| {
+        hook_err!(
+            first_location,
+            "Reading the hooked instruction at 0x{:x} failed: {}",
+            from_address,
+            e
+        )
+    })?;
+
+    // Write jump to extra block
+    let locations = pre
+        .iter()
+        .chain(post)
+        .map(|(_, location)| location.clone())
+        .collect::<Vec<_>>();
+    let jump = hook::arm::make_branch_u32(
+        false,
+        from_address,
+        extra_writer.base_address(),
+        hook::arm::ArmCondition::AL,
+    )
+    .map_err(|_| {
+        hook_err!(
+            first_location,
+            "Trampoline at 0x{:x} is out of range from 0x{:x}",
+            extra_writer.base_address(),
+            from_address,
+        )
+    })?;
+    writer.write_with_reason(
+        from_address,
+        jump.to_le_bytes(),
+        HookWriteReason::Hook("pre/post", locations),
+    )?;
+
+    let write_calls = |extra_writer: &mut HookWriter, hooks: &[(u32, HookLocation)]| {
+        for (dest_addr, location) in hooks {
+            // push {r0-r12, lr}
+            let push = hook::arm::try_make_push_u32(0x5FFF, hook::arm::ArmCondition::AL)
+                .map_err(|e| hook_err!(location, "{}", e))?;
+            extra_writer.write_end(push.to_le_bytes())?;
+
+            let call = hook::arm::make_branch_u32(
+                true,
+                extra_writer.end_address(),
+                *dest_addr,
+                hook::arm::ArmCondition::AL,
+            )
+            .map_err(|e| hook_err!(location, "{}", e))?;
+            extra_writer.write_end(call.to_le_bytes())?;
+
+            // pop {r0-r12, lr}
+            let pop = hook::arm::try_make_pop_u32(0x5FFF, hook::arm::ArmCondition::AL)
+                .map_err(|e| hook_err!(location, "{}", e))?;
+            extra_writer.write_end(pop.to_le_bytes())?;
+        }
+        MakeResult::Ok(())
+    };
+
+    write_calls(extra_writer, pre)?;
+
+    // Write original instruction
+    let relocated_instruction = hook::arm::relocate_u32(
+        original_instruction,
+        from_address,
+        extra_writer.end_address(),
+    )
+    .map_err(|e| {
+        hook_err!(
+            first_location,
+            "Relocating original instruction at 0x{:x} failed: {}",
+            from_address,
+            e
+        )
+    })?;
+    extra_writer.write_end(relocated_instruction.to_le_bytes())?;
+
+    write_calls(extra_writer, post)?;
+
+    // Write jump back to original code
+    let jump_back = hook::arm::make_branch_u32(
+        false,
+        extra_writer.end_address(),
+        from_address + 4,
+        hook::arm::ArmCondition::AL,
+    )
+    .map_err(|_| {
+        hook_err!(
+            first_location,
+            "Trampoline at 0x{:x} is out of range from 0x{:x}",
+            extra_writer.base_address(),
+            from_address,
+        )
+    })?;
+    extra_writer.write_end(jump_back.to_le_bytes())?;
+
+    Ok(())
+}
+
+/// Trampolines of hooks on original code go to the loader, the others to the tail of the
+/// code, unless the hook forces a position.
+fn select_extra_pos(
+    address: u32,
+    custom_text_address: u32,
+    forced_pos: Option<HookExtraPos>,
+) -> HookExtraPos {
+    match forced_pos {
+        Some(pos) => pos,
+        None if address < custom_text_address => HookExtraPos::Loader,
+        None => HookExtraPos::Tail,
     }
 }
 
-const LINKER_SCRIPT_SECTIONS: &str = r#"    {
-    __mw_text_start = .;
-    *(.text);
-    *(.text.*);
-    *(.rodata);
-    *(.rodata.*);
-    __init_array_start = .;
-    *(.init_array);
-    *(.init_array.*);
-    __init_array_end = .;
-    __fini_array_start = .;
-    *(.fini_array);
-    *(.fini_array.*);
-    __fini_array_end = .;
-    *(.data);
-    *(.data.*);
-    *(.bss);
-    *(.bss.*);
-    __mw_text_end = .;
+/// Whether a variant leaves out the source of `job`, neither compiling nor linking it.
+fn is_variant_ignored(job: &Job, ignore: &IgnoreList) -> bool {
+    let path = job.src_path.strip_prefix("source").unwrap_or(&job.src_path);
+    ignore.is_file_ignored(path)
+}
+
+/// `path` with the variant name inserted before its extension, e.g. changes.eur.txt.
+fn variant_file_path(path: &std::path::Path, variant: &str) -> PathBuf {
+    let mut name = path.file_stem().unwrap_or_default().to_owned();
+    name.push(format!(".{variant}"));
+    if let Some(extension) = path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    path.with_file_name(name)
+}
+
+/// Directory of Luma3DS code patches for a title, relative to the SD card root.
+fn luma_exefs_dir(title_id: u64) -> PathBuf {
+    PathBuf::from(format!("luma/titles/{title_id:016X}/exefs"))
+}
+
+/// Where the original code and exheader are read from.
+#[derive(Debug, PartialEq)]
+enum OriginalFiles {
+    /// Already extracted code.bin and exheader.bin
+    Loose { code: PathBuf, exheader: PathBuf },
+    /// CXI containing both
+    Ncch(PathBuf),
+}
+
+impl OriginalFiles {
+    /// Prefers the loose files and falls back to the only .cxi or .app in `dir`.
+    fn find(dir: &std::path::Path) -> MakeResult<Self> {
+        let loose = Self::Loose {
+            code: dir.join("code.bin"),
+            exheader: dir.join("exheader.bin"),
+        };
+        if loose.paths().iter().all(|path| path.is_file()) {
+            return Ok(loose);
+        }
+
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(loose),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut ncch_paths = Vec::new();
+        for e in entries {
+            let path = e?.path();
+            let is_ncch = path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| {
+                    ext.eq_ignore_ascii_case("cxi") || ext.eq_ignore_ascii_case("app")
+                });
+            if is_ncch && path.is_file() {
+                ncch_paths.push(path);
+            }
+        }
+
+        match ncch_paths.len() {
+            0 => Ok(loose),
+            1 => Ok(Self::Ncch(ncch_paths.remove(0))),
+            _ => fatal_error!(
+                "{} contains multiple CXIs, keep only one or extract its code.bin and exheader.bin",
+                dir.display()
+            ),
+        }
+    }
+
+    /// Files the exheader is read from
+    fn exheader_path(&self) -> &std::path::Path {
+        match self {
+            Self::Loose { exheader, .. } => exheader,
+            Self::Ncch(path) => path,
+        }
+    }
+
+    /// Files the code is read from
+    fn code_path(&self) -> &std::path::Path {
+        match self {
+            Self::Loose { code, .. } => code,
+            Self::Ncch(path) => path,
+        }
+    }
+
+    fn paths(&self) -> Vec<PathBuf> {
+        match self {
+            Self::Loose { code, exheader } => vec![code.clone(), exheader.clone()],
+            Self::Ncch(path) => vec![path.clone()],
+        }
+    }
+
+    /// Reads the exheader and the decompressed code.
+    fn read(&self) -> MakeResult<(Vec<u8>, Exheader)> {
+        let read = |path: &PathBuf| {
+            std::fs::read(path)
+                .map_err(|e| MakeError::Fatal(format!("Reading {} failed: {}", path.display(), e)))
+        };
+
+        match self {
+            Self::Loose { code, exheader } => {
+                let exheader: Exheader = std::io::Cursor::new(read(exheader)?).read_ne()?;
+                Ok((read(code)?, exheader))
+            }
+            Self::Ncch(path) => {
+                let ncch_error = |e: String| {
+                    MakeError::Fatal(format!("Reading {} failed: {}", path.display(), e))
+                };
+                let ncch = Ncch::parse(&read(path)?).map_err(|e| ncch_error(e.to_string()))?;
+                let exheader: Exheader = std::io::Cursor::new(&ncch.exheader).read_ne()?;
+
+                let code = ncch
+                    .exefs
+                    .get(".code")
+                    .map_err(|e| ncch_error(e.to_string()))?;
+                let code = if exheader.info.sci.flags[5] & exheader::FLAG_COMPRESS_CODE != 0 {
+                    lzss::decompress(code).map_err(|e| ncch_error(e.to_string()))?
+                } else {
+                    code.to_vec()
+                };
+
+                Ok((code, exheader))
+            }
+        }
+    }
+}
+
+/// hks file applied before all others, for the patches every build shares
+const BASE_HKS_NAME: &str = "base.hks";
+
+/// Finds the hks files in `dir` and its subdirectories in the order they are applied:
+/// base.hks of `dir` first, then all others sorted by path. A file can only override what an
+/// earlier one wrote with `--allow-overlaps`, otherwise the overlap is an error.
+fn find_hks_files(dir: impl AsRef<std::path::Path>) -> std::io::Result<Vec<PathBuf>> {
+    let dir = dir.as_ref();
+    let mut paths = Vec::new();
+
+    match find_hks_files_in(dir, &mut paths) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(paths),
+        result => result?,
+    }
+
+    paths.sort();
+    let base_path = dir.join(BASE_HKS_NAME);
+    if let Some(index) = paths.iter().position(|path| *path == base_path) {
+        let base = paths.remove(index);
+        paths.insert(0, base);
+    }
+
+    Ok(paths)
+}
+
+fn find_hks_files_in(dir: &std::path::Path, paths: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for e in std::fs::read_dir(dir)? {
+        let e = e?;
+
+        // Editor scratch files and directories like .git
+        if e.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+
+        let file_type = e.file_type()?;
+        if file_type.is_dir() {
+            find_hks_files_in(&e.path(), paths)?;
+            continue;
+        }
+
+        if !file_type.is_file() {
+            continue;
+        }
+
+        if e.path().extension() != Some(std::ffi::OsStr::new("hks")) {
+            continue;
+        }
+
+        paths.push(e.path());
+    }
+
+    Ok(())
+}
+
+fn project_path(args: &Args) -> PathBuf {
+    match args.project_path.clone() {
+        Some(path) => path,
+        None => std::env::current_dir().expect("Failed to get current directory"),
+    }
+}
+
+/// Makes the project directory the working directory, which relative paths are resolved in.
+fn enter_project_dir(project_path: &std::path::Path) -> MakeResult<()> {
+    std::env::set_current_dir(project_path).map_err(|e| {
+        MakeError::Fatal(format!(
+            "Entering project directory {} failed: {}",
+            project_path.display(),
+            e
+        ))
+    })
+}
+
+/// Prints the symbols of the original code merged with the custom symbols of the last build
+/// of every selected target, so any address a hook can use is listed in one place.
+pub fn list_symbols(args: &Args) -> MakeResult<()> {
+    let project_path = project_path(args);
+    let build_dir = project_path.join(args.build_dir());
+
+    let config = config::Config::load(&project_path)?;
+    let variants = config.select_variants(&args.variants, args.all_variants)?;
+
+    let targets = match variants.is_empty() {
+        true => vec![(None, PathBuf::from("symbols.ld"), build_dir)],
+        false => variants
+            .iter()
+            .map(|variant| {
+                (
+                    Some(variant.name.as_str()),
+                    variant.symbols_path.clone(),
+                    build_dir.join(&variant.name),
+                )
+            })
+            .collect(),
+    };
+
+    for (variant_name, symbols_path, build_dir) in targets {
+        if let Some(name) = variant_name {
+            println!("{}", console::style(format!("Variant {name}:")).bold());
+        }
+
+        let script = std::fs::read_to_string(project_path.join(&symbols_path)).map_err(|e| {
+            MakeError::Fatal(format!("Reading {} failed: {}", symbols_path.display(), e))
+        })?;
+
+        // Written by every build, missing before the first one
+        let sym_path = build_dir.join("out.sym");
+        let custom = match std::fs::read_to_string(&sym_path) {
+            Ok(s) => symbol_map::parse_sym(&s),
+            Err(_) => {
+                log::warn!(
+                    "{} not found, build first to list custom symbols",
+                    sym_path.display()
+                );
+                Vec::new()
+            }
+        };
+
+        let symbols = symbol_map::merge_symbols(symbol_map::parse_linker_symbols(&script), custom);
+        for (address, name, origin) in symbols {
+            println!("0x{address:08x}  {origin:<8}  {name}");
+        }
+    }
+
+    Ok(())
+}
+
+/// Describes the jobs as JSON, with the total count and the sources that would be rebuilt.
+/// Jobs not selected by `--only` are listed but not rebuilt.
+fn jobs_to_json(jobs: &[Job], selected: impl Fn(&Job) -> bool) -> Json {
+    let path = |path: &std::path::Path| Json::from(path.to_string_lossy().into_owned());
+    let rebuild = jobs
+        .iter()
+        .filter(|job| job.build_required() && selected(job))
+        .collect::<Vec<_>>();
+
+    Json::object([
+        ("total", Json::from(jobs.len())),
+        ("rebuild_count", Json::from(rebuild.len())),
+        (
+            "rebuild",
+            Json::array(rebuild.iter().map(|job| path(&job.src_path))),
+        ),
+        (
+            "jobs",
+            Json::array(jobs.iter().map(|job| {
+                Json::object([
+                    ("kind", Json::from(job.kind.name())),
+                    ("src", path(&job.src_path)),
+                    ("obj", path(&job.obj_path)),
+                    ("dep", path(&job.dep_path)),
+                    (
+                        "build_reason",
+                        job.build_reason
+                            .map_or(Json::Null, |reason| Json::from(reason.name())),
+                    ),
+                    ("selected", Json::from(selected(job))),
+                ])
+            })),
+        ),
+    ])
+}
+
+/// Prints the jobs a build of the project would run and why, without building anything.
+pub fn list_jobs(args: &Args) -> MakeResult<()> {
+    let project_path = project_path(args);
+    enter_project_dir(&project_path)?;
+
+    let config = config::Config::load(&project_path)?;
+    let variants = config.select_variants(&args.variants, args.all_variants)?;
+    let env = config::EnvConfig::from_env()?;
+    let job_env = job_env(&project_path, &Toolchain::resolve(), &config, &env, args);
+
+    let jobs = resolve_jobs(args.build_dir(), &config, &variants, &job_env, args)?;
+    println!("{}", jobs_to_json(&jobs, only_selected(args)));
+
+    Ok(())
+}
+
+/// Where a value printed by `--print-config` comes from.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum ConfigSource {
+    Default,
+    File,
+    Env,
+    Flag,
+}
+
+impl ConfigSource {
+    fn name(self) -> &'static str {
+        match self {
+            ConfigSource::Default => "default",
+            ConfigSource::File => "file",
+            ConfigSource::Env => "env",
+            ConfigSource::Flag => "flag",
+        }
+    }
+
+    /// Source of a value set by any of the magwi.toml `keys`, given as `table.key`.
+    fn file(config: &config::Config, keys: &[&str]) -> Self {
+        match keys.iter().any(|key| config.is_set(key)) {
+            true => ConfigSource::File,
+            false => ConfigSource::Default,
+        }
+    }
+
+    /// Source of a value set by a command line option if `set`.
+    fn flag(set: bool) -> Self {
+        match set {
+            true => ConfigSource::Flag,
+            false => ConfigSource::Default,
+        }
+    }
+}
+
+/// A table of the effective configuration, its keys with value and source.
+type ConfigTable = (String, Vec<(&'static str, String, ConfigSource)>);
+
+fn toml_string(s: impl AsRef<str>) -> String {
+    format!("{:?}", s.as_ref())
+}
+
+fn toml_array<S: AsRef<str>>(values: impl IntoIterator<Item = S>) -> String {
+    let values = values.into_iter().map(toml_string).collect::<Vec<_>>();
+    format!("[{}]", values.join(", "))
+}
+
+/// Collects what the build would use after merging the defaults, magwi.toml, the
+/// environment and the command line.
+fn effective_config(
+    project_path: &std::path::Path,
+    toolchain: &Toolchain,
+    config: &config::Config,
+    env: &config::EnvConfig,
+    args: &Args,
+    num_cpus: usize,
+) -> Vec<ConfigTable> {
+    let job_env = job_env(project_path, toolchain, config, env, args);
+    let kinds = [
+        ("c", JobKind::C),
+        ("cpp", JobKind::CPP),
+        ("asm", JobKind::ASM),
+    ];
+
+    let toolchain_source = match toolchain.bin_dir {
+        Some(_) => ConfigSource::Env,
+        None => ConfigSource::Default,
+    };
+    let compiler = kinds
+        .iter()
+        .map(|(name, kind)| {
+            let path = job_env.compiler[*kind].to_string_lossy().into_owned();
+            let env_compiler = match kind {
+                JobKind::CPP => &env.cxx,
+                _ => &env.cc,
+            };
+            let source = match env_compiler {
+                Some(_) => ConfigSource::Env,
+                None => toolchain_source,
+            };
+            (*name, toml_string(path), source)
+        })
+        .collect();
+
+    // The include directories are the same for every kind, they are listed on their own
+    let mut quote_dirs = Vec::new();
+    let mut system_dirs = Vec::new();
+    let flags = kinds
+        .iter()
+        .map(|(name, kind)| {
+            let mut flags = Vec::new();
+            let mut iter = job_env.flags[*kind].iter();
+            while let Some(flag) = iter.next() {
+                match *flag {
+                    "-iquote" | "-isystem" => {
+                        let dirs = match *flag {
+                            "-iquote" => &mut quote_dirs,
+                            _ => &mut system_dirs,
+                        };
+                        let dir = iter.next().copied().unwrap_or_default();
+                        if !dirs.contains(&dir) {
+                            dirs.push(dir);
+                        }
+                    }
+                    flag => flags.push(flag),
+                }
+            }
+            flags.extend(job_env.env_flags[*kind].iter().map(String::as_str));
+
+            // Named after the last contribution, the environment flags are appended after -g
+            let source = match (job_env.env_flags[*kind].is_empty(), args.profile) {
+                (false, _) => ConfigSource::Env,
+                (true, Profile::Debug) => ConfigSource::Flag,
+                (true, _) => ConfigSource::Default,
+            };
+            (*name, toml_array(flags), source)
+        })
+        .collect();
+
+    let cpu = &config.cpu;
+    let hooks = &config.hooks;
+
+    let mut tables = vec![
+        ("compiler".to_string(), compiler),
+        ("flags".to_string(), flags),
+        (
+            "include".to_string(),
+            vec![
+                ("quote", toml_array(quote_dirs), ConfigSource::Default),
+                ("system", toml_array(system_dirs), ConfigSource::Default),
+            ],
+        ),
+        (
+            "defines".to_string(),
+            vec![(
+                "filename_define",
+                toml_string(&hooks.filename_define),
+                ConfigSource::file(config, &["hooks.filename_define"]),
+            )],
+        ),
+        (
+            "cpu".to_string(),
+            vec![
+                (
+                    "march",
+                    toml_string(&cpu.march),
+                    ConfigSource::file(config, &["cpu.march"]),
+                ),
+                (
+                    "mtune",
+                    toml_string(&cpu.mtune),
+                    ConfigSource::file(config, &["cpu.mtune"]),
+                ),
+                (
+                    "mfloat_abi",
+                    toml_string(&cpu.mfloat_abi),
+                    ConfigSource::file(config, &["cpu.mfloat_abi"]),
+                ),
+                (
+                    "mtp",
+                    toml_string(&cpu.mtp),
+                    ConfigSource::file(config, &["cpu.mtp"]),
+                ),
+            ],
+        ),
+        (
+            "link".to_string(),
+            vec![
+                (
+                    "flags",
+                    toml_array(link_flags(cpu)),
+                    ConfigSource::file(
+                        config,
+                        &["cpu.march", "cpu.mtune", "cpu.mfloat_abi", "cpu.mtp"],
+                    ),
+                ),
+                (
+                    "scripts",
+                    toml_array(&config.link_scripts),
+                    ConfigSource::file(config, &["link.scripts"]),
+                ),
+            ],
+        ),
+        (
+            "hooks".to_string(),
+            vec![
+                (
+                    "symbol_prefix",
+                    toml_string(&hooks.prefix.symbol),
+                    ConfigSource::file(config, &["hooks.symbol_prefix"]),
+                ),
+                (
+                    "section_prefix",
+                    toml_string(&hooks.prefix.section),
+                    ConfigSource::file(config, &["hooks.section_prefix"]),
+                ),
+            ],
+        ),
+        (
+            "directories".to_string(),
+            vec![
+                (
+                    "project",
+                    toml_string(project_path.to_string_lossy()),
+                    ConfigSource::flag(args.project_path.is_some()),
+                ),
+                ("source", toml_string("source"), ConfigSource::Default),
+                (
+                    "build",
+                    toml_string(args.build_dir().to_string_lossy()),
+                    ConfigSource::flag(args.build_dir.is_some()),
+                ),
+            ],
+        ),
+        (
+            "source".to_string(),
+            vec![
+                (
+                    "ignore",
+                    toml_array(&config.source_ignore),
+                    ConfigSource::file(config, &["source.ignore"]),
+                ),
+                (
+                    "thumb",
+                    toml_array(&config.source_thumb),
+                    ConfigSource::file(config, &["source.thumb"]),
+                ),
+            ],
+        ),
+        (
+            "jobs".to_string(),
+            vec![match env.jobs {
+                Some(jobs) => ("workers", jobs.to_string(), ConfigSource::Env),
+                None => ("workers", num_cpus.to_string(), ConfigSource::Default),
+            }],
+        ),
+    ];
+
+    for variant in &config.variants {
+        tables.push((
+            format!("variants.{}", variant.name),
+            vec![
+                (
+                    "original",
+                    toml_string(variant.original_dir.to_string_lossy()),
+                    ConfigSource::File,
+                ),
+                (
+                    "symbols",
+                    toml_string(variant.symbols_path.to_string_lossy()),
+                    ConfigSource::File,
+                ),
+            ],
+        ));
+    }
+
+    tables
+}
+
+/// Formats the tables as TOML, every value is followed by a comment naming its source.
+fn format_config(tables: &[ConfigTable]) -> String {
+    let mut out = String::new();
+    for (i, (name, values)) in tables.iter().enumerate() {
+        if i != 0 {
+            out.push('\n');
+        }
+        out.push_str(&format!("[{name}]\n"));
+        for (key, value, source) in values {
+            out.push_str(&format!("{key} = {value}  # {}\n", source.name()));
+        }
+    }
+    out
+}
+
+/// Prints the configuration a build of the project would use, see `effective_config`.
+pub fn print_config(args: &Args) -> MakeResult<()> {
+    let project_path = project_path(args);
+    let config = config::Config::load(&project_path)?;
+    let env = config::EnvConfig::from_env()?;
+    let toolchain = Toolchain::resolve();
+
+    let tables = effective_config(
+        &project_path,
+        &toolchain,
+        &config,
+        &env,
+        args,
+        num_cpus::get(),
+    );
+    print!("{}", format_config(&tables));
+
+    Ok(())
+}
+
+/// Whether `--only` selects a job. It uses the glob syntax of source_ignore, relative to the
+/// source directory.
+fn only_selected(args: &Args) -> impl Fn(&Job) -> bool + '_ {
+    let only = IgnoreList::new(&args.only);
+    move |job| {
+        args.only.is_empty()
+            || job
+                .src_path
+                .strip_prefix("source")
+                .is_ok_and(|path| only.is_ignored(path, false))
+    }
+}
+
+/// Files in the build directory recording the profile and the Thumb sources of the objects
+const PROFILE_STAMP: &str = "profile";
+const THUMB_STAMP: &str = "thumb";
+
+/// Finds the jobs of the selected variants and why each of them has to be built, if at all.
+/// Expects the project directory to be the current directory.
+fn resolve_jobs(
+    build_dir: &std::path::Path,
+    config: &config::Config,
+    variants: &[&config::Variant],
+    job_env: &JobEnv,
+    args: &Args,
+) -> MakeResult<Vec<Job>> {
+    let ignore = IgnoreList::new(&config.source_ignore);
+    let mut jobs = find_jobs(
+        "source",
+        build_dir.join("obj"),
+        build_dir.join("dep"),
+        true,
+        &ignore,
+    )?;
+
+    // Sources ignored by every selected variant aren't compiled at all
+    let variant_ignores = variants
+        .iter()
+        .map(|variant| IgnoreList::new(&variant.ignore))
+        .collect::<Vec<_>>();
+    if !variant_ignores.is_empty() {
+        jobs.retain(|job| {
+            variant_ignores
+                .iter()
+                .any(|ignore| !is_variant_ignored(job, ignore))
+        });
+    }
+
+    log::info!("found {} jobs", jobs.len());
+
+    jobs.iter_mut().for_each(|job| {
+        job.update_build_reason(&job_env.flags_hash(job));
+        log::debug!("{}: build reason {:?}", job.src_path.display(), job.build_reason);
+    });
+
+    // Objects built with different flags can't be reused
+    let built_profile = std::fs::read_to_string(build_dir.join(PROFILE_STAMP)).ok();
+    if built_profile.as_deref().unwrap_or(Profile::Default.name()) != args.profile.name() {
+        log::info!("profile changed to {}, rebuilding all", args.profile.name());
+        for job in &mut jobs {
+            job.build_reason = Some(BuildReason::Forced);
+        }
+    }
+
+    // Sources switching between ARM and Thumb can't be reused either
+    let built_thumb = std::fs::read_to_string(build_dir.join(THUMB_STAMP))
+        .map(|s| s.lines().map(str::to_string).collect::<Vec<_>>())
+        .unwrap_or_default();
+    if built_thumb != config.source_thumb {
+        let built_thumb = IgnoreList::new(&built_thumb);
+        for job in &mut jobs {
+            let path = job.src_path.strip_prefix("source").unwrap_or(&job.src_path);
+            if built_thumb.is_ignored(path, false) != job_env.thumb.is_ignored(path, false) {
+                job.build_reason = Some(BuildReason::Forced);
+            }
+        }
+    }
+
+    // A check writes no objects, so up to date sources are checked as well
+    if args.check {
+        let selected = only_selected(args);
+        for job in jobs.iter_mut().filter(|job| selected(job)) {
+            job.build_reason = Some(BuildReason::Forced);
+        }
+    }
+
+    Ok(jobs)
+}
+
+/// Compilers and flags of the jobs. The compilers and flags of the environment take
+/// precedence over the built-in ones.
+fn job_env(
+    project_path: &std::path::Path,
+    toolchain: &Toolchain,
+    config: &config::Config,
+    env: &config::EnvConfig,
+    args: &Args,
+) -> JobEnv<'static> {
+    let cc = env
+        .cc
+        .clone()
+        .unwrap_or_else(|| toolchain.tool("arm-none-eabi-gcc"));
+    let cxx = env
+        .cxx
+        .clone()
+        .unwrap_or_else(|| toolchain.tool("arm-none-eabi-g++"));
+
+    let mut job_env = JobEnv {
+        cwd: project_path.to_path_buf(),
+        compiler: enum_map! {
+            JobKind::C   => cc.clone(),
+            JobKind::CPP => cxx.clone(),
+            JobKind::ASM => cc.clone(),
+        },
+        flags: enum_map! {
+            JobKind::C   => vec![
+                "-iquote", "include", "-isystem", "include/sys", "-isystem", "include/sys/clib",
+                "-fdiagnostics-color", "-Wall", "-O3", "-mword-relocations", "-fshort-wchar", "-fomit-frame-pointer", "-ffunction-sections", "-nostdinc"
+            ],
+            JobKind::CPP => vec![
+                "-iquote", "include", "-isystem", "include/sys", "-isystem", "include/sys/clib",
+                "-fdiagnostics-color", "-Wall", "-O3", "-mword-relocations", "-fshort-wchar", "-fomit-frame-pointer", "-ffunction-sections", "-nostdinc",
+                "-fno-exceptions", "-fno-rtti"
+            ],
+            JobKind::ASM => vec![
+                "-iquote", "include", "-isystem", "include/sys", "-isystem", "include/sys/clib",
+                "-fdiagnostics-color", "-x", "assembler-with-cpp"
+            ],
+        },
+        cpu_flags: config.cpu.flags(),
+        env_flags: env.flags.clone(),
+        filename_define: config.hooks.filename_define.clone(),
+        syntax_only: args.check,
+        thumb: IgnoreList::new(&config.source_thumb),
+    };
+
+    if args.profile == Profile::Debug {
+        for flags in job_env.flags.values_mut() {
+            flags.push("-g");
+        }
+    }
+
+    job_env
+}
+
+/// Flags of the link, the CPU flags have to match the ones the objects were compiled with.
+fn link_flags(cpu: &config::CpuConfig) -> Vec<String> {
+    let mut flags = vec!["-nodefaultlibs".to_string(), "-nostartfiles".to_string()];
+    flags.extend(cpu.flags());
+    flags.push("-fdiagnostics-color".to_string());
+    flags
+}
+
+/// Compiles the project and links and patches every selected target.
+/// Returns the layouts of the targets that were linked.
+pub fn make(
+    args: &Args,
+    observer: std::sync::Arc<dyn BuildObserver>,
+) -> MakeResult<Vec<LayoutSummary>> {
+    let project_path = project_path(args);
+    // Relative to the directory magwi was started in, not the project directory
+    let verify_reference = args.verify_reference.as_ref().map(|path| {
+        std::env::current_dir()
+            .expect("Failed to get current directory")
+            .join(path)
+    });
+    enter_project_dir(&project_path)?;
+
+    // Relative to the project directory unless absolute
+    let build_dir = args.build_dir().to_path_buf();
+    std::fs::create_dir_all(&build_dir).map_err(|e| {
+        MakeError::Fatal(format!(
+            "Creating build directory {} failed: {}",
+            build_dir.display(),
+            e
+        ))
+    })?;
+
+    let config = config::Config::load(&project_path)?;
+    let variants = config.select_variants(&args.variants, args.all_variants)?;
+
+    let env = config::EnvConfig::from_env()?;
+    let toolchain = Toolchain::resolve();
+    output::status!("Toolchain: {toolchain}");
+
+    let job_env = std::sync::Arc::from(job_env(&project_path, &toolchain, &config, &env, args));
+
+    let mut timings = Timings::default();
+    start_phase(&mut timings, &*observer, "dependency scan");
+
+    let jobs = resolve_jobs(&build_dir, &config, &variants, &job_env, args)?;
+    let selected = only_selected(args);
+
+    let todo_jobs: Vec<&Job> = jobs
+        .iter()
+        .filter(|job| job.build_required() && selected(job))
+        .collect();
+    let any_rebuilt = !todo_jobs.is_empty();
+
+    let skipped_jobs = jobs
+        .iter()
+        .filter(|job| job.build_required() && !selected(job))
+        .count();
+    if skipped_jobs > 0 && !args.check {
+        observer.on_warning(&format!(
+            "{} out of date sources don't match --only, their previous objects are linked",
+            skipped_jobs
+        ));
+    }
+
+    match args.check {
+        true => print_step(1, "Checking..."),
+        false => print_step(1, "Compiling..."),
+    }
+    start_phase(&mut timings, &*observer, "compile");
+    observer.on_progress_start(todo_jobs.len());
+
+    let num_workers = env.jobs.unwrap_or_else(num_cpus::get);
+    let mut pool = WorkerPool::new(num_workers);
+    let job_durations = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+
+    for job in todo_jobs {
+        // a bit wasteful to clone these, but oh well
+        let job = job.clone();
+        let job_env = job_env.clone();
+        let job_durations = job_durations.clone();
+        let observer = observer.clone();
+
+        let submitted = pool.submit_task(move |_| {
+            // Terminates the pool, queued jobs are dropped
+            if interrupt::interrupted() {
+                return TaskResult::Terminate;
+            }
+
+            let start = std::time::Instant::now();
+            match run_compile_job(&job_env, &job, &*observer) {
+                Ok(_) => {
+                    job_durations
+                        .lock()
+                        .unwrap()
+                        .push((job.src_path.clone(), start.elapsed()));
+                    observer.on_progress();
+                    TaskResult::Ok
+                }
+                Err(_) => TaskResult::Terminate,
+            }
+        });
+
+        // A failed job terminated the pool, the remaining ones would never run
+        if submitted.is_err() {
+            break;
+        }
+    }
+
+    let pool_result = pool.wait();
+    observer.on_progress_end();
+
+    for message in pool.panics() {
+        println!(
+            "{}",
+            console::style(format!("Compile job panicked: {message}"))
+                .red()
+                .bold()
+        );
+    }
+
+    for (src_path, duration) in job_durations.lock().unwrap().drain(..) {
+        timings.add_job(src_path, duration);
+    }
+
+    check_interrupted()?;
+
+    if pool_result == TaskResult::Terminate {
+        return Err(MakeError::CompilationFailed);
+    }
+
+    if args.check {
+        print_done(args, &mut timings);
+        return Ok(Vec::new());
+    }
+
+    // Sources skipped by `--only` may still be built with the previous profile
+    if skipped_jobs == 0 {
+        std::fs::write(build_dir.join(PROFILE_STAMP), args.profile.name()).ok();
+        std::fs::write(build_dir.join(THUMB_STAMP), config.source_thumb.join("\n")).ok();
+    }
+
+    let text_align = config.text_align.unwrap_or(exheader::PAGE_SIZE);
+    let link_scripts = config
+        .link_scripts
+        .iter()
+        .map(PathBuf::from)
+        .collect::<Vec<_>>();
+    let mut summaries = Vec::new();
+
+    if variants.is_empty() {
+        let target = Target {
+            original_dir: PathBuf::from("original"),
+            symbols_path: PathBuf::from("symbols.ld"),
+            build_dir: build_dir.clone(),
+            original_exefs_path: config
+                .exefs
+                .as_ref()
+                .map(|name| PathBuf::from("original").join(name)),
+            luma: config.luma,
+            manifest: config.manifest,
+            text_align,
+            verify_reference: verify_reference.clone(),
+            link_scripts: link_scripts.clone(),
+            linker: job_env.compiler[JobKind::CPP].clone(),
+            cpu: config.cpu.clone(),
+            hook_prefix: config.hooks.prefix.clone(),
+            split_extra: config.split_extra,
+            fill: config.fill,
+            hexdiff_path: args.emit_hexdiff.clone(),
+        };
+        summaries.extend(build_target(
+            args,
+            &project_path,
+            &jobs,
+            any_rebuilt,
+            &target,
+            &mut timings,
+            &*observer,
+        )?);
+    }
+
+    // Objects are shared, linking and hooks depend on the addresses of each variant
+    for variant in &variants {
+        output::status!(
+            "{}",
+            console::style(format!("Variant {}:", variant.name)).bold()
+        );
+
+        let target = Target {
+            original_dir: variant.original_dir.clone(),
+            symbols_path: variant.symbols_path.clone(),
+            build_dir: build_dir.join(&variant.name),
+            original_exefs_path: config
+                .exefs
+                .as_ref()
+                .map(|name| variant.original_dir.join(name)),
+            luma: config.luma,
+            manifest: config.manifest,
+            text_align,
+            verify_reference: verify_reference.clone(),
+            link_scripts: link_scripts.clone(),
+            linker: job_env.compiler[JobKind::CPP].clone(),
+            cpu: config.cpu.clone(),
+            hook_prefix: config.hooks.prefix.clone(),
+            split_extra: config.split_extra,
+            fill: config.fill,
+            hexdiff_path: args
+                .emit_hexdiff
+                .as_ref()
+                .map(|path| variant_file_path(path, &variant.name)),
+        };
+        let ignore = IgnoreList::new(&variant.ignore);
+        let variant_jobs = jobs
+            .iter()
+            .filter(|job| !is_variant_ignored(job, &ignore))
+            .cloned()
+            .collect::<Vec<_>>();
+        summaries.extend(build_target(
+            args,
+            &project_path,
+            &variant_jobs,
+            any_rebuilt,
+            &target,
+            &mut timings,
+            &*observer,
+        )?);
+    }
+
+    print_done(args, &mut timings);
+
+    Ok(summaries)
+}
+
+/// Starts timing `phase` and reports it to `observer`.
+fn start_phase(timings: &mut Timings, observer: &dyn BuildObserver, phase: &'static str) {
+    timings.start(phase);
+    observer.on_phase(phase);
+}
+
+/// Compiles `job`, reporting its start and result to `observer`.
+fn run_compile_job(
+    job_env: &JobEnv,
+    job: &Job,
+    observer: &dyn BuildObserver,
+) -> std::io::Result<String> {
+    observer.on_job_start(job);
+    let result = job_env.execute_job(job);
+    match &result {
+        Ok(warnings) => observer.on_job_done(job, Ok(warnings)),
+        Err(e) => observer.on_job_done(job, Err(&e.to_string())),
+    }
+    result
+}
+
+/// Reports every hook write of `writer` to `observer`, by address.
+fn report_hooks(writer: &HookWriter, observer: &dyn BuildObserver) {
+    for (address, size, reason) in writer.writes() {
+        if let HookWriteReason::Hook(kind, locations) = reason {
+            observer.on_hook_applied(address, size, kind, locations);
+        }
+    }
+}
+
+fn print_done(args: &Args, timings: &mut Timings) {
+    timings.stop();
+
+    output::status!("{}", console::style("Done!").green().bold());
+
+    if args.time {
+        println!("{}", console::style("Timings:").bold());
+        print!("{}", timings.report(SLOWEST_JOB_COUNT));
+    }
+}
+
+/// Original files and output directory of a single link.
+struct Target {
+    /// Directory containing code.bin and exheader.bin, or a CXI
+    original_dir: PathBuf,
+    symbols_path: PathBuf,
+    build_dir: PathBuf,
+    /// ExeFS the patched code is repacked into, if enabled
+    original_exefs_path: Option<PathBuf>,
+    /// Also writes the outputs to luma/titles in the build directory
+    luma: bool,
+    /// Writes hooks.manifest
+    manifest: bool,
+    /// The custom text is padded up to a multiple of this
+    text_align: u32,
+    /// Reference code.bin the patched code is compared against instead of writing outputs
+    verify_reference: Option<PathBuf>,
+    /// Linker script fragments appended to the generated linker script
+    link_scripts: Vec<PathBuf>,
+    /// Writes the code appended to the original code to extra.bin
+    split_extra: bool,
+    /// Word the inserted padding is filled with
+    fill: u32,
+    /// Compiler driver the target is linked with
+    linker: PathBuf,
+    cpu: config::CpuConfig,
+    hook_prefix: HookPrefix,
+    /// Hexdump of the changed regions, see `format_hexdiff`
+    hexdiff_path: Option<PathBuf>,
+}
+
+/// Placement of the loader and the custom text of a linked target.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct LayoutSummary {
+    pub loader_address: u32,
+    pub loader_max_size: u32,
+    pub loader_used: u32,
+    pub custom_text_address: u32,
+    pub custom_text_size: u32,
+    /// End of the custom text, rounded up to the text alignment
+    pub end_address: u32,
+    /// Bytes of the fill word added after the custom text to reach `end_address`
+    pub padding: u32,
+    /// The loader is larger than the space available to it
+    pub loader_overflow: bool,
+}
+
+impl LayoutSummary {
+    fn new(
+        loader_address: u32,
+        loader_max_size: u32,
+        loader_used: u32,
+        custom_text_address: u32,
+        custom_text_size: u32,
+        text_align: u32,
+    ) -> Self {
+        let text_end = custom_text_address + custom_text_size;
+        let end_address = (text_end + text_align - 1) & !(text_align - 1);
+
+        Self {
+            loader_address,
+            loader_max_size,
+            loader_used,
+            custom_text_address,
+            custom_text_size,
+            end_address,
+            padding: end_address - text_end,
+            loader_overflow: loader_used > loader_max_size,
+        }
+    }
+
+    /// Percentage of the maximum loader size in use
+    pub fn loader_usage(&self) -> f32 {
+        self.loader_used as f32 / self.loader_max_size as f32 * 100.0
+    }
+}
+
+impl std::fmt::Display for LayoutSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", console::style("Loader:").bold())?;
+        writeln!(f, "  address: 0x{:08x}", self.loader_address)?;
+        writeln!(f, " max size: 0x{:08x}", self.loader_max_size)?;
+        writeln!(
+            f,
+            "     size: 0x{:08x} ({:.2}%)",
+            self.loader_used,
+            self.loader_usage()
+        )?;
+        writeln!(f, "{}", console::style("Custom text:").bold())?;
+        writeln!(f, "  address: 0x{:08x}", self.custom_text_address)?;
+        writeln!(f, "     size: 0x{:08x}", self.custom_text_size)?;
+        writeln!(f, "  padding: 0x{:08x}", self.padding)
+    }
+}
+
+/// Everything the hook pass after linking reads.
+fn hook_stage_inputs(
+    args: &Args,
+    target: &Target,
+    original_files: &OriginalFiles,
+    hks_paths: &[PathBuf],
+) -> incremental::StageInputs {
+    incremental::StageInputs {
+        files: [target.build_dir.join("out.elf")]
+            .into_iter()
+            .chain(hks_paths.iter().cloned())
+            .chain([target.symbols_path.clone()])
+            .chain(original_files.paths())
+            .chain(target.original_exefs_path.clone())
+            // A missing config is an empty one and no input
+            .chain(Some(PathBuf::from(config::CONFIG_FILE_NAME)).filter(|path| path.exists()))
+            .collect(),
+        options: format!(
+            "{} {} allow_overlaps={} max_loader_usage={:?} text_align=0x{:x} split_extra={} fill=0x{:x} exefs={:?} luma={} manifest={}\n{:#?}\n",
+            APP_NAME,
+            APP_VERSION,
+            args.allow_overlaps,
+            args.max_loader_usage,
+            target.text_align,
+            target.split_extra,
+            target.fill,
+            target.original_exefs_path,
+            target.luma,
+            target.manifest,
+            hks_paths,
+        ),
+    }
+}
+
+/// Files the hook pass writes. Only code.bin is compared against the inputs, the others are
+/// written after it but have to exist for the target to be up to date.
+fn hook_stage_outputs(target: &Target) -> Vec<PathBuf> {
+    let mut outputs = vec![
+        target.build_dir.join("code.bin"),
+        target.build_dir.join("exheader.bin"),
+    ];
+    if target.original_exefs_path.is_some() {
+        outputs.push(target.build_dir.join("exefs.bin"));
+    }
+    if target.split_extra {
+        outputs.push(target.build_dir.join("extra.bin"));
+    }
+    if target.manifest {
+        outputs.push(target.build_dir.join("hooks.manifest"));
+    }
+    outputs
+}
+
+/// Links the compiled objects against one set of original files and applies the hooks.
+/// Returns the layout of the target, or `None` if it was up to date.
+fn build_target(
+    args: &Args,
+    project_path: &std::path::Path,
+    jobs: &[Job],
+    any_rebuilt: bool,
+    target: &Target,
+    timings: &mut Timings,
+    observer: &dyn BuildObserver,
+) -> MakeResult<Option<LayoutSummary>> {
+    let hook_prefix = &target.hook_prefix;
+    start_phase(timings, observer, "dependency scan");
+
+    let build_dir = &target.build_dir;
+    std::fs::create_dir_all(build_dir).map_err(|e| {
+        MakeError::Fatal(format!(
+            "Creating build directory {} failed: {}",
+            build_dir.display(),
+            e
+        ))
+    })?;
+
+    let linker_script_path = build_dir.join("linker.ld");
+    let elf_path = build_dir.join("out.elf");
+    let map_path = build_dir.join("out.map");
+    let symbol_cache_path = build_dir.join("symbols.cache");
+
+    let hks_paths = find_hks_files("hooks")
+        .map_err(|e| MakeError::Fatal(format!("Reading hooks directory failed: {}", e)))?;
+
+    // Symbols referenced by hks files are unknown to the linker and have to be kept alive
+    // Also counts the entries, so the hook progress bar has a known length
+    let mut hks_symbols = std::collections::HashSet::new();
+    let mut hks_entry_count = 0;
+    for hks_path in &hks_paths {
+        let entries = hook::hks::open_file(hks_path).map_err(|e| {
+            MakeError::Fatal(format!("Opening {} failed: {}", hks_path.display(), e))
+        })?;
+        for h in entries {
+            hks_entry_count += 1;
+            let Ok(mut h) = h else {
+                continue;
+            };
+
+            hks_symbols.extend(hks_symbol_values(&mut h));
+        }
+    }
+
+    // Objects and everything else that changes the linked ELF. The hks symbols are kept alive
+    // by the linker script, so only editing them relinks.
+    let original_files = OriginalFiles::find(&target.original_dir)?;
+    let link_inputs = incremental::StageInputs {
+        files: jobs
+            .iter()
+            .map(|job| job.obj_path.clone())
+            .chain([
+                target.symbols_path.clone(),
+                original_files.exheader_path().to_path_buf(),
+            ])
+            .chain(target.link_scripts.iter().cloned())
+            .collect(),
+        options: format!(
+            "{} {} gc_sections={} profile={}\n{:?}\n{:#?}\n{:#?}\n",
+            APP_NAME,
+            APP_VERSION,
+            args.gc_sections,
+            args.profile.name(),
+            hook_prefix,
+            jobs.iter().map(|job| &job.obj_path).collect::<Vec<_>>(),
+            hks_symbols
+                .iter()
+                .collect::<std::collections::BTreeSet<_>>(),
+        ),
+    };
+    let link_stamp_path = build_dir.join("link");
+
+    let hook_inputs = hook_stage_inputs(args, target, &original_files, &hks_paths);
+    let hook_outputs = hook_stage_outputs(target);
+    let hook_stamp_path = build_dir.join("hooks");
+    let code_path = build_dir.join("code.bin");
+    let exheader_path = build_dir.join("exheader.bin");
+    let exefs_path = build_dir.join("exefs.bin");
+    let extra_path = build_dir.join("extra.bin");
+
+    let link_up_to_date =
+        !any_rebuilt && !args.force && link_inputs.up_to_date(&elf_path, &link_stamp_path);
+
+    // Explaining, dumping and hexdiffs need the full hook processing
+    if link_up_to_date
+        && args.explain_address.is_none()
+        && !args.dump_trampolines
+        && target.hexdiff_path.is_none()
+        && target.verify_reference.is_none()
+        && hook_outputs.iter().all(|path| path.exists())
+        && hook_inputs.up_to_date(&code_path, &hook_stamp_path)
+    {
+        output::status!("Up to date, skipping linking (use --force to rebuild)");
+        return Ok(None);
+    }
+
+    let (original_code, mut exheader) = original_files.read()?;
+
+    // A failed extraction would otherwise only show up as every hook being out of bounds
+    let min_code_size = calc_min_code_size(&exheader);
+    if original_code.is_empty() {
+        fatal_error!(
+            "Original code from {} is empty, extract it again",
+            original_files.code_path().display()
+        );
+    }
+    if original_code.len() < min_code_size as usize {
+        fatal_error!(
+            "Original code from {} is 0x{:x} bytes but the exheader needs 0x{:x}, it is truncated or still compressed",
+            original_files.code_path().display(),
+            original_code.len(),
+            min_code_size
+        );
+    }
+
+    let mut writer = HookWriter::new(calc_code_base(&exheader), original_code.clone())
+        // Overlaps are reported together once every hook is written
+        .with_duplicate_check(false)
+        .with_fill(target.fill);
+
+    let loader_address = calc_loader_address(&exheader);
+    let loader_max_size = calc_loader_max_size(&exheader);
+    let custom_text_address = calc_custom_text_address(&exheader);
+    let layout = layout::Layout::from_exheader(&exheader);
+
+    output::status!("Title id: {:016X}", exheader.title_id());
+
+    log::debug!(
+        "loader address: 0x{:x}, loader max size: 0x{:x}, custom text address: 0x{:x}",
+        loader_address,
+        loader_max_size,
+        custom_text_address
+    );
+
+    print_step(2, "Section hooks...");
+    start_phase(timings, observer, "section hooks");
+
+    let mut linker_file = std::fs::File::create(tmp_path(&linker_script_path)).unwrap();
+
+    // Let ld itself report a region overflow if the loader exceeds its budget
+    linker_file
+        .write(
+            format!(
+                "MEMORY\n{{\n    loader : ORIGIN = 0x{loader_address:x}, LENGTH = 0x{loader_max_size:x}\n}}\n\n"
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+
+    linker_file
+        .write("SECTIONS\n{\n    /* Hook Generated Sections */\n".as_bytes())
+        .unwrap();
+
+    let original_code_end = writer.end_address();
+    let mut obj_paths = Vec::new();
+    let mut obj_section_sizes: HashMap<(&PathBuf, String), u64> = HashMap::new();
+    let mut keep_sections = std::collections::BTreeSet::new();
+    let mut replace_sections = Vec::new();
+    let mut empty_sources = Vec::new();
+
+    for job in jobs {
+        obj_paths.push(&job.obj_path);
+
+        let elf_data = std::fs::read(&job.obj_path).unwrap();
+        let elf_file = object::File::parse(elf_data.as_slice()).unwrap();
+
+        if args.empty_objects != EmptyObjects::Allow
+            && is_empty_object(elf_file.sections().map(|s| (s.kind(), s.size())))
+        {
+            empty_sources.push(&job.src_path);
+        }
+
+        for section in elf_file.sections() {
+            if let Ok(name) = section.name() {
+                obj_section_sizes.insert((&job.obj_path, name.to_string()), section.size());
+            }
+        }
+
+        for (name, hi) in
+            hook::discover_section_hooks(&elf_file, hook_prefix).map_err(hook_discovery_error)?
+        {
+            log::debug!("section hook {:?} at {}", hi.kind, hi.location);
+            match hi.kind {
+                HookKind::Replace(repl_addr) | HookKind::Data(repl_addr) => {
+                    let size = obj_section_sizes[&(&job.obj_path, name.clone())];
+                    // Writes past the original code would silently grow code.bin
+                    if repl_addr < writer.base_address()
+                        || repl_addr as u64 + size > original_code_end as u64
+                    {
+                        hook_error!(
+                            hi.location,
+                            "Section of 0x{:x} bytes at 0x{:x} does not fit into the code (0x{:x}..0x{:x})",
+                            size,
+                            repl_addr,
+                            writer.base_address(),
+                            original_code_end,
+                        );
+                    }
+                    replace_sections.push((repl_addr, size, hi.location));
+                    linker_file
+                        .write(
+                            format!("    {name} 0x{repl_addr:x} : {{ KEEP(*({name})); }}\n")
+                                .as_bytes(),
+                        )
+                        .unwrap();
+                }
+                // Invalid kinds are discarded
+                _ => {
+                    hook_error!(hi.location, "Invalid hook kind for section hook");
+                }
+            }
+        }
+
+        let symbols = elf_file
+            .symbols()
+            .filter_map(|sym| {
+                let section = elf_file.section_by_index(sym.section_index()?).ok()?;
+                Some((sym.name().ok()?, section.name().ok()?.to_string()))
+            })
+            .collect::<Vec<_>>();
+        keep_sections.append(&mut gc::collect_keep_sections(
+            symbols.iter().map(|(sym, section)| (*sym, section.as_str())),
+            &hks_symbols,
+            hook_prefix,
+        ));
+    }
+
+    // Usually a define that is missing for the variant, the source silently contributes nothing
+    if args.empty_objects == EmptyObjects::Deny && !empty_sources.is_empty() {
+        fatal_error!(
+            "{} sources compiled to empty objects:\n{}",
+            empty_sources.len(),
+            empty_sources
+                .iter()
+                .map(|path| format!("  {}", path.display()))
+                .collect::<Vec<_>>()
+                .join("\n")
+        );
+    }
+    for path in empty_sources {
+        observer.on_warning(&format!("{} compiled to an empty object", path.display()));
+    }
+
+    // ld would place overlapping sections on top of each other or fail cryptically
+    if let Some(((a_addr, a_size, a_loc), (b_addr, b_size, b_loc))) =
+        find_overlap(&mut replace_sections)
+    {
+        hook_error!(
+            b_loc,
+            "Replaced range 0x{:x}..0x{:x} overlaps 0x{:x}..0x{:x} replaced at {}",
+            b_addr,
+            *b_addr as u64 + b_size,
+            a_addr,
+            *a_addr as u64 + a_size,
+            a_loc
+        );
+    }
+
+    linker_file.write(format!(
+        "\n    .mw_loader_text 0x{loader_address:x} : {{ KEEP(*(.mw_loader_text)); KEEP(*(.mw_loader_text.*)); }} > loader\n",
+    ).as_bytes()).unwrap();
+
+    linker_file
+        .write(format!("    .text 0x{custom_text_address:x} :\n",).as_bytes())
+        .unwrap();
+    linker_file
+        .write("    {\n        __mw_text_start = .;\n".as_bytes())
+        .unwrap();
+    for section in &keep_sections {
+        linker_file
+            .write(format!("        KEEP(*({section}));\n").as_bytes())
+            .unwrap();
+    }
+    linker_file
+        .write(LINKER_SCRIPT_SECTIONS.as_bytes())
+        .unwrap();
+
+    linker_file.write("}\n".as_bytes()).unwrap();
+
+    // ld merges multiple MEMORY and SECTIONS commands, so fragments can simply follow
+    for script_path in &target.link_scripts {
+        let script = std::fs::read_to_string(script_path).map_err(|e| {
+            MakeError::Fatal(format!(
+                "Reading link script {} failed: {}",
+                script_path.display(),
+                e
+            ))
+        })?;
+        linker_file
+            .write(format!("\n/* {} */\n{}\n", script_path.display(), script).as_bytes())
+            .unwrap();
+    }
+
+    drop(linker_file);
+    std::fs::rename(tmp_path(&linker_script_path), &linker_script_path)?;
+
+    print_step(3, "Linking...");
+    start_phase(timings, observer, "link");
+
+    let mut gc_removed_count = 0usize;
+    let mut gc_removed_size = 0u64;
+
+    if link_up_to_date {
+        output::status!("Linked ELF is up to date, only applying hooks");
+    } else {
+        let mut link_args = Vec::new();
+        if args.gc_sections {
+            link_args.push("-Wl,--gc-sections");
+            link_args.push("-Wl,--print-gc-sections");
+        }
+        if args.profile == Profile::Release {
+            link_args.push("-Wl,--strip-debug");
+        }
+
+        let obj_sources = jobs
+            .iter()
+            .map(|job| (job.obj_path.as_path(), job.src_path.as_path()))
+            .collect::<Vec<_>>();
+
+        let output = Command::new(&target.linker)
+            .current_dir(project_path)
+            .args(link_flags(&target.cpu))
+            .arg("-T")
+            .arg(&target.symbols_path)
+            .arg("-T")
+            .arg(&linker_script_path)
+            .arg(format!("-Wl,-Map={}", map_path.display()))
+            .args(link_args)
+            .args(&obj_paths)
+            .arg("-o")
+            .arg(tmp_path(&elf_path))
+            .output();
+
+        match output {
+            Ok(output) => {
+                let mut err = String::new();
+                for line in String::from_utf8_lossy(&output.stderr).lines() {
+                    match gc::parse_removed_section(line) {
+                        Some((section, obj_path)) => {
+                            gc_removed_count += 1;
+                            gc_removed_size += obj_section_sizes
+                                .get(&(&PathBuf::from(obj_path), section.to_string()))
+                                .copied()
+                                .unwrap_or(0);
+                        }
+                        None => {
+                            match link_errors::describe_multiple_definition(line, &obj_sources) {
+                                Some(description) => err.push_str(&description),
+                                None => err.push_str(line),
+                            }
+                            err.push('\n');
+                        }
+                    }
+                }
+                check_interrupted()?;
+                if !err.is_empty() {
+                    println!("{}", err);
+                }
+                if !output.status.success() {
+                    return Err(MakeError::LinkingFailed);
+                }
+                std::fs::rename(tmp_path(&elf_path), &elf_path)?;
+            }
+            Err(e) => {
+                fatal_error!("Running linker failed: {e}");
+            }
+        }
+    }
+
+    start_phase(timings, observer, "section hooks");
+
+    let elf_data = std::fs::read(&elf_path).unwrap();
+    let elf_file = object::File::parse(elf_data.as_slice()).unwrap();
+
+    let mut loader_text_section = None;
+    let mut custom_text_section = None;
+    let mut plan = HookPlan::default();
+
+    for section in elf_file.sections() {
+        let Ok(name) = section.name() else {
+            continue;
+        };
+
+        if name == ".mw_loader_text" {
+            writer.set_loader_extra_address(section.address() as u32 + section.size() as u32);
+            writer.set_loader_end_address(loader_address + loader_max_size);
+            loader_text_section = Some(section);
+            continue;
+        }
+
+        if name == ".text" {
+            custom_text_section = Some(section);
+            continue;
+        }
+
+        // No need for a full parse here. Emitting the section is only possible if the hook is valid.
+        if !name.starts_with(hook_prefix.section.as_str()) {
+            continue;
+        }
+
+        let address = section.address() as u32;
+        let data = section
+            .data()
+            .expect("Failed to read section data for hook section");
+        let hi = HookInfo::from_section_str(name, hook_prefix).ok();
+        let kind = match hi.as_ref().map(|hi| &hi.kind) {
+            Some(HookKind::Data(_)) => "data",
+            _ => "replace",
+        };
+        let locations = hi.map(|hi| vec![hi.location]).unwrap_or_default();
+
+        plan.write(address, data, kind, locations);
+    }
+
+    print_step(4, "Symbol hooks...");
+    start_phase(timings, observer, "symbol hooks");
+
+    let symtab = elf_file.symbol_table().unwrap();
+
+    // Any rebuilt object invalidates the cache, even if the ELF identity happens to match
+    let cached_symtab_index = if any_rebuilt {
+        None
+    } else {
+        symbol_cache::load(&symbol_cache_path, &elf_path)
+    };
+    let symtab_index_cached = cached_symtab_index.is_some();
+    let symtab_index = match cached_symtab_index {
+        Some(index) => index,
+        None => {
+            // Keeps the Thumb bit so branches to Thumb code become blx
+            let modes = hook::CodeModes::from_elf(&elf_file);
+            let (index, ambiguous) =
+                symbol_map::index_symbols(symtab.symbols().filter_map(|sym| {
+                    Some((
+                        sym.name().ok()?,
+                        modes.symbol_address(&sym),
+                        sym.is_global(),
+                    ))
+                }));
+            for name in ambiguous.iter().filter(|name| hks_symbols.contains(*name)) {
+                observer.on_warning(&format!(
+                    "symbol \"{}\" is defined at multiple addresses, using 0x{:x}",
+                    name, index[name]
+                ));
+            }
+            index
+        }
+    };
+
+    // Reported together up front instead of one per build
+    let mut unresolved = Vec::new();
+    for hks_path in &hks_paths {
+        unresolved.extend(unresolved_hks_symbols(
+            hks_path,
+            hook::hks::open_file(hks_path)?,
+            &symtab_index,
+        ));
+    }
+    if !unresolved.is_empty() {
+        fatal_error!(
+            "{} unresolved symbols in hook files:\n{}",
+            unresolved.len(),
+            unresolved
+                .iter()
+                .map(|(location, name)| format!("  {location}: {name}"))
+                .collect::<Vec<_>>()
+                .join("\n")
+        );
+    }
+
+    let text_end_symbol = symtab
+        .symbols()
+        .find(|sym| sym.name().ok() == Some("__mw_text_end"));
+
+    for (address, hi) in
+        hook::discover_symbol_hooks(&elf_file, hook_prefix).map_err(hook_discovery_error)?
+    {
+        log::debug!(
+            "symbol hook {:?} at {} -> 0x{:x}",
+            hi.kind,
+            hi.location,
+            address
+        );
+
+        match hi.kind {
+            HookKind::Branch(branch) => {
+                let to_addr = address;
+                let data = branch
+                    .to_u32(to_addr)
+                    .map_err(|e| hook_err!(&hi.location, "{}", e))?
+                    .to_le_bytes();
+                plan.write(branch.from_addr, data, "branch", vec![hi.location]);
+            }
+            HookKind::Pre(from_addr, forced_pos) | HookKind::Post(from_addr, forced_pos) => {
+                let extra_pos = select_extra_pos(from_addr, custom_text_address, forced_pos);
+                let pre = matches!(hi.kind, HookKind::Pre(..));
+                plan.add_pre_post(from_addr, extra_pos, pre, (0, address, hi.location.clone()))
+                    .map_err(|e| hook_err!(hi.location, "{}", e))?;
+            }
+            HookKind::Symptr(patch_addr) => plan.write(
+                patch_addr,
+                address.to_le_bytes(),
+                "symptr",
+                vec![hi.location],
+            ),
+            HookKind::Detour(from_addr) => plan
+                .add_detour(from_addr, address, hi.location.clone())
+                .map_err(|e| hook_err!(hi.location, "{}", e))?,
+            HookKind::DetourOriginal(from_addr) => {
+                plan.add_detour_original(from_addr, address, hi.location)
+            }
+            _ => {
+                hook_error!(hi.location, "Invalid hook kind for symbol hook");
+            }
+        }
+    }
+
+    if !symtab_index_cached {
+        symbol_cache::save(&symbol_cache_path, &elf_path, &symtab_index).ok();
+    }
+
+    symbol_map::write_sym(
+        build_dir.join("out.sym"),
+        &symtab_index,
+        custom_text_address,
+    )
+    .map_err(|e| MakeError::Fatal(format!("Writing out.sym failed: {}", e)))?;
+    symbol_map::write_gdb(
+        build_dir.join("symbols.gdb"),
+        project_path.join(&elf_path),
+        &symtab_index,
+        custom_text_address,
+    )
+    .map_err(|e| MakeError::Fatal(format!("Writing symbols.gdb failed: {}", e)))?;
+
+    observer.on_progress_start(hks_entry_count);
+
+    for hks_path in &hks_paths {
+        // Set by base entries, applies to the following entries of the same file
+        let mut base = 0u32;
+
+        let mut entries = hook::hks::open_file(hks_path)?;
+        while let Some(h) = entries.next() {
+            observer.on_progress();
+
+            let mut h = h.map_err(|e| {
+                let location = HookLocation {
+                    file: hks_path.clone(),
+                    line: entries.line() as u32,
+                };
+                hook_err!(location, "Failed to parse hook file: {}", e)
+            })?;
+
+            // Only used for diagnostics, consumed before anything else looks at the keys
+            let name = h.get("name").ok();
+            let desc = h.get("desc").ok();
+            let hook_name = name.or(desc);
+
+            macro_rules! hks_hook_err {
+                ($($arg:tt)*) => {
+                    hook_err!(
+                        HookLocation { file: hks_path.clone(), line: h.line() as u32 },
+                        "{}{}",
+                        hook_name.as_ref().map(|name| format!("hook '{name}': ")).unwrap_or_default(),
+                        format_args!($($arg)*)
+                    )
+                }
+            }
+
+            macro_rules! hks_hook_error {
+                ($($arg:tt)*) => {
+                    return Err(hks_hook_err!($($arg)*))
+                }
+            }
+
+            // A later file overrides earlier writes, overlaps are reported once all are written
+            macro_rules! hks_write {
+                ($address:expr, $data:expr, $kind:expr) => {
+                    plan.write(
+                        $address,
+                        $data,
+                        $kind,
+                        vec![HookLocation {
+                            file: hks_path.clone(),
+                            line: h.line() as u32,
+                        }],
+                    )
+                };
+            }
+
+            macro_rules! hks_check_write {
+                ($address:expr, $size:expr) => {
+                    let segment = layout
+                        .check_write($address, $size as u32)
+                        .map_err(|e| hks_hook_err!("{}", e))?;
+                    log::trace!("hks write 0x{:x} in {}", $address, segment);
+                };
+            }
+
+            let hook_type = h
+                .peek("type")
+                .ok_or_else(|| hks_hook_err!("Missing required key \"type\""))?;
+
+            let Some(spec) = hook::schema::find_type(hook_type) else {
+                hks_hook_error!("Invalid hook type \"{}\"", hook_type);
+            };
+
+            let mut key_errors = spec.validate(h.remaining_keys());
+            // The type was resolved above and may be an alias
+            h.get("type").map_err(|e| hks_hook_err!("{}", e))?;
+            key_errors.extend(spec.validate_values(h.remaining()));
+            if !key_errors.is_empty() {
+                hks_hook_error!(
+                    "{}",
+                    key_errors
+                        .iter()
+                        .map(|e| e.to_string())
+                        .collect::<Vec<_>>()
+                        .join("\n    ")
+                );
+            }
+
+            if spec.name == "require" {
+                let required = h
+                    .get("version")
+                    .map_err(|e| hks_hook_err!("{}", e))?
+                    .parse::<version::Version>()
+                    .map_err(|e| hks_hook_err!("Invalid version \"{}\"", e))?;
+
+                let current = APP_VERSION.parse::<version::Version>().unwrap();
+                if current < required {
+                    hks_hook_error!(
+                        "Hook file requires {} {} or newer, this is {}",
+                        APP_NAME,
+                        required,
+                        current
+                    );
+                }
+                continue;
+            }
+
+            if spec.name == "base" {
+                base = h
+                    .get_address("offset")
+                    .map_err(|e| hks_hook_err!("{}", e))?;
+                continue;
+            }
+
+            macro_rules! hks_check_done {
+                () => {
+                    if !h.is_done() {
+                        hks_hook_error!(
+                            "Unused keys: \"{}\"",
+                            h.remaining_keys().collect::<Vec<_>>().join("\", \"")
+                        );
+                    }
+                };
+            }
+
+            if matches!(spec.name, "nop_list" | "branch_list") {
+                let addrs = h.get_list("addrs").map_err(|e| hks_hook_err!("{}", e))?;
+
+                let branch = match spec.name {
+                    "branch_list" => {
+                        let link = h.get_bool("link").map_err(|e| hks_hook_err!("{}", e))?;
+                        let to_address = get_hks_branch_dest(&mut h, base, &symtab_index)
+                            .map_err(|e| hks_hook_err!("{}", e))?;
+                        Some((link, to_address))
+                    }
+                    _ => None,
+                };
+
+                let kind = match branch {
+                    Some(_) => "branch",
+                    None => "nop",
+                };
+                for (address, instruction) in
+                    expand_hks_list(addrs, base, &symtab_index, &layout, branch)
+                        .map_err(|e| hks_hook_err!("{}", e))?
+                {
+                    hks_write!(address, instruction.to_le_bytes(), kind);
+                }
+
+                hks_check_done!();
+                continue;
+            }
+
+            let address = get_hks_address(&mut h, "addr", base, &symtab_index)
+                .map_err(|e| hks_hook_err!("{}", e))?;
+
+            match spec.name {
+                "branch" => {
+                    let link = h.get_bool("link").map_err(|e| hks_hook_err!("{}", e))?;
+                    let to_address = get_hks_branch_dest(&mut h, base, &symtab_index)
+                        .map_err(|e| hks_hook_err!("{}", e))?;
+
+                    plan.add_branch(
+                        &layout,
+                        address,
+                        to_address,
+                        link,
+                        hook::arm::ArmCondition::AL,
+                        HookLocation {
+                            file: hks_path.clone(),
+                            line: h.line() as u32,
+                        },
+                    )
+                    .map_err(|e| hks_hook_err!("{}", e))?;
+                }
+                "softbranch" => {
+                    let opcode_pos = h.get("opcode").map_err(|e| hks_hook_err!("{}", e))?;
+                    hks_check_write!(address, 4);
+
+                    let to_address = get_hks_branch_dest(&mut h, base, &symtab_index)
+                        .map_err(|e| hks_hook_err!("{}", e))?;
+
+                    let forced_pos = match h.get("section").ok().as_deref() {
+                        None => None,
+                        Some("loader") => Some(HookExtraPos::Loader),
+                        Some("tail") => Some(HookExtraPos::Tail),
+                        Some(section) => {
+                            hks_hook_error!("Invalid section \"{}\"", section);
+                        }
+                    };
+                    let extra_pos = select_extra_pos(address, custom_text_address, forced_pos);
+
+                    let priority = match h.has("priority") {
+                        true => h
+                            .get_integer("priority")
+                            .map_err(|e| hks_hook_err!("{}", e))?,
+                        false => 0,
+                    };
+
+                    let a = (
+                        priority,
+                        to_address,
+                        HookLocation {
+                            file: hks_path.clone(),
+                            line: h.line() as u32,
+                        },
+                    );
+
+                    // The call runs on the other side of the original opcode
+                    let pre = match opcode_pos.as_str() {
+                        "pre" => false,
+                        "post" => true,
+                        _ => {
+                            hks_hook_error!("Invalid opcode position \"{}\"", opcode_pos);
+                        }
+                    };
+                    plan.add_pre_post(address, extra_pos, pre, a)
+                        .map_err(|e| hks_hook_err!("{}", e))?;
+                }
+                "patch" => {
+                    let data_str = h.get("data").map_err(|e| hks_hook_err!("{}", e))?;
+                    let data = hook::parse_hex_data(&data_str)
+                        .map_err(|e| hks_hook_err!("Invalid patch data \"{}\": {}", data_str, e))?;
+
+                    let location = HookLocation {
+                        file: hks_path.clone(),
+                        line: h.line() as u32,
+                    };
+                    plan.add_patch(&layout, address, data, location)
+                        .map_err(|e| hks_hook_err!("{}", e))?;
+                }
+                "symbol" => {
+                    let sym = h.get("sym").map_err(|e| hks_hook_err!("{}", e))?;
+                    let location = HookLocation {
+                        file: hks_path.clone(),
+                        line: h.line() as u32,
+                    };
+                    plan.add_symptr(&layout, address, &sym, &symtab_index, location)
+                        .map_err(|e| hks_hook_err!("{}", e))?;
+                }
+                _ => unreachable!("hook type \"{}\" has no handler", spec.name),
+            }
+
+            hks_check_done!();
+        }
+    }
+
+    observer.on_progress_end();
+
+    commit_writes(&mut writer, &plan.writes)?;
+    let HookPlan {
+        pre_post: mut pre_post_entries,
+        detours: detour_entries,
+        ..
+    } = plan;
+
+    let Some(loader_text_section) = loader_text_section else {
+        fatal_error!("Loader text section not found");
+    };
+    let Some(custom_text_section) = custom_text_section else {
+        fatal_error!("Custom text section not found");
+    };
+
+    let summary = LayoutSummary::new(
+        loader_address,
+        loader_max_size,
+        loader_text_section.size() as u32,
+        custom_text_address,
+        custom_text_section.size() as u32,
+        target.text_align,
+    );
+    if !output::quiet() {
+        print!("{summary}");
+    }
+
+    if summary.loader_overflow {
+        fatal_error!("Loader size exceeds maximum size");
+    }
+
+    if let Some(max_usage) = args.max_loader_usage {
+        if summary.loader_usage() > max_usage {
+            fatal_error!(
+                "Loader uses {:.2}% of its maximum size, more than the {}% allowed by --max-loader-usage",
+                summary.loader_usage(),
+                max_usage
+            );
+        }
+    }
+
+    let data = loader_text_section
+        .data()
+        .expect("Failed to read loader text section data");
+    writer.write(loader_address, data).unwrap();
+
+    let data = custom_text_section
+        .data()
+        .expect("Failed to read custom text section data");
+    // The former bss stays zeroed, only the alignment padding after the custom text is filled
+    writer.resize_zeroed_until(custom_text_address).unwrap();
+    writer.resize_until(summary.end_address).unwrap();
+    writer.write(custom_text_address, data).unwrap();
+
+    if let Some(_text_end_symbol) = text_end_symbol {
+        // TODO: This sym needs to be fixed, otherwise extra data will not be reprotected by the loader properly
+        // set to writer.end_address()
+    }
+
+    if args.gc_sections && !link_up_to_date {
+        output::status!("{}", console::style("Garbage collection:").bold());
+        output::status!(
+            "  removed: {} sections (0x{:x} bytes)",
+            gc_removed_count,
+            gc_removed_size
+        );
+    }
+
+    // Extra block address ranges by hooked address
+    let mut trampolines = HashMap::new();
+
+    observer.on_progress_start(pre_post_entries.len());
+
+    for entry in pre_post_entries.values_mut() {
+        sort_pre_post_calls(&mut entry.pre);
+        sort_pre_post_calls(&mut entry.post);
+    }
+
+    // Checked before any trampoline is written, so errors don't leave half written hooks
+    for (from_address, entry) in &pre_post_entries {
+        if *from_address < writer.base_address()
+            || *from_address as u64 + 4 > writer.end_address() as u64
+        {
+            let (_, _, location) = entry.pre.iter().chain(&entry.post).next().unwrap();
+            hook_error!(
+                location,
+                "Hook address 0x{:x} is outside of the code (0x{:x}..0x{:x})",
+                from_address,
+                writer.base_address(),
+                writer.end_address(),
+            );
+        }
+    }
+
+    // Usually a branch hook that was migrated to a pre/post hook and not removed
+    for (from_address, entry) in &pre_post_entries {
+        if let Some(branch_location) = find_branch_conflict(&writer, *from_address) {
+            let (_, _, location) = entry.pre.iter().chain(&entry.post).next().unwrap();
+            hook_error!(
+                location,
+                "Pre/post hook at 0x{:x} conflicts with the branch hook at {}, both replace the instruction there and can't coexist",
+                from_address,
+                branch_location,
+            );
+        }
+    }
+
+    // Trampolines run a copy of the instruction they displace, taken from the patched code
+    let displacing_hooks = pre_post_entries
+        .iter()
+        .map(|(from_address, entry)| {
+            let (_, _, location) = entry.pre.iter().chain(&entry.post).next().unwrap();
+            (*from_address, "pre/post", location)
+        })
+        .chain(detour_entries.iter().filter_map(|(from_address, entry)| {
+            let (_, location) = entry.hook.as_ref()?;
+            Some((*from_address, "detour", location))
+        }));
+    for (from_address, kind, location) in displacing_hooks {
+        let Some((write_address, other_kind, other_location)) =
+            find_displaced_write(&writer, from_address)
+        else {
+            continue;
+        };
+
+        let msg = format!(
+            "{kind} hook displaces the instruction at 0x{from_address:x} into a trampoline, but the {other_kind} hook at {other_location} writes to it (0x{write_address:x})"
+        );
+        match args.allow_overlaps {
+            true => observer.on_warning(&format!("{}: {}", location, msg)),
+            false => hook_error!(location, "{}", msg),
+        }
+    }
+
+    for (from_address, entry) in &pre_post_entries {
+        observer.on_progress();
+
+        let trampoline = writer
+            .write_extra(entry.extra_pos, |writer, extra_writer| {
+                write_pre_post_trampoline(
+                    writer,
+                    extra_writer,
+                    *from_address,
+                    &strip_priorities(&entry.pre),
+                    &strip_priorities(&entry.post),
+                )
+            })
+            .map_err(|e| match e {
+                MakeError::Writer(e) => {
+                    let (_, _, location) = entry.pre.iter().chain(&entry.post).next().unwrap();
+                    hook_err!(location, "Writing hook trampoline failed: {}", e)
+                }
+                e => e,
+            })?;
+
+        trampolines.insert(*from_address, trampoline);
+    }
+
+    observer.on_progress_end();
+
+    for (from_address, entry) in &detour_entries {
+        let Some((hook_address, hook_location)) = &entry.hook else {
+            let (_, location) = &entry.originals[0];
+            hook_error!(location, "No detour hook for 0x{:x}", from_address);
+        };
+
+        if pre_post_entries.contains_key(from_address) {
+            hook_error!(
+                hook_location,
+                "Detour hook for 0x{:x} conflicts with pre/post hooks",
+                from_address,
+            );
+        }
+
+        let extra_pos = select_extra_pos(*from_address, custom_text_address, None);
+
+        let hook_branch = hook::arm::make_branch_u32(
+            false,
+            *from_address,
+            *hook_address,
+            hook::arm::ArmCondition::AL,
+        )
+        .map_err(|e| hook_err!(hook_location, "Branching to detour hook failed: {}", e))?;
+
+        let trampoline = writer
+            .write_extra(extra_pos, |writer, extra_writer| -> MakeResult<()> {
+                let original_instruction = writer.read_u32(*from_address).map_err(|e| {
+                    hook_err!(
+                        hook_location,
+                        "Reading the hooked instruction at 0x{:x} failed: {}",
+                        from_address,
+                        e
+                    )
+                })?;
+
+                // The hook takes the place of the function
+                writer.write_with_reason(
+                    *from_address,
+                    hook_branch.to_le_bytes(),
+                    HookWriteReason::Hook("detour", vec![hook_location.clone()]),
+                )?;
+
+                // The trampoline runs the displaced instruction and continues after it
+                let relocated_instruction = hook::arm::relocate_u32(
+                    original_instruction,
+                    *from_address,
+                    extra_writer.end_address(),
+                )
+                .map_err(|e| {
+                    hook_err!(
+                        hook_location,
+                        "Relocating original instruction at 0x{:x} failed: {}",
+                        from_address,
+                        e
+                    )
+                })?;
+                extra_writer.write_end(relocated_instruction.to_le_bytes())?;
+
+                let jump_back = hook::arm::make_branch_u32(
+                    false,
+                    extra_writer.end_address(),
+                    *from_address + 4,
+                    hook::arm::ArmCondition::AL,
+                )
+                .map_err(|_| {
+                    hook_err!(
+                        hook_location,
+                        "Trampoline at 0x{:x} is out of range from 0x{:x}",
+                        extra_writer.base_address(),
+                        from_address,
+                    )
+                })?;
+                extra_writer.write_end(jump_back.to_le_bytes())?;
+
+                Ok(())
+            })
+            .map_err(|e| match e {
+                MakeError::Writer(e) => {
+                    hook_err!(hook_location, "Writing detour trampoline failed: {}", e)
+                }
+                e => e,
+            })?;
+
+        for (pointer_address, location) in &entry.originals {
+            writer
+                .write_with_reason(
+                    *pointer_address,
+                    trampoline.start.to_le_bytes(),
+                    HookWriteReason::Hook("detour_original", vec![location.clone()]),
+                )
+                .map_err(|e| {
+                    hook_err!(location, "Writing original function pointer failed: {}", e)
+                })?;
+        }
+
+        trampolines.insert(*from_address, trampoline);
+    }
+
+    writer.fill_loader_slack();
+
+    if let Some(remaining) = writer.loader_space_remaining() {
+        output::status!("{}", console::style("Loader trampolines:").bold());
+        output::status!("     free: 0x{:08x}", remaining);
+    }
+
+    // Nothing has been output yet, so an error leaves the previous outputs intact
+    let overlaps = writer.overlaps();
+    if !overlaps.is_empty() {
+        match args.allow_overlaps {
+            true => observer.on_warning(&format!(
+                "{} writes overlapped earlier writes, the later ones were applied:\n{}",
+                overlaps.len(),
+                format_overlaps(overlaps)
+            )),
+            false => fatal_error!(
+                "{} writes overlap earlier writes, pass --allow-overlaps to apply the later ones:\n{}",
+                overlaps.len(),
+                format_overlaps(overlaps)
+            ),
+        }
+    }
+
+    report_hooks(&writer, observer);
+
+    if let Some(address) = args.explain_address {
+        print_explanation(address, &original_code, &writer, &trampolines);
+    }
+
+    if args.dump_trampolines {
+        println!("{}", console::style("Trampolines:").bold());
+        let mut sites = trampolines.keys().collect::<Vec<_>>();
+        sites.sort();
+        for site in sites {
+            print_trampoline(&writer, *site, &trampolines[site]);
+        }
+    }
+
+    if let Some(reference_path) = &target.verify_reference {
+        let reference = std::fs::read(reference_path).map_err(|e| {
+            MakeError::Fatal(format!(
+                "Reading {} failed: {}",
+                reference_path.display(),
+                e
+            ))
+        })?;
+
+        let mismatches = diff_ranges(writer.data(), &reference, writer.base_address());
+        if !mismatches.is_empty() {
+            print_mismatches(&writer, &mismatches);
+            return Err(MakeError::VerifyFailed(mismatches.len()));
+        }
+
+        output::status!("Patched code matches {}", reference_path.display());
+        return Ok(Some(summary));
+    }
+
+    // Last point to stop before the outputs are replaced
+    check_interrupted()?;
+
+    start_phase(timings, observer, "output");
+
+    // The original code keeps its size, the appended code is loaded from extra.bin instead
+    let (code_data, extra_data) = match target.split_extra {
+        true => writer.data().split_at(original_code.len()),
+        false => (writer.data(), &[][..]),
+    };
+    write_atomic(&code_path, code_data)?;
+
+    if target.split_extra {
+        let extra_address = writer.base_address() + original_code.len() as u32;
+        write_atomic(&extra_path, extra_data)?;
+        output::status!(
+            "Extra: {} (0x{:x} bytes at 0x{:08x})",
+            extra_path.display(),
+            extra_data.len(),
+            extra_address
+        );
+    }
+
+    if target.manifest {
+        write_atomic(build_dir.join("hooks.manifest"), format_manifest(&writer))?;
+    }
+    if let Some(hexdiff_path) = &target.hexdiff_path {
+        write_atomic(hexdiff_path, format_hexdiff(&writer, &original_code))?;
+    }
+
+    patch_exheader(
+        &mut exheader,
+        (!target.split_extra).then_some(writer.end_address()),
+    );
+
+    if let Some(original_exefs_path) = &target.original_exefs_path {
+        let repack_error = |e: String| {
+            MakeError::Fatal(format!(
+                "Repacking {} failed: {}",
+                original_exefs_path.display(),
+                e
+            ))
+        };
+        let original_exefs =
+            std::fs::read(original_exefs_path).map_err(|e| repack_error(e.to_string()))?;
+        let mut exefs = Exefs::parse(&original_exefs).map_err(|e| repack_error(e.to_string()))?;
+        exefs
+            .replace(".code", code_data.to_vec())
+            .map_err(|e| repack_error(e.to_string()))?;
+        write_atomic(&exefs_path, exefs.to_bytes())?;
+
+        // The repacked code is not compressed
+        exheader.info.sci.flags[5] &= !exheader::FLAG_COMPRESS_CODE;
+    }
+
+    let mut exheader_data = std::io::Cursor::new(Vec::new());
+    exheader_data.write_ne(&exheader)?;
+    let exheader_data = exheader_data.into_inner();
+    write_atomic(&exheader_path, &exheader_data)?;
+
+    if target.luma {
+        let luma_dir = build_dir.join(luma_exefs_dir(exheader.title_id()));
+        std::fs::create_dir_all(&luma_dir)?;
+        write_atomic(luma_dir.join("code.bin"), code_data)?;
+        write_atomic(luma_dir.join("exheader.bin"), &exheader_data)?;
+        output::status!("Luma3DS patch: {}", luma_dir.display());
+    }
+
+    if args.profile == Profile::Debug {
+        output::status!("Debug info: {}, {}", elf_path.display(), map_path.display());
+    }
+
+    link_inputs.save(&link_stamp_path)?;
+    hook_inputs.save(&hook_stamp_path)?;
+    let outputs = hook_outputs.iter().map(|path| path.as_path()).collect::<Vec<_>>();
+    std::fs::write(build_dir.join("code.d"), hook_inputs.dep_rule(&outputs))?;
+
+    Ok(Some(summary))
+}
+
+/// Input sections of the custom .text output section.
+/// Nothing zero initializes memory at startup, so .bss and COMMON are placed in the loaded
+/// section as well. The linker then writes their zeros into it, which end up in code.bin.
+const LINKER_SCRIPT_SECTIONS: &str = r#"        *(.text);
+        *(.text.*);
+        *(.rodata);
+        *(.rodata.*);
+        . = ALIGN(4);
+        __init_array_start = .;
+        KEEP(*(.init_array));
+        KEEP(*(.init_array.*));
+        __init_array_end = .;
+        . = ALIGN(4);
+        __fini_array_start = .;
+        KEEP(*(.fini_array));
+        KEEP(*(.fini_array.*));
+        __fini_array_end = .;
+        *(.data);
+        *(.data.*);
+        *(.bss);
+        *(.bss.*);
+        *(COMMON);
+        __mw_text_end = .;
+    }
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_manifest() {
+        let location = |line| HookLocation {
+            file: "source/main.cpp".into(),
+            line,
+        };
+
+        let mut writer = HookWriter::new(0x100000, vec![0; 0x20]);
+        writer
+            .write_with_reason(
+                0x100010,
+                0x300000u32.to_le_bytes(),
+                HookWriteReason::Hook("symptr", vec![location(20)]),
+            )
+            .unwrap();
+        writer
+            .write_with_reason(
+                0x100000,
+                hook::arm::make_branch_u32(true, 0x100000, 0x100100, hook::arm::ArmCondition::AL)
+                    .unwrap()
+                    .to_le_bytes(),
+                HookWriteReason::Hook("branch", vec![location(10)]),
+            )
+            .unwrap();
+        writer
+            .write_with_reason(
+                0x100004,
+                [0; 8],
+                HookWriteReason::Hook("patch", vec![location(30), location(31)]),
+            )
+            .unwrap();
+        writer.write(0x100018, [0; 4]).unwrap();
+
+        assert_eq!(
+            format_manifest(&writer),
+            "0x00100000  branch           bl 0x00100100         source/main.cpp:10\n\
+             0x00100004  patch            0x8 bytes             source/main.cpp:30, source/main.cpp:31\n\
+             0x00100010  symptr           -> 0x00300000         source/main.cpp:20\n"
+        );
+    }
+
+    #[test]
+    fn test_format_hexdiff() {
+        let location = |line| HookLocation {
+            file: "source/main.cpp".into(),
+            line,
+        };
+
+        let original = (0..0x20).collect::<Vec<u8>>();
+        let mut writer = HookWriter::new(0x100000, original.clone());
+        writer
+            .write_with_reason(
+                0x100004,
+                [0xAA; 4],
+                HookWriteReason::Hook("patch", vec![location(10)]),
+            )
+            .unwrap();
+        writer
+            .write_with_reason(
+                0x100008,
+                [0xBB; 6],
+                HookWriteReason::Hook("patch", vec![location(20), location(21)]),
+            )
+            .unwrap();
+        // Unchanged bytes and writes past the original code are left out
+        writer.write(0x100018, [0x18, 0x19]).unwrap();
+        writer.write_end([0xCC; 4]).unwrap();
+        writer.write(0x100020, [0xDD; 4]).unwrap();
+
+        assert_eq!(
+            format_hexdiff(&writer, &original),
+            "0x00100004..0x0010000e\n\
+             \x20 patch hook at source/main.cpp:10\n\
+             \x20 patch hook at source/main.cpp:20, source/main.cpp:21\n\
+             * 0x00100004  04 05 06 07 08 09 0a 0b  |  aa aa aa aa bb bb bb bb\n\
+             * 0x0010000c  0c 0d                    |  bb bb\n\
+             \n"
+        );
+    }
+
+    #[test]
+    fn test_is_variant_ignored() {
+        let job = |src_path: &str| Job {
+            kind: JobKind::C,
+            src_path: src_path.into(),
+            obj_path: "build/obj/a.c.o".into(),
+            dep_path: "build/dep/a.c.d".into(),
+            build_reason: None,
+        };
+        let jobs = [
+            job("source/main.c"),
+            job("source/regions/eur/text.c"),
+            job("source/regions/usa/text.c"),
+        ];
+        let ignore = IgnoreList::new(&["regions/eur/".into()]);
+
+        let variant_jobs = jobs
+            .iter()
+            .filter(|job| !is_variant_ignored(job, &ignore))
+            .map(|job| job.src_path.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            variant_jobs,
+            [
+                PathBuf::from("source/main.c"),
+                PathBuf::from("source/regions/usa/text.c")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cpu_flags() {
+        let config = config::Config::parse("[cpu]\nmarch = \"armv5te\"").unwrap();
+        let job_env = job_env(
+            std::path::Path::new("."),
+            &Toolchain::default(),
+            &config,
+            &config::EnvConfig::default(),
+            &Args::default(),
+        );
+        let job = Job {
+            kind: JobKind::C,
+            src_path: "source/main.c".into(),
+            obj_path: "build/obj/main.c.o".into(),
+            dep_path: "build/dep/main.c.d".into(),
+            build_reason: None,
+        };
+
+        let march = std::ffi::OsString::from("-march=armv5te");
+        assert!(job_env.job_flags(&job).contains(&march));
+        assert!(link_flags(&config.cpu).contains(&"-march=armv5te".to_string()));
+        assert!(!link_flags(&config.cpu).contains(&"-march=armv6k+fp".to_string()));
+    }
+
+    #[test]
+    fn test_jobs_to_json() {
+        let job = |name: &str, kind, build_reason| Job {
+            kind,
+            src_path: format!("source/{name}").into(),
+            obj_path: format!("build/obj/{name}.o").into(),
+            dep_path: format!("build/dep/{name}.d").into(),
+            build_reason,
+        };
+        let jobs = [
+            job("main.cpp", JobKind::CPP, Some(BuildReason::SrcNewer)),
+            job("ui/menu.c", JobKind::C, Some(BuildReason::FlagsChanged)),
+            job("start.s", JobKind::ASM, None),
+        ];
+        let args = Args {
+            only: vec!["*.cpp".into(), "*.s".into()],
+            ..Default::default()
+        };
+
+        let json = jobs_to_json(&jobs, only_selected(&args));
+        let Json::Object(entries) = &json else {
+            panic!("expected an object");
+        };
+        assert_eq!(entries[0], ("total".to_string(), Json::from(3usize)));
+        assert_eq!(
+            entries[1],
+            ("rebuild_count".to_string(), Json::from(1usize))
+        );
+        assert_eq!(
+            entries[2],
+            (
+                "rebuild".to_string(),
+                Json::array([Json::from("source/main.cpp")])
+            )
+        );
+
+        let Json::Array(listed) = &entries[3].1 else {
+            panic!("expected an array");
+        };
+        assert_eq!(
+            listed[1],
+            Json::object([
+                ("kind", Json::from("c")),
+                ("src", Json::from("source/ui/menu.c")),
+                ("obj", Json::from("build/obj/ui/menu.c.o")),
+                ("dep", Json::from("build/dep/ui/menu.c.d")),
+                ("build_reason", Json::from("flags_changed")),
+                ("selected", Json::from(false)),
+            ])
+        );
+        assert_eq!(
+            listed[2],
+            Json::object([
+                ("kind", Json::from("asm")),
+                ("src", Json::from("source/start.s")),
+                ("obj", Json::from("build/obj/start.s.o")),
+                ("dep", Json::from("build/dep/start.s.d")),
+                ("build_reason", Json::Null),
+                ("selected", Json::from(true)),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_env_precedence() {
+        let config = config::Config::parse("[cpu]\nmarch = \"armv5te\"").unwrap();
+        let mut env = config::EnvConfig {
+            jobs: Some(2),
+            cxx: Some(PathBuf::from("/opt/gcc/bin/arm-none-eabi-g++")),
+            ..Default::default()
+        };
+        env.flags[JobKind::C] = vec!["-O2".into(), "-march=armv6k".into()];
+        let toolchain = Toolchain::default();
+        let args = Args::default();
+
+        let job_env = job_env(std::path::Path::new("."), &toolchain, &config, &env, &args);
+        assert_eq!(
+            job_env.compiler[JobKind::C],
+            PathBuf::from("arm-none-eabi-gcc")
+        );
+        assert_eq!(
+            job_env.compiler[JobKind::CPP],
+            PathBuf::from("/opt/gcc/bin/arm-none-eabi-g++")
+        );
+
+        // The environment flags come last and win over the built-in and magwi.toml ones
+        let job = Job {
+            kind: JobKind::C,
+            src_path: "source/main.c".into(),
+            obj_path: "build/obj/main.c.o".into(),
+            dep_path: "build/dep/main.c.d".into(),
+            build_reason: None,
+        };
+        let flags = job_env.job_flags(&job);
+        let position = |flag: &str| flags.iter().position(|f| f == flag).unwrap();
+        assert!(position("-O3") < position("-O2"));
+        assert!(position("-march=armv5te") < position("-march=armv6k"));
+
+        let tables = effective_config(
+            std::path::Path::new("."),
+            &toolchain,
+            &config,
+            &env,
+            &args,
+            4,
+        );
+        let out = format_config(&tables);
+        assert!(out.contains("c = \"arm-none-eabi-gcc\"  # default\n"));
+        assert!(out.contains("cpp = \"/opt/gcc/bin/arm-none-eabi-g++\"  # env\n"));
+        assert!(out.contains("\"-nostdinc\", \"-O2\", \"-march=armv6k\"]  # env\n"));
+        assert!(out.contains("workers = 2  # env\n"));
+    }
+
+    #[test]
+    fn test_effective_config() {
+        let config = config::Config::parse(
+            "[cpu]\nmarch = \"armv5te\"\nmtune = \"mpcore\"\n[variants.eur]\noriginal = \"original/eur\"\nsymbols = \"eur.ld\"",
+        )
+        .unwrap();
+        let toolchain = Toolchain {
+            bin_dir: Some(PathBuf::from("/opt/devkitARM/bin")),
+        };
+        let args = Args {
+            profile: Profile::Debug,
+            build_dir: Some(PathBuf::from("out")),
+            ..Default::default()
+        };
+        let tables = effective_config(
+            std::path::Path::new("."),
+            &toolchain,
+            &config,
+            &config::EnvConfig::default(),
+            &args,
+            4,
+        );
+        let out = format_config(&tables);
+
+        assert!(
+            out.starts_with("[compiler]\nc = \"/opt/devkitARM/bin/arm-none-eabi-gcc\"  # env\n")
+        );
+        assert!(out.contains("\n[include]\nquote = [\"include\"]  # default\n"));
+        assert!(out.contains("system = [\"include/sys\", \"include/sys/clib\"]  # default\n"));
+        assert!(out.contains("\"-g\"]  # flag\n"));
+        assert!(!out.contains("\"-iquote\""));
+        // Set in the file, even though it's the default
+        assert!(out.contains("march = \"armv5te\"  # file\nmtune = \"mpcore\"  # file\n"));
+        assert!(out.contains("mfloat_abi = \"hard\"  # default\n"));
+        assert!(out.contains("filename_define = \"__mw_symbol_safe_filename\"  # default\n"));
+        assert!(out.contains("build = \"out\"  # flag\n"));
+        assert!(out.contains("[jobs]\nworkers = 4  # default\n"));
+        assert!(out.ends_with(
+            "[variants.eur]\noriginal = \"original/eur\"  # file\nsymbols = \"eur.ld\"  # file\n"
+        ));
+
+        // The environment flags are appended after -g
+        let mut env = config::EnvConfig::default();
+        env.flags[JobKind::C] = vec!["-O2".into()];
+        let tables = effective_config(
+            std::path::Path::new("."),
+            &toolchain,
+            &config,
+            &env,
+            &args,
+            4,
+        );
+        assert!(format_config(&tables).contains("\"-g\", \"-O2\"]  # env\n"));
+    }
+
+    #[test]
+    fn test_variant_file_path() {
+        assert_eq!(
+            variant_file_path(std::path::Path::new("build/changes.txt"), "eur"),
+            PathBuf::from("build/changes.eur.txt")
+        );
+        assert_eq!(
+            variant_file_path(std::path::Path::new("changes"), "usa"),
+            PathBuf::from("changes.usa")
+        );
+    }
+
+    #[test]
+    fn test_find_displaced_write() {
+        let location = HookLocation {
+            file: "source/main.cpp".into(),
+            line: 12,
+        };
+        let mut writer = HookWriter::new(0x100000, vec![0; 0x100]);
+        writer
+            .write_with_reason(
+                0x100012,
+                [0; 2],
+                HookWriteReason::Hook("patch", vec![location.clone()]),
+            )
+            .unwrap();
+        writer.write(0x100020, [0; 4]).unwrap();
+
+        assert_eq!(
+            find_displaced_write(&writer, 0x100010),
+            Some((0x100012, "patch", &location))
+        );
+        assert_eq!(find_displaced_write(&writer, 0x100014), None);
+        // Loader and custom text writes aren't hooks
+        assert_eq!(find_displaced_write(&writer, 0x100020), None);
+    }
+
+    #[test]
+    fn test_find_branch_conflict() {
+        let location = HookLocation {
+            file: "source/main.cpp".into(),
+            line: 12,
+        };
+        let mut writer = HookWriter::new(0x100000, vec![0; 0x100]);
+        writer
+            .write_with_reason(
+                0x100010,
+                [0; 4],
+                HookWriteReason::Hook("branch", vec![location.clone()]),
+            )
+            .unwrap();
+        writer
+            .write_with_reason(
+                0x100020,
+                [0; 4],
+                HookWriteReason::Hook("patch", vec![location.clone()]),
+            )
+            .unwrap();
+
+        assert_eq!(find_branch_conflict(&writer, 0x100010), Some(&location));
+        // Only branches replacing the same instruction
+        assert_eq!(find_branch_conflict(&writer, 0x10000e), None);
+        assert_eq!(find_branch_conflict(&writer, 0x100014), None);
+        assert_eq!(find_branch_conflict(&writer, 0x100020), None);
+    }
+
+    #[test]
+    fn test_hook_plan() {
+        let location = |line| HookLocation {
+            file: "source/main.cpp".into(),
+            line,
+        };
+        let mut plan = HookPlan::default();
+
+        plan.write(0x100010, [1; 4], "branch", vec![location(1)]);
+        plan.write(0x100014, [2; 2], "patch", vec![location(2)]);
+        assert_eq!(
+            plan.writes[1],
+            PlannedWrite {
+                address: 0x100014,
+                bytes: vec![2; 2],
+                kind: "patch",
+                locations: vec![location(2)],
+            }
+        );
+
+        plan.add_pre_post(
+            0x100020,
+            HookExtraPos::Loader,
+            true,
+            (0, 0x200000, location(3)),
+        )
+        .unwrap();
+        plan.add_pre_post(
+            0x100020,
+            HookExtraPos::Loader,
+            false,
+            (1, 0x200010, location(4)),
+        )
+        .unwrap();
+        assert_eq!(
+            plan.add_pre_post(
+                0x100020,
+                HookExtraPos::Tail,
+                true,
+                (0, 0x200020, location(5))
+            ),
+            Err(PlanError::PrePostSections(0x100020))
+        );
+        assert_eq!(
+            plan.add_pre_post(
+                0x100022,
+                HookExtraPos::Loader,
+                true,
+                (0, 0x200000, location(3))
+            ),
+            Err(PlanError::Unaligned(0x100022))
+        );
+        assert_eq!(
+            plan.pre_post[&0x100020],
+            PrePostEntry {
+                extra_pos: HookExtraPos::Loader,
+                pre: vec![(0, 0x200000, location(3))],
+                post: vec![(1, 0x200010, location(4))],
+            }
+        );
+
+        plan.add_detour_original(0x100030, 0x200100, location(6));
+        plan.add_detour(0x100030, 0x200030, location(7)).unwrap();
+        assert_eq!(
+            plan.add_detour(0x100030, 0x200040, location(8)),
+            Err(PlanError::MultipleDetours(0x100030, location(7)))
+        );
+        assert_eq!(
+            plan.add_detour(0x100031, 0x200030, location(7)),
+            Err(PlanError::Unaligned(0x100031))
+        );
+        assert_eq!(
+            plan.detours[&0x100030],
+            DetourEntry {
+                hook: Some((0x200030, location(7))),
+                originals: vec![(0x200100, location(6))],
+            }
+        );
+
+        // Nothing is written until committed
+        let mut writer = HookWriter::new(0x100000, vec![0; 0x40]).with_duplicate_check(false);
+        plan.write(0x100012, [3; 4], "patch", vec![location(9)]);
+        commit_writes(&mut writer, &plan.writes).unwrap();
+        assert_eq!(writer.read_u32(0x100010), Ok(0x03030101));
+        assert_eq!(writer.read_u32(0x100014), Ok(0x00000303));
+        assert_eq!(writer.overlaps().len(), 1);
+
+        plan.write(0x10003E, [4; 4], "patch", vec![location(10)]);
+        assert!(matches!(
+            commit_writes(&mut writer, &plan.writes[3..]),
+            Err(MakeError::HookLocation(location, _)) if location.line == 10
+        ));
+    }
+
+    #[test]
+    fn test_hook_plan_api() {
+        let location = |line| HookLocation {
+            file: "tools/gen_hooks.rs".into(),
+            line,
+        };
+        let layout = layout::Layout::new(vec![(0x100000..0x101000, layout::Segment::Text)]);
+        let symtab_index = HashMap::from([("my_table".to_string(), 0x200000)]);
+        let mut plan = HookPlan::default();
+
+        plan.add_branch(
+            &layout,
+            0x100010,
+            0x100100,
+            false,
+            hook::arm::ArmCondition::NE,
+            location(1),
+        )
+        .unwrap();
+        plan.add_patch(&layout, 0x100020, [0xAA, 0xBB], location(2))
+            .unwrap();
+        plan.add_symptr(&layout, 0x100024, "my_table", &symtab_index, location(3))
+            .unwrap();
+
+        assert_eq!(
+            plan.add_symptr(&layout, 0x100028, "missing", &symtab_index, location(4)),
+            Err(PlanError::UnknownSymbol("missing".into()))
+        );
+        assert!(matches!(
+            plan.add_patch(&layout, 0x100FFE, [0; 4], location(5)),
+            Err(PlanError::Layout(_))
+        ));
+        assert!(matches!(
+            plan.add_branch(
+                &layout,
+                0x100012,
+                0x100100,
+                true,
+                hook::arm::ArmCondition::AL,
+                location(6)
+            ),
+            Err(PlanError::Encoding(_))
+        ));
+        assert_eq!(plan.writes.len(), 3);
+
+        let mut writer = HookWriter::new(0x100000, vec![0; 0x40]);
+        commit_writes(&mut writer, &plan.writes).unwrap();
+        assert_eq!(writer.read_u32(0x100010), Ok(0x1A00003A));
+        assert_eq!(writer.read_u32(0x100020), Ok(0x0000BBAA));
+        assert_eq!(writer.read_u32(0x100024), Ok(0x200000));
+
+        // Go through the same duplicate write check as the hks hooks
+        let mut plan = HookPlan::default();
+        plan.add_patch(&layout, 0x100010, [0; 4], location(7))
+            .unwrap();
+        assert!(matches!(
+            commit_writes(&mut writer, &plan.writes),
+            Err(MakeError::HookLocation(location, _)) if location.line == 7
+        ));
+    }
+
+    #[test]
+    fn test_format_overlaps() {
+        let location = |file: &str, line| HookLocation {
+            file: file.into(),
+            line,
+        };
+        let mut writer = HookWriter::new(0x100000, vec![0; 0x100]).with_duplicate_check(false);
+        let writes = [
+            (0x100010, 4, "branch", location("source/main.cpp", 12)),
+            (0x100014, 4, "patch", location("hooks/base.hks", 3)),
+            (0x100012, 4, "patch", location("hooks/feature.hks", 8)),
+            (0x100020, 4, "symptr", location("source/a.cpp", 4)),
+            (0x100020, 4, "patch", location("hooks/feature.hks", 12)),
+        ];
+        for (address, size, kind, location) in writes {
+            writer
+                .write_with_reason(
+                    address,
+                    vec![0; size],
+                    HookWriteReason::Hook(kind, vec![location]),
+                )
+                .unwrap();
+        }
+
+        assert_eq!(
+            format_overlaps(writer.overlaps()),
+            "  0x00100012..0x00100016: patch hook at hooks/feature.hks:8 overlaps branch hook at source/main.cpp:12 and patch hook at hooks/base.hks:3\n\
+             \x20 0x00100020..0x00100024: patch hook at hooks/feature.hks:12 overlaps symptr hook at source/a.cpp:4"
+        );
+    }
+
+    #[test]
+    fn test_is_empty_object() {
+        assert!(is_empty_object([]));
+        // Only the sections every object has
+        assert!(is_empty_object([
+            (SectionKind::Text, 0),
+            (SectionKind::Data, 0),
+            (SectionKind::UninitializedData, 0),
+            (SectionKind::OtherString, 0x20),
+            (SectionKind::Metadata, 0x40),
+        ]));
+
+        assert!(!is_empty_object([(SectionKind::Text, 4)]));
+        assert!(!is_empty_object([(SectionKind::ReadOnlyString, 6)]));
+        assert!(!is_empty_object([(SectionKind::UninitializedData, 0x100)]));
+    }
+
+    #[test]
+    fn test_sort_pre_post_calls() {
+        let location = |file: &str, line| HookLocation {
+            file: file.into(),
+            line,
+        };
+        let mut calls = vec![
+            (0, 0x300000, location("source/b.cpp", 1)),
+            (0, 0x300010, location("hooks/a.hks", 20)),
+            (-1, 0x300020, location("source/c.cpp", 5)),
+            (0, 0x300030, location("hooks/a.hks", 3)),
+            (10, 0x300040, location("source/a.cpp", 1)),
+        ];
+        sort_pre_post_calls(&mut calls);
+
+        assert_eq!(
+            strip_priorities(&calls)
+                .iter()
+                .map(|(dest_addr, _)| *dest_addr)
+                .collect::<Vec<_>>(),
+            vec![0x300020, 0x300030, 0x300010, 0x300000, 0x300040]
+        );
+    }
+
+    #[test]
+    fn test_diff_ranges() {
+        let data = [1, 2, 3, 4, 5, 6, 7, 8];
+        assert!(diff_ranges(&data, &data, 0x100000).is_empty());
+        assert_eq!(
+            diff_ranges(&data, &[1, 0, 0, 4, 5, 0, 7, 8], 0x100000),
+            vec![0x100001..0x100003, 0x100005..0x100006]
+        );
+        assert_eq!(
+            diff_ranges(&data, &data[..6], 0x100000),
+            vec![0x100006..0x100008]
+        );
+        assert_eq!(
+            diff_ranges(&data[..4], &[1, 2, 3, 0, 5], 0x100000),
+            vec![0x100003..0x100005]
+        );
+    }
+
+    #[test]
+    fn test_layout_summary() {
+        let summary = LayoutSummary::new(0x2FF000, 0x1000, 0x800, 0x300000, 0x1234, 0x1000);
+        assert_eq!(summary.end_address, 0x302000);
+        assert_eq!(summary.padding, 0xDCC);
+        assert!(!summary.loader_overflow);
+        assert_eq!(summary.loader_usage(), 50.0);
+
+        let summary = LayoutSummary::new(0x2FF000, 0x1000, 0x1004, 0x300000, 0x1000, 0x1000);
+        assert_eq!(summary.end_address, 0x301000);
+        assert_eq!(summary.padding, 0);
+        assert!(summary.loader_overflow);
+
+        let summary = LayoutSummary::new(0x2FF000, 0x1000, 0x800, 0x300000, 0x1234, 0x100);
+        assert_eq!(summary.end_address, 0x301300);
+        assert_eq!(summary.padding, 0xCC);
+
+        let summary = LayoutSummary::new(0x2FF000, 0x1000, 0x800, 0x300000, 0x1234, 4);
+        assert_eq!(summary.end_address, 0x301234);
+        assert_eq!(summary.padding, 0);
+    }
+
+    #[test]
+    fn test_calc_addresses() {
+        let mut exheader: Exheader = std::io::Cursor::new(vec![0u8; 0x800]).read_ne().unwrap();
+        let sci = &mut exheader.info.sci;
+        sci.text_section.address = 0x14000000;
+        sci.text_section.size = 0x1F00;
+        sci.text_section.num_pages = 2;
+        sci.data_section.address = 0x14004000;
+        sci.data_section.size = 0x800;
+        sci.data_section.num_pages = 1;
+        sci.bss_size = 0x300;
+
+        assert_eq!(calc_code_base(&exheader), 0x14000000);
+        assert_eq!(calc_loader_address(&exheader), 0x14001F00);
+        assert_eq!(calc_loader_max_size(&exheader), 0x100);
+        assert_eq!(calc_custom_text_address(&exheader), 0x14005300);
+        assert_eq!(calc_min_code_size(&exheader), 0x4800);
+    }
+
+    #[test]
+    fn test_linker_script_bss() {
+        // Custom bss only reads as zero at startup if it's part of the loaded section
+        let end = LINKER_SCRIPT_SECTIONS.find("__mw_text_end").unwrap();
+        for input in ["*(.data);", "*(.bss);", "*(.bss.*);", "*(COMMON);"] {
+            assert!(LINKER_SCRIPT_SECTIONS.find(input).unwrap() < end, "{input}");
+        }
+        assert!(!LINKER_SCRIPT_SECTIONS.contains("NOLOAD"));
+    }
+
+    #[test]
+    fn test_patch_exheader_bss() {
+        let mut exheader: Exheader = std::io::Cursor::new(vec![0u8; 0x800]).read_ne().unwrap();
+        let sci = &mut exheader.info.sci;
+        sci.text_section.address = 0x100000;
+        sci.text_section.size = 0x1F00;
+        sci.text_section.num_pages = 2;
+        sci.data_section.address = 0x102000;
+        sci.data_section.size = 0x800;
+        sci.data_section.num_pages = 1;
+        sci.bss_size = 0x1234;
+
+        // The original code ends with the unpadded data section
+        let mut writer = HookWriter::new(0x100000, vec![1; 0x2800]).with_fill(0xDEADBEEF);
+        let custom_text_address = calc_custom_text_address(&exheader);
+        assert_eq!(custom_text_address, 0x104234);
+        writer.resize_zeroed_until(custom_text_address).unwrap();
+        writer.resize_until(custom_text_address + 0x100).unwrap();
+        writer.write(custom_text_address, [2; 0x100]).unwrap();
+
+        patch_exheader(&mut exheader, Some(writer.end_address()));
+        let sci = &exheader.info.sci;
+        assert_eq!(sci.text_section.size, 0x2000);
+        assert_eq!(sci.bss_size, 0);
+        assert_eq!(sci.data_section.num_pages, 3);
+
+        // The declared data section ends with code.bin, the former bss is stored as zeros
+        let data_offset = (sci.data_section.address - writer.base_address()) as usize;
+        assert_eq!(
+            data_offset + sci.data_section.size as usize,
+            writer.data().len()
+        );
+        assert!(writer.data()[0x2800..0x4234].iter().all(|b| *b == 0));
+
+        // Loaded separately, the data section and bss stay as they are
+        let mut exheader: Exheader = std::io::Cursor::new(vec![0u8; 0x800]).read_ne().unwrap();
+        exheader.info.sci.data_section.size = 0x800;
+        exheader.info.sci.bss_size = 0x1234;
+        patch_exheader(&mut exheader, None);
+        assert_eq!(exheader.info.sci.data_section.size, 0x800);
+        assert_eq!(exheader.info.sci.bss_size, 0x1234);
+    }
+
+    #[test]
+    fn test_select_extra_pos() {
+        assert_eq!(
+            select_extra_pos(0x100000, 0x300000, None),
+            HookExtraPos::Loader
+        );
+        assert_eq!(
+            select_extra_pos(0x300000, 0x300000, None),
+            HookExtraPos::Tail
+        );
+        assert_eq!(
+            select_extra_pos(0x100000, 0x300000, Some(HookExtraPos::Tail)),
+            HookExtraPos::Tail
+        );
+        assert_eq!(
+            select_extra_pos(0x300000, 0x300000, Some(HookExtraPos::Loader)),
+            HookExtraPos::Loader
+        );
+    }
+
+    #[test]
+    fn test_luma_exefs_dir() {
+        assert_eq!(
+            luma_exefs_dir(0x0004000000055D00),
+            PathBuf::from("luma/titles/0004000000055D00/exefs")
+        );
+    }
+
+    #[test]
+    fn test_hks_base() {
+        let mut entries = hook::hks::HksReader::new(std::io::Cursor::new(
+            "region:\n    type: base\n    offset: 0x1000\nhook:\n    addr: 0x200\n    dest: 0x100200\n",
+        ))
+        .map(|h| h.unwrap());
+
+        let base = entries.next().unwrap().get_address("offset").unwrap();
+        assert_eq!(base, 0x1000);
+
+        let symtab_index = HashMap::new();
+        let mut h = entries.next().unwrap();
+        assert_eq!(
+            get_hks_address(&mut h, "addr", base, &symtab_index),
+            Ok(0x1200)
+        );
+        assert_eq!(
+            get_hks_address(&mut h, "dest", base, &symtab_index),
+            Ok(0x101200)
+        );
+        assert_eq!(
+            get_hks_address(&mut h, "dest", base, &symtab_index),
+            Err(HksParseError::MissingKey("dest".into()))
+        );
+    }
+
+    #[test]
+    fn test_hks_address_symbol() {
+        let mut h = hook::hks::HksReader::new(std::io::Cursor::new(
+            "hook:\n    addr: my_function\n    dest: missing_function\n",
+        ))
+        .next()
+        .unwrap()
+        .unwrap();
+
+        let symtab_index = HashMap::from([("my_function".to_string(), 0x100400)]);
+
+        // Symbol addresses are not offset by the base
+        assert_eq!(
+            get_hks_address(&mut h, "addr", 0x1000, &symtab_index),
+            Ok(0x100400)
+        );
+        assert_eq!(
+            get_hks_address(&mut h, "dest", 0x1000, &symtab_index),
+            Err(HksParseError::UnknownAddressSymbol(
+                "missing_function".into()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_hks_branch_dest() {
+        let mut entries = hook::hks::HksReader::new(std::io::Cursor::new(
+            "a:\n    func: my_function\n    offset: 4\nb:\n    dest: 0x200\n    offset: 0x8\nc:\n    func: missing_function\n",
+        ))
+        .map(|h| h.unwrap());
+
+        let symtab_index = HashMap::from([("my_function".to_string(), 0x100400)]);
+
+        let mut h = entries.next().unwrap();
+        assert_eq!(
+            get_hks_branch_dest(&mut h, 0x1000, &symtab_index),
+            Ok(0x100404)
+        );
+        assert!(h.is_done());
+
+        let mut h = entries.next().unwrap();
+        assert_eq!(
+            get_hks_branch_dest(&mut h, 0x1000, &symtab_index),
+            Ok(0x1208)
+        );
+
+        let mut h = entries.next().unwrap();
+        assert_eq!(
+            get_hks_branch_dest(&mut h, 0x1000, &symtab_index),
+            Err(HksParseError::UnknownSymbol("missing_function".into()))
+        );
+    }
+
+    #[test]
+    fn test_expand_hks_list() {
+        let mut h = hook::hks::HksReader::new(std::io::Cursor::new(
+            "nops:\n    type: nop_list\n    addrs: 0x100,\n        0x108, my_site\n",
+        ))
+        .next()
+        .unwrap()
+        .unwrap();
+        let symtab_index = HashMap::from([("my_site".to_string(), 0x100200)]);
+        let layout = layout::Layout::new(vec![(0x100000..0x101000, layout::Segment::Text)]);
+        let addrs = h.get_list("addrs").unwrap();
+
+        let nop = hook::arm::make_nop_u32();
+        assert_eq!(
+            expand_hks_list(addrs.clone(), 0x100000, &symtab_index, &layout, None),
+            Ok(vec![(0x100100, nop), (0x100108, nop), (0x100200, nop)])
+        );
+        assert_eq!(
+            expand_hks_list(addrs, 0, &symtab_index, &layout, Some((true, 0x100400))),
+            Err("addrs[0]: Address 0x100 is outside of the code segments".to_string())
+        );
+
+        let branches = expand_hks_list(
+            vec!["0x100000".into(), "0x100004".into()],
+            0,
+            &symtab_index,
+            &layout,
+            Some((false, 0x100008)),
+        );
+        assert_eq!(
+            branches,
+            Ok(vec![(0x100000, 0xEA000000), (0x100004, 0xEAFFFFFF)])
+        );
+    }
+
+    #[test]
+    fn test_unresolved_hks_symbols() {
+        let entries = hook::hks::HksReader::new(std::io::Cursor::new(
+            "a:\n    type: branch\n    addr: 0x100000\n    func: my_function\n\
+             b:\n    type: branch\n    addr: missing_site\n    func: missing_function\n\
+             c:\n    type: symptr\n    addr: my_function\n    sym: missing_data\n\
+             d:\n    type: nop_list\n    addrs: 0x100004, my_function, missing_call\n",
+        ));
+        let symtab_index = HashMap::from([("my_function".to_string(), 0x100400)]);
+        let location = |line| HookLocation {
+            file: "hooks/main.hks".into(),
+            line,
+        };
+
+        assert_eq!(
+            unresolved_hks_symbols(
+                std::path::Path::new("hooks/main.hks"),
+                entries,
+                &symtab_index
+            ),
+            vec![
+                (location(5), "missing_site".to_string()),
+                (location(5), "missing_function".to_string()),
+                (location(9), "missing_data".to_string()),
+                (location(13), "missing_call".to_string()),
+            ]
+        );
+    }
+
+    fn test_target(build_dir: PathBuf) -> Target {
+        Target {
+            original_dir: "original".into(),
+            symbols_path: "symbols.ld".into(),
+            build_dir,
+            original_exefs_path: None,
+            luma: false,
+            manifest: false,
+            text_align: 4,
+            verify_reference: None,
+            link_scripts: Vec::new(),
+            split_extra: false,
+            fill: 0,
+            linker: "arm-none-eabi-gcc".into(),
+            cpu: config::CpuConfig::default(),
+            hook_prefix: config::HooksConfig::default().prefix,
+            hexdiff_path: None,
+        }
+    }
+
+    #[test]
+    fn test_hook_stage_stamp() {
+        let original_files = OriginalFiles::Loose {
+            code: "original/code.bin".into(),
+            exheader: "original/exheader.bin".into(),
+        };
+        let args = Args::default();
+        let stamp_options =
+            |target: &Target| hook_stage_inputs(&args, target, &original_files, &[]).options;
+
+        let plain = stamp_options(&test_target("build".into()));
+        let mut target = test_target("build".into());
+        target.luma = true;
+        assert_ne!(stamp_options(&target), plain);
+
+        let mut target = test_target("build".into());
+        target.manifest = true;
+        assert_ne!(stamp_options(&target), plain);
+
+        let limited_args = Args {
+            max_loader_usage: Some(80.0),
+            ..Args::default()
+        };
+        let target = test_target("build".into());
+        assert_ne!(
+            hook_stage_inputs(&limited_args, &target, &original_files, &[]).options,
+            plain
+        );
+
+        let mut target = test_target("build".into());
+        target.original_exefs_path = Some("original/exefs.bin".into());
+        assert_ne!(stamp_options(&target), plain);
+
+        // The repacked ExeFS has to exist for the target to be up to date
+        assert_eq!(
+            hook_stage_outputs(&target),
+            vec![
+                PathBuf::from("build/code.bin"),
+                PathBuf::from("build/exheader.bin"),
+                PathBuf::from("build/exefs.bin"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hks_symbols_are_kept() {
+        let hks_text = "a:\n    type: branch\n    addr: hooked_site\n    link: true\n    dest: dest_func\n\
+                        b:\n    type: nop_list\n    addrs: 0x100000, listed_site\n\
+                        c:\n    type: symbol\n    addr: 0x100004\n    sym: pointed_data\n";
+        let hks_symbols = hook::hks::HksReader::new(std::io::Cursor::new(hks_text))
+            .flat_map(|h| hks_symbol_values(&mut h.unwrap()))
+            .collect::<std::collections::HashSet<_>>();
+
+        let keep = gc::collect_keep_sections(
+            [
+                ("hooked_site", ".text.hooked_site"),
+                ("dest_func", ".text.dest_func"),
+                ("listed_site", ".text.listed_site"),
+                ("pointed_data", ".data.pointed_data"),
+                ("unused_func", ".text.unused_func"),
+            ],
+            &hks_symbols,
+            &HookPrefix::default(),
+        );
+        assert_eq!(
+            keep.into_iter().collect::<Vec<_>>(),
+            vec![
+                ".data.pointed_data",
+                ".text.dest_func",
+                ".text.hooked_site",
+                ".text.listed_site",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_hks_files() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let hooks_dir = tempdir.path().join("hooks");
+
+        assert_eq!(find_hks_files(&hooks_dir).unwrap(), Vec::<PathBuf>::new());
+
+        std::fs::create_dir_all(hooks_dir.join("sub.hks")).unwrap();
+        std::fs::write(hooks_dir.join("b.hks"), "").unwrap();
+        std::fs::write(hooks_dir.join("a.hks"), "").unwrap();
+        std::fs::write(hooks_dir.join("c.txt"), "").unwrap();
+        assert_eq!(
+            find_hks_files(&hooks_dir).unwrap(),
+            vec![hooks_dir.join("a.hks"), hooks_dir.join("b.hks")]
+        );
+
+        // base.hks first, then nested files in path order
+        std::fs::create_dir_all(hooks_dir.join("feature/nested")).unwrap();
+        std::fs::create_dir_all(hooks_dir.join(".git")).unwrap();
+        std::fs::write(hooks_dir.join("base.hks"), "").unwrap();
+        std::fs::write(hooks_dir.join("feature/base.hks"), "").unwrap();
+        std::fs::write(hooks_dir.join("feature/nested/a.hks"), "").unwrap();
+        std::fs::write(hooks_dir.join(".git/x.hks"), "").unwrap();
+        assert_eq!(
+            find_hks_files(&hooks_dir).unwrap(),
+            vec![
+                hooks_dir.join("base.hks"),
+                hooks_dir.join("a.hks"),
+                hooks_dir.join("b.hks"),
+                hooks_dir.join("feature/base.hks"),
+                hooks_dir.join("feature/nested/a.hks"),
+            ]
+        );
+
+        assert!(find_hks_files(hooks_dir.join("a.hks")).is_err());
+    }
+
+    #[test]
+    fn test_find_original_files() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let dir = tempdir.path();
+        let loose = OriginalFiles::Loose {
+            code: dir.join("code.bin"),
+            exheader: dir.join("exheader.bin"),
+        };
+
+        assert!(matches!(
+            OriginalFiles::find(&dir.join("missing")).unwrap(),
+            OriginalFiles::Loose { .. }
+        ));
+        assert_eq!(OriginalFiles::find(dir).unwrap(), loose);
+
+        std::fs::write(dir.join("game.CXI"), "").unwrap();
+        std::fs::write(dir.join("code.bin"), "").unwrap();
+        assert_eq!(
+            OriginalFiles::find(dir).unwrap(),
+            OriginalFiles::Ncch(dir.join("game.CXI"))
+        );
+
+        std::fs::write(dir.join("exheader.bin"), "").unwrap();
+        assert_eq!(OriginalFiles::find(dir).unwrap(), loose);
+        assert_eq!(loose.code_path(), dir.join("code.bin"));
+        assert_eq!(loose.exheader_path(), dir.join("exheader.bin"));
+
+        std::fs::remove_file(dir.join("exheader.bin")).unwrap();
+        std::fs::write(dir.join("00000000.app"), "").unwrap();
+        assert!(OriginalFiles::find(dir).is_err());
+    }
+
+    #[test]
+    fn test_write_atomic() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("code.bin");
+
+        assert_eq!(tmp_path(&path), tempdir.path().join("code.bin.tmp"));
+
+        std::fs::write(&path, [1, 2, 3, 4]).unwrap();
+        write_atomic(&path, [5, 6]).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), vec![5, 6]);
+        assert!(!tmp_path(&path).exists());
+
+        assert!(write_atomic(tempdir.path().join("missing/code.bin"), [0]).is_err());
+    }
+
+    #[test]
+    fn test_find_overlap() {
+        let mut ranges = vec![
+            (0x100010, 0x10, "c"),
+            (0x100000, 0x10, "a"),
+            (0x100020, 0x4, "d"),
+        ];
+        assert_eq!(find_overlap(&mut ranges), None);
+        assert_eq!(ranges[0].2, "a");
+
+        ranges.push((0x10001C, 0x8, "e"));
+        assert_eq!(
+            find_overlap(&mut ranges),
+            Some((&(0x100010, 0x10, "c"), &(0x10001C, 0x8, "e")))
+        );
+
+        let mut ranges = vec![(0x100000, 0x0, "a"), (0x100000, 0x4, "b")];
+        assert_eq!(find_overlap(&mut ranges), None);
+    }
+
+    #[test]
+    fn test_hook_location_error_display() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("hooks.hks");
+        std::fs::write(&path, "first:\n    type: patch\n").unwrap();
+
+        let location = HookLocation {
+            file: path.clone(),
+            line: 2,
+        };
+        let s = MakeError::HookLocation(location, "Missing key \"addr\"".into()).to_string();
+        assert_eq!(
+            s,
+            format!(
+                "{}:2: Missing key \"addr\"\n    2 |     type: patch",
+                path.display()
+            )
+        );
+
+        let missing = HookLocation {
+            file: tempdir.path().join("missing.hks"),
+            line: 1,
+        };
+        let s = MakeError::HookLocation(missing, "message".into()).to_string();
+        assert!(s.ends_with("missing.hks:1: message"));
+    }
+
+    /// Records every event as a line.
+    #[derive(Default)]
+    struct RecordingObserver(std::sync::Mutex<Vec<String>>);
+
+    impl RecordingObserver {
+        fn push(&self, line: String) {
+            self.0.lock().unwrap().push(line);
+        }
+    }
+
+    impl BuildObserver for RecordingObserver {
+        fn on_phase(&self, phase: &'static str) {
+            self.push(format!("phase {phase}"));
+        }
+
+        fn on_job_start(&self, job: &Job) {
+            self.push(format!("start {}", job.src_path.display()));
+        }
+
+        fn on_job_done(&self, job: &Job, result: std::result::Result<&str, &str>) {
+            let result = match result {
+                Ok(warnings) => format!("warnings={:?}", warnings.trim_end()),
+                Err(_) => "failed".to_string(),
+            };
+            self.push(format!("done {} {result}", job.src_path.display()));
+        }
+
+        fn on_warning(&self, message: &str) {
+            self.push(format!("warning {message}"));
+        }
+
+        fn on_hook_applied(
+            &self,
+            address: u32,
+            size: u32,
+            kind: &'static str,
+            locations: &[HookLocation],
+        ) {
+            self.push(format!(
+                "hook {kind} 0x{address:x} 0x{size:x} {}",
+                locations[0]
+            ));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_build_observer() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let observer = RecordingObserver::default();
+        let mut timings = Timings::default();
+        start_phase(&mut timings, &observer, "compile");
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let config = config::Config::default();
+        let args = Args::default();
+        let mut job_env = job_env(
+            tempdir.path(),
+            &Toolchain::default(),
+            &config,
+            &config::EnvConfig::default(),
+            &args,
+        );
+        let job = |name: &str| Job {
+            kind: JobKind::C,
+            src_path: format!("source/{name}.c").into(),
+            obj_path: tempdir.path().join(format!("build/{name}.c.o")),
+            dep_path: tempdir.path().join(format!("build/{name}.c.d")),
+            build_reason: Some(BuildReason::ObjMissing),
+        };
+
+        // Succeeds with a warning on stderr
+        let compiler = tempdir.path().join("cc");
+        std::fs::write(
+            &compiler,
+            "#!/bin/sh\necho 'main.c:1: warning: unused' >&2\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&compiler, std::fs::Permissions::from_mode(0o755)).unwrap();
+        job_env.compiler[JobKind::C] = compiler;
+        run_compile_job(&job_env, &job("main"), &observer).unwrap();
+        job_env.compiler[JobKind::C] = PathBuf::from("false");
+        run_compile_job(&job_env, &job("broken"), &observer).unwrap_err();
+
+        let location = HookLocation {
+            file: "hooks/main.hks".into(),
+            line: 3,
+        };
+        let mut writer = HookWriter::new(0x100000, vec![0; 0x40]);
+        writer.write(0x100000, [0; 4]).unwrap();
+        writer
+            .write_with_reason(
+                0x100010,
+                [0; 2],
+                HookWriteReason::Hook("patch", vec![location.clone()]),
+            )
+            .unwrap();
+        report_hooks(&writer, &observer);
+
+        assert_eq!(
+            *observer.0.lock().unwrap(),
+            vec![
+                "phase compile",
+                "start source/main.c",
+                "done source/main.c warnings=\"main.c:1: warning: unused\"",
+                "start source/broken.c",
+                "done source/broken.c failed",
+                "hook patch 0x100010 0x2 hooks/main.hks:3",
+            ]
+        );
+    }
 }
-"#;
@@ -0,0 +1,177 @@
+//! NCCH containers, e.g. a CXI dumped from a cartridge or the executable content of a CIA.
+
+use super::exefs::{Exefs, ExefsError};
+
+const MAGIC: &[u8; 4] = b"NCCH";
+const MAGIC_OFFSET: usize = 0x100;
+const EXHEADER_SIZE_OFFSET: usize = 0x180;
+const FLAGS_OFFSET: usize = 0x188;
+const EXEFS_OFFSET: usize = 0x1A0;
+/// The exheader directly follows the 0x200 bytes of NCCH header.
+const EXHEADER_OFFSET: usize = 0x200;
+/// Exheader including the access descriptor following it, the header only counts the former.
+const EXHEADER_SIZE: usize = 0x800;
+/// Offsets and sizes are in media units of this size shifted left by `flags[6]`.
+const MEDIA_UNIT_SIZE: usize = 0x200;
+/// Bit of `flags[7]` set if the contents are not encrypted.
+const FLAG_NO_CRYPTO: u8 = 0x4;
+
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum NcchError {
+    #[error("Not an NCCH")]
+    NotNcch,
+
+    #[error("NCCH is truncated")]
+    Truncated,
+
+    #[error("NCCH is encrypted, decrypt it first")]
+    Encrypted,
+
+    #[error("NCCH has no exheader, it is not a CXI")]
+    NoExheader,
+
+    #[error("NCCH has no ExeFS")]
+    NoExefs,
+
+    #[error("NCCH has an invalid media unit size of 0x200 << {0}")]
+    InvalidMediaUnit(u8),
+
+    #[error("{0}")]
+    Exefs(#[from] ExefsError),
+}
+
+/// Executable parts of a CXI.
+#[derive(Debug, PartialEq)]
+pub struct Ncch {
+    pub exheader: Vec<u8>,
+    pub exefs: Exefs,
+}
+
+fn read_u32(data: &[u8], offset: usize) -> usize {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap()) as usize
+}
+
+impl Ncch {
+    pub fn parse(data: &[u8]) -> Result<Self, NcchError> {
+        let header = data.get(..EXHEADER_OFFSET).ok_or(NcchError::Truncated)?;
+        if &header[MAGIC_OFFSET..MAGIC_OFFSET + 4] != MAGIC {
+            return Err(NcchError::NotNcch);
+        }
+
+        let flags = &header[FLAGS_OFFSET..FLAGS_OFFSET + 8];
+        if flags[7] & FLAG_NO_CRYPTO == 0 {
+            return Err(NcchError::Encrypted);
+        }
+        // Shifts dropping bits of the unit size can't come from a real NCCH
+        let media_unit = MEDIA_UNIT_SIZE
+            .checked_shl(flags[6] as u32)
+            .filter(|media_unit| media_unit >> flags[6] == MEDIA_UNIT_SIZE)
+            .ok_or(NcchError::InvalidMediaUnit(flags[6]))?;
+
+        if read_u32(header, EXHEADER_SIZE_OFFSET) == 0 {
+            return Err(NcchError::NoExheader);
+        }
+        let exheader = data
+            .get(EXHEADER_OFFSET..EXHEADER_OFFSET + EXHEADER_SIZE)
+            .ok_or(NcchError::Truncated)?;
+
+        // Regions too large to be addressed can't be in `data` either
+        let units = |offset| {
+            read_u32(header, offset)
+                .checked_mul(media_unit)
+                .ok_or(NcchError::Truncated)
+        };
+        let exefs_offset = units(EXEFS_OFFSET)?;
+        let exefs_size = units(EXEFS_OFFSET + 4)?;
+        if exefs_size == 0 {
+            return Err(NcchError::NoExefs);
+        }
+        let exefs_end = exefs_offset
+            .checked_add(exefs_size)
+            .ok_or(NcchError::Truncated)?;
+        let exefs = data
+            .get(exefs_offset..exefs_end)
+            .ok_or(NcchError::Truncated)?;
+
+        Ok(Self {
+            exheader: exheader.to_vec(),
+            exefs: Exefs::parse(exefs)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_ncch(media_unit_shift: u8) -> Vec<u8> {
+        let media_unit = MEDIA_UNIT_SIZE << media_unit_shift;
+        let exefs = Exefs {
+            files: vec![(".code".into(), vec![1; 0x234])],
+        }
+        .to_bytes();
+
+        let mut data = vec![0u8; EXHEADER_OFFSET];
+        data[MAGIC_OFFSET..MAGIC_OFFSET + 4].copy_from_slice(MAGIC);
+        data[EXHEADER_SIZE_OFFSET..EXHEADER_SIZE_OFFSET + 4]
+            .copy_from_slice(&0x400u32.to_le_bytes());
+        data[FLAGS_OFFSET + 6] = media_unit_shift;
+        data[FLAGS_OFFSET + 7] = FLAG_NO_CRYPTO;
+        data.extend_from_slice(&[2; EXHEADER_SIZE]);
+
+        data.resize(data.len().next_multiple_of(media_unit), 0);
+        let exefs_offset = (data.len() / media_unit) as u32;
+        let exefs_units = exefs.len().div_ceil(media_unit) as u32;
+        data[EXEFS_OFFSET..EXEFS_OFFSET + 4].copy_from_slice(&exefs_offset.to_le_bytes());
+        data[EXEFS_OFFSET + 4..EXEFS_OFFSET + 8].copy_from_slice(&exefs_units.to_le_bytes());
+        data.extend_from_slice(&exefs);
+        data.resize(data.len().next_multiple_of(media_unit), 0);
+        data
+    }
+
+    #[test]
+    fn test_parse() {
+        for media_unit_shift in [0, 1] {
+            let ncch = Ncch::parse(&test_ncch(media_unit_shift)).unwrap();
+            assert_eq!(ncch.exheader, vec![2; EXHEADER_SIZE]);
+            assert_eq!(ncch.exefs.get(".code"), Ok(&[1; 0x234][..]));
+        }
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        let data = test_ncch(0);
+        assert_eq!(Ncch::parse(&data[..0x100]), Err(NcchError::Truncated));
+        assert_eq!(Ncch::parse(&data[..0x600]), Err(NcchError::Truncated));
+
+        let mut encrypted = data.clone();
+        encrypted[FLAGS_OFFSET + 7] = 0;
+        assert_eq!(Ncch::parse(&encrypted), Err(NcchError::Encrypted));
+
+        let mut cfa = data.clone();
+        cfa[EXHEADER_SIZE_OFFSET] = 0;
+        cfa[EXHEADER_SIZE_OFFSET + 1] = 0;
+        assert_eq!(Ncch::parse(&cfa), Err(NcchError::NoExheader));
+
+        let mut not_ncch = data;
+        not_ncch[MAGIC_OFFSET] = 0;
+        assert_eq!(Ncch::parse(&not_ncch), Err(NcchError::NotNcch));
+    }
+
+    #[test]
+    fn test_parse_bogus_media_unit() {
+        let data = test_ncch(0);
+
+        for shift in [60, 0xFF] {
+            let mut bogus = data.clone();
+            bogus[FLAGS_OFFSET + 6] = shift;
+            assert_eq!(Ncch::parse(&bogus), Err(NcchError::InvalidMediaUnit(shift)));
+        }
+
+        // A valid shift whose offsets don't fit the address space
+        let mut bogus = data;
+        bogus[FLAGS_OFFSET + 6] = 31;
+        bogus[EXEFS_OFFSET..EXEFS_OFFSET + 8].copy_from_slice(&[0xFF; 8]);
+        assert_eq!(Ncch::parse(&bogus), Err(NcchError::Truncated));
+    }
+}
@@ -0,0 +1,38 @@
+use super::{hook::HookLocation, jobs::Job};
+
+/// Receives the events of a build, so front-ends can follow it without parsing the output.
+/// Every method does nothing by default.
+///
+/// Compile jobs run on the worker threads, their events may arrive from any thread.
+pub trait BuildObserver: Send + Sync {
+    /// A build phase started, named as in the `--time` report.
+    fn on_phase(&self, _phase: &'static str) {}
+
+    /// The current phase runs `total` counted steps, e.g. compile jobs or hook entries.
+    fn on_progress_start(&self, _total: usize) {}
+
+    /// One of the counted steps is done.
+    fn on_progress(&self) {}
+
+    /// The counted steps are over. Not reported if the build fails before.
+    fn on_progress_end(&self) {}
+
+    fn on_job_start(&self, _job: &Job) {}
+
+    /// A compile job finished with the compiler's warnings, or its error output if it failed.
+    fn on_job_done(&self, _job: &Job, _result: Result<&str, &str>) {}
+
+    /// A problem that doesn't fail the build, e.g. overlapping writes with `--allow-overlaps`.
+    fn on_warning(&self, _message: &str) {}
+
+    /// A hook wrote `size` bytes at `address`, reported once all hooks of a target are applied
+    /// without overlaps that fail the build.
+    fn on_hook_applied(
+        &self,
+        _address: u32,
+        _size: u32,
+        _kind: &'static str,
+        _locations: &[HookLocation],
+    ) {
+    }
+}
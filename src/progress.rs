@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::thread::ThreadId;
+
+use super::{interrupt, jobs::Job, observer::BuildObserver};
+
+/// Terminal output of the CLI: a progress bar for the counted steps of a phase and a spinner
+/// per compile worker. Compile errors are printed even if the bars are hidden.
+pub struct ProgressObserver {
+    root: indicatif::MultiProgress,
+    quiet: bool,
+    bar: Mutex<Option<indicatif::ProgressBar>>,
+    /// Spinners of the compile workers, by worker thread
+    spinners: Mutex<HashMap<ThreadId, indicatif::ProgressBar>>,
+}
+
+impl ProgressObserver {
+    pub fn new(quiet: bool) -> Self {
+        let root = match quiet {
+            true => {
+                indicatif::MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden())
+            }
+            false => indicatif::MultiProgress::new(),
+        };
+
+        Self {
+            root,
+            quiet,
+            bar: Mutex::new(None),
+            spinners: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Removes the bar and spinners, also those of a phase an error cut short.
+    pub fn clear(&self) {
+        if let Some(bar) = self.bar.lock().unwrap().take() {
+            bar.finish_and_clear();
+            self.root.remove(&bar);
+        }
+        for (_, spinner) in self.spinners.lock().unwrap().drain() {
+            spinner.finish_and_clear();
+            self.root.remove(&spinner);
+        }
+        self.root.clear().ok();
+    }
+}
+
+impl BuildObserver for ProgressObserver {
+    fn on_progress_start(&self, total: usize) {
+        self.clear();
+
+        let bar = indicatif::ProgressBar::new(total as u64).with_style(
+            indicatif::ProgressStyle::with_template(
+                "{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {pos}/{len} ({eta})",
+            )
+            .expect("Progress style template should be valid")
+            .progress_chars("=>."),
+        );
+        let bar = self.root.add(bar);
+        bar.inc(0);
+        *self.bar.lock().unwrap() = Some(bar);
+    }
+
+    fn on_progress(&self) {
+        if let Some(bar) = self.bar.lock().unwrap().as_ref() {
+            bar.inc(1);
+        }
+    }
+
+    fn on_progress_end(&self) {
+        self.clear();
+    }
+
+    fn on_job_start(&self, job: &Job) {
+        let mut spinners = self.spinners.lock().unwrap();
+        let spinner = spinners
+            .entry(std::thread::current().id())
+            .or_insert_with(|| {
+                let spinner = self.root.add(indicatif::ProgressBar::new_spinner());
+                spinner.set_style(
+                    indicatif::style::ProgressStyle::default_spinner()
+                        .template("{spinner:.green} {msg}")
+                        .expect("Progress style template should be valid"),
+                );
+                spinner.enable_steady_tick(std::time::Duration::from_millis(100));
+                spinner
+            });
+        spinner.set_message(job.src_path.display().to_string());
+    }
+
+    fn on_job_done(&self, _job: &Job, result: Result<&str, &str>) {
+        match result {
+            Ok(warnings) if !warnings.is_empty() && !self.quiet => {
+                self.root.suspend(|| println!("{}", warnings.trim_end()));
+            }
+            Ok(_) => {}
+            // The compiler got the Ctrl-C too, its failure isn't worth reporting
+            Err(_) if interrupt::interrupted() => {}
+            Err(error) => self.root.suspend(|| println!("{}", error.trim_end())),
+        }
+    }
+
+    fn on_warning(&self, message: &str) {
+        self.root.suspend(|| log::warn!("{message}"));
+    }
+}
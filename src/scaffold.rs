@@ -0,0 +1,144 @@
+use std::path::{Path, PathBuf};
+
+use super::{config::CONFIG_FILE_NAME, APP_NAME, APP_VERSION};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ScaffoldError {
+    #[error("{0} already exists and is not empty")]
+    NotEmpty(PathBuf),
+
+    #[error("Creating {0} failed: {1}")]
+    Io(PathBuf, std::io::Error),
+}
+
+const ORIGINAL_README: &str = "\
+Put the original code.bin and exheader.bin of the game here, or a decrypted CXI
+to have them extracted from it.
+
+They are never modified, the patched files are written to the build directory.
+";
+
+const MAIN_CPP: &str = "\
+#include \"magwi.h\"
+
+// Called before the instruction at the given address of the original code runs.
+// Replace the address with one of your game and uncomment it.
+//
+// mw_pre(0x100000) void example_pre_hook()
+// {
+// }
+";
+
+const SYMBOLS_LD: &str = "\
+/* Addresses of functions and data of the original code, callable from custom code. */
+/* example_function = 0x00100000; */
+";
+
+const CONFIG: &str = "\
+# Declare variants to build the same code for several versions of the game, each with its
+# own original files and symbols.
+#
+# [variants.eur]
+# original = \"original/eur\"
+# symbols = \"symbols/eur.ld\"
+
+[source]
+ignore = []
+";
+
+/// Files of a new project. The hook file example is generated to require this version.
+fn files() -> Vec<(&'static str, String)> {
+    vec![
+        ("original/README.md", ORIGINAL_README.to_string()),
+        ("source/main.cpp", MAIN_CPP.to_string()),
+        (
+            "include/magwi.h",
+            include_str!("../resources/include/magwi.h").to_string(),
+        ),
+        (
+            "hooks/example.hks",
+            format!(
+                "\
+# Each entry is a name followed by indented keys, run `{APP_NAME} schema` for all types.
+# All .hks files in hooks/ and its subdirectories are applied, hooks/base.hks first and the
+# others in path order. Writing to the same address twice is an error unless overlaps are
+# allowed, then the later file wins.
+
+require:
+    type: require
+    version: {APP_VERSION}
+
+# example_branch:
+#     type: branch
+#     addr: 0x100000
+#     link: true
+#     func: example_function
+"
+            ),
+        ),
+        ("symbols.ld", SYMBOLS_LD.to_string()),
+        (CONFIG_FILE_NAME, CONFIG.to_string()),
+    ]
+}
+
+/// Creates the skeleton of a project in `dir`, which has to be missing or empty.
+pub fn create(dir: impl AsRef<Path>) -> Result<(), ScaffoldError> {
+    let dir = dir.as_ref();
+    let io_error = |path: &Path| {
+        let path = path.to_path_buf();
+        move |e| ScaffoldError::Io(path, e)
+    };
+
+    match std::fs::read_dir(dir) {
+        Ok(mut entries) => {
+            if entries.next().is_some() {
+                return Err(ScaffoldError::NotEmpty(dir.to_path_buf()));
+            }
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(ScaffoldError::Io(dir.to_path_buf(), e)),
+    }
+
+    for (name, content) in files() {
+        let path = dir.join(name);
+        let parent = path
+            .parent()
+            .expect("scaffold files are in the project directory");
+        std::fs::create_dir_all(parent).map_err(io_error(parent))?;
+        std::fs::write(&path, content).map_err(io_error(&path))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let dir = tempdir.path().join("mygame");
+
+        create(&dir).unwrap();
+
+        for name in ["original", "source", "include", "hooks"] {
+            assert!(dir.join(name).is_dir());
+        }
+        assert!(dir.join("symbols.ld").is_file());
+        assert!(crate::config::Config::load(&dir).is_ok());
+
+        let types = crate::hook::hks::open_file(dir.join("hooks/example.hks"))
+            .unwrap()
+            .map(|h| h.unwrap().peek("type").unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(types, vec!["require"]);
+
+        assert!(matches!(create(&dir), Err(ScaffoldError::NotEmpty(_))));
+
+        // An existing empty directory is fine
+        let empty = tempdir.path().join("empty");
+        std::fs::create_dir(&empty).unwrap();
+        create(&empty).unwrap();
+    }
+}
@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+/// Identifies the ELF file a cache was created from by its modification time and size.
+fn elf_key(elf_path: impl AsRef<Path>) -> Option<String> {
+    let meta = std::fs::metadata(elf_path).ok()?;
+    let mtime = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some(format!(
+        "{}.{:09} {}",
+        mtime.as_secs(),
+        mtime.subsec_nanos(),
+        meta.len()
+    ))
+}
+
+/// Loads a symbol index previously stored with `save`.
+/// Returns `None` if there is no cache or if the ELF file changed since it was written.
+pub fn load(
+    cache_path: impl AsRef<Path>,
+    elf_path: impl AsRef<Path>,
+) -> Option<HashMap<String, u32>> {
+    let key = elf_key(elf_path)?;
+
+    let file = std::fs::File::open(cache_path).ok()?;
+    let mut lines = std::io::BufReader::new(file).lines();
+
+    if lines.next()?.ok()? != key {
+        return None;
+    }
+
+    let mut index = HashMap::new();
+
+    for line in lines {
+        let line = line.ok()?;
+        let (address, name) = line.split_once(' ')?;
+        index.insert(name.to_string(), u32::from_str_radix(address, 16).ok()?);
+    }
+
+    Some(index)
+}
+
+/// Stores a symbol index alongside the identity of the ELF file it was created from.
+pub fn save(
+    cache_path: impl AsRef<Path>,
+    elf_path: impl AsRef<Path>,
+    index: &HashMap<String, u32>,
+) -> std::io::Result<()> {
+    let key = elf_key(elf_path).ok_or(std::io::ErrorKind::NotFound)?;
+
+    let mut file = std::io::BufWriter::new(std::fs::File::create(cache_path)?);
+    writeln!(file, "{key}")?;
+
+    for (name, address) in index {
+        writeln!(file, "{address:08x} {name}")?;
+    }
+
+    file.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use filetime::set_file_mtime;
+
+    #[test]
+    fn test_save_load() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let elf_path = tempdir.path().join("out.elf");
+        let cache_path = tempdir.path().join("symbols.cache");
+
+        assert_eq!(load(&cache_path, &elf_path), None);
+
+        std::fs::write(&elf_path, "elf").unwrap();
+        set_file_mtime(&elf_path, filetime::FileTime::from_unix_time(1000, 0)).unwrap();
+
+        let index: HashMap<String, u32> = [
+            ("func".to_string(), 0x100000),
+            ("ns::func(int, char)".to_string(), 0x100004),
+        ]
+        .into_iter()
+        .collect();
+
+        save(&cache_path, &elf_path, &index).unwrap();
+        assert_eq!(load(&cache_path, &elf_path), Some(index));
+
+        set_file_mtime(&elf_path, filetime::FileTime::from_unix_time(2000, 0)).unwrap();
+        assert_eq!(load(&cache_path, &elf_path), None);
+    }
+}
@@ -0,0 +1,295 @@
+use std::collections::{BTreeSet, HashMap};
+use std::io::Write;
+use std::path::Path;
+
+/// Demangles a C++ symbol name. Only names with a mangling prefix are handed to the
+/// demangler, as most symbols of a large ELF are not mangled and rejecting them is slow.
+pub fn demangle(name: &str) -> Option<String> {
+    let maybe_mangled =
+        name.starts_with("_Z") || name.starts_with("__Z") || name.starts_with("_GLOBAL_");
+    if !maybe_mangled {
+        return None;
+    }
+
+    cpp_demangle::Symbol::new(name)
+        .ok()
+        .map(|sym| sym.to_string())
+}
+
+/// Builds the index of symbol addresses by name, including demangled names. `symbols`
+/// yields name, address and whether the symbol is global.
+///
+/// Names defined more than once resolve independently of the symbol order: global symbols
+/// win over local ones, otherwise the lowest address wins. Names left with differing
+/// addresses are returned as ambiguous.
+pub fn index_symbols<'a>(
+    symbols: impl IntoIterator<Item = (&'a str, u32, bool)>,
+) -> (HashMap<String, u32>, BTreeSet<String>) {
+    let mut index: HashMap<String, (u32, bool)> = HashMap::new();
+    let mut ambiguous = BTreeSet::new();
+
+    let mut insert = |name: String, address: u32, global: bool| {
+        let Some((other_address, other_global)) = index.get_mut(&name) else {
+            index.insert(name, (address, global));
+            return;
+        };
+
+        if global && !*other_global {
+            *other_address = address;
+            *other_global = true;
+            ambiguous.remove(&name);
+        } else if global == *other_global && address != *other_address {
+            *other_address = address.min(*other_address);
+            ambiguous.insert(name);
+        }
+    };
+
+    for (name, address, global) in symbols {
+        insert(name.to_string(), address, global);
+        if let Some(demangled) = demangle(name) {
+            insert(demangled, address, global);
+        }
+    }
+
+    let index = index
+        .into_iter()
+        .map(|(name, (address, _))| (name, address))
+        .collect();
+    (index, ambiguous)
+}
+
+/// Symbols at or above `min_address`, sorted by address and then name.
+fn custom_symbols(index: &HashMap<String, u32>, min_address: u32) -> Vec<(u32, &str)> {
+    let mut symbols = index
+        .iter()
+        .filter(|(_, address)| **address >= min_address)
+        .map(|(name, address)| (*address, name.as_str()))
+        .collect::<Vec<_>>();
+    symbols.sort();
+    symbols
+}
+
+/// Writes an `address name` list of the custom code symbols, one per line.
+/// Names may contain spaces, everything after the first space is the name.
+pub fn write_sym(
+    path: impl AsRef<Path>,
+    index: &HashMap<String, u32>,
+    min_address: u32,
+) -> std::io::Result<()> {
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+
+    for (address, name) in custom_symbols(index, min_address) {
+        writeln!(file, "{address:08x} {name}")?;
+    }
+
+    file.flush()
+}
+
+/// Writes a GDB script loading the symbols of the linked ELF. The ELF is linked at the
+/// runtime addresses, so it is added without offset. The symbol list is included as
+/// comments for reference.
+pub fn write_gdb(
+    path: impl AsRef<Path>,
+    elf_path: impl AsRef<Path>,
+    index: &HashMap<String, u32>,
+    min_address: u32,
+) -> std::io::Result<()> {
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+
+    writeln!(
+        file,
+        "add-symbol-file \"{}\" -o 0",
+        elf_path.as_ref().display()
+    )?;
+    writeln!(file)?;
+
+    for (address, name) in custom_symbols(index, min_address) {
+        writeln!(file, "# 0x{address:08x} {name}")?;
+    }
+
+    file.flush()
+}
+
+/// Where a listed symbol is defined.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum SymbolOrigin {
+    /// Symbols script of the original code
+    Original,
+    /// Custom code of the last build
+    Custom,
+}
+
+impl std::fmt::Display for SymbolOrigin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            SymbolOrigin::Original => "original",
+            SymbolOrigin::Custom => "custom",
+        })
+    }
+}
+
+/// Reads the `name = address;` assignments of a symbols linker script, optionally wrapped in
+/// `PROVIDE`. Other statements, e.g. with expressions, are skipped.
+pub fn parse_linker_symbols(script: &str) -> Vec<(u32, String)> {
+    let mut text = String::new();
+    let mut rest = script;
+    while let Some((before, after)) = rest.split_once("/*") {
+        text.push_str(before);
+        rest = after.split_once("*/").map_or("", |(_, after)| after);
+    }
+    text.push_str(rest);
+
+    text.split(';')
+        .filter_map(|statement| {
+            let statement = statement.trim();
+            let statement = ["PROVIDE_HIDDEN(", "PROVIDE("]
+                .iter()
+                .find_map(|provide| statement.strip_prefix(provide)?.strip_suffix(')'))
+                .unwrap_or(statement);
+            let (name, value) = statement.split_once('=')?;
+            let address = super::hook::parse_address(value.trim()).ok()?;
+            Some((address, name.trim().to_string()))
+        })
+        .collect()
+}
+
+/// Reads an `address name` list as written by [`write_sym`].
+pub fn parse_sym(s: &str) -> Vec<(u32, String)> {
+    s.lines()
+        .filter_map(|line| {
+            let (address, name) = line.split_once(' ')?;
+            Some((u32::from_str_radix(address, 16).ok()?, name.to_string()))
+        })
+        .collect()
+}
+
+/// Merges original and custom symbols, sorted by address and then name, without duplicates.
+pub fn merge_symbols(
+    original: Vec<(u32, String)>,
+    custom: Vec<(u32, String)>,
+) -> Vec<(u32, String, SymbolOrigin)> {
+    let mut symbols = original
+        .into_iter()
+        .map(|(address, name)| (address, name, SymbolOrigin::Original))
+        .chain(
+            custom
+                .into_iter()
+                .map(|(address, name)| (address, name, SymbolOrigin::Custom)),
+        )
+        .collect::<Vec<_>>();
+    symbols.sort();
+    // An original symbol also linked into the custom code is listed once, as original
+    symbols.dedup_by(|b, a| a.0 == b.0 && a.1 == b.1);
+    symbols
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_demangle() {
+        assert_eq!(demangle("_ZN2ns4funcEv").as_deref(), Some("ns::func()"));
+        assert_eq!(demangle("main"), None);
+        assert_eq!(demangle("__mw_text_end"), None);
+    }
+
+    #[test]
+    fn test_index_symbols() {
+        let (index, ambiguous) = index_symbols([
+            ("counter", 0x300010, false),
+            ("counter", 0x300000, false),
+            ("update", 0x300100, false),
+            ("update", 0x300200, true),
+            ("same", 0x300300, false),
+            ("same", 0x300300, false),
+            ("_ZN2ns4funcEv", 0x300400, true),
+            // Absolute symbol of a NOLOAD section placed by a user linker script
+            ("mod_scratch", 0x08000000, true),
+        ]);
+
+        assert_eq!(index["counter"], 0x300000);
+        assert_eq!(index["update"], 0x300200);
+        assert_eq!(index["same"], 0x300300);
+        assert_eq!(index["ns::func()"], 0x300400);
+        assert_eq!(index["mod_scratch"], 0x08000000);
+        assert_eq!(ambiguous, BTreeSet::from(["counter".to_string()]));
+
+        // The order of the symbols doesn't matter
+        let (reversed, _) = index_symbols([
+            ("update", 0x300200, true),
+            ("update", 0x300100, false),
+            ("counter", 0x300000, false),
+            ("counter", 0x300010, false),
+        ]);
+        assert_eq!(reversed["counter"], 0x300000);
+        assert_eq!(reversed["update"], 0x300200);
+    }
+
+    #[test]
+    fn test_parse_linker_symbols() {
+        let script = "/* Addresses of the original code */\n\
+            func_a = 0x00100000;\n\
+            /* func_b = 0x00100100; */\n\
+            PROVIDE(func_c = 0x00100200); data_d = 1048576;\n\
+            computed = func_a + 4;\n";
+        assert_eq!(
+            parse_linker_symbols(script),
+            vec![
+                (0x100000, "func_a".to_string()),
+                (0x100200, "func_c".to_string()),
+                (0x100000, "data_d".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_symbols() {
+        let custom = parse_sym("00300000 my_hook\n00300010 ns::func()\n00100000 func_a\n");
+        assert_eq!(custom[1], (0x300010, "ns::func()".to_string()));
+
+        assert_eq!(
+            merge_symbols(
+                vec![(0x100100, "func_b".into()), (0x100000, "func_a".into())],
+                custom
+            ),
+            vec![
+                (0x100000, "func_a".to_string(), SymbolOrigin::Original),
+                (0x100100, "func_b".to_string(), SymbolOrigin::Original),
+                (0x300000, "my_hook".to_string(), SymbolOrigin::Custom),
+                (0x300010, "ns::func()".to_string(), SymbolOrigin::Custom),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_write() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let sym_path = tempdir.path().join("out.sym");
+        let gdb_path = tempdir.path().join("symbols.gdb");
+
+        let index: HashMap<String, u32> = [
+            ("orig".to_string(), 0x100000),
+            ("b".to_string(), 0x200004),
+            ("ns::a(int)".to_string(), 0x200000),
+            ("a_alias".to_string(), 0x200000),
+        ]
+        .into_iter()
+        .collect();
+
+        write_sym(&sym_path, &index, 0x200000).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&sym_path).unwrap(),
+            "00200000 a_alias\n00200000 ns::a(int)\n00200004 b\n"
+        );
+
+        write_gdb(&gdb_path, "/project/build/out.elf", &index, 0x200000).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&gdb_path).unwrap(),
+            "add-symbol-file \"/project/build/out.elf\" -o 0\n\n\
+             # 0x00200000 a_alias\n\
+             # 0x00200000 ns::a(int)\n\
+             # 0x00200004 b\n"
+        );
+    }
+}
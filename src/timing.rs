@@ -0,0 +1,120 @@
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// Wall clock time spent in each build phase, reported with `--time`.
+///
+/// Phases are timed back to back, starting a phase ends the previous one. Phases entered
+/// multiple times, e.g. once per variant, are summed up.
+#[derive(Debug, Default)]
+pub struct Timings {
+    phases: Vec<(&'static str, Duration)>,
+    current: Option<(&'static str, Instant)>,
+    jobs: Vec<(PathBuf, Duration)>,
+}
+
+impl Timings {
+    pub fn start(&mut self, phase: &'static str) {
+        self.stop();
+        self.current = Some((phase, Instant::now()));
+    }
+
+    pub fn stop(&mut self) {
+        if let Some((phase, start)) = self.current.take() {
+            self.add(phase, start.elapsed());
+        }
+    }
+
+    fn add(&mut self, phase: &'static str, duration: Duration) {
+        match self.phases.iter_mut().find(|(name, _)| *name == phase) {
+            Some((_, total)) => *total += duration,
+            None => self.phases.push((phase, duration)),
+        }
+    }
+
+    pub fn add_job(&mut self, src_path: PathBuf, duration: Duration) {
+        self.jobs.push((src_path, duration));
+    }
+
+    /// Formats the phase breakdown followed by the `slowest` slowest compile jobs.
+    pub fn report(&self, slowest: usize) -> String {
+        let mut report = String::new();
+        let width = self
+            .phases
+            .iter()
+            .map(|(name, _)| name.len())
+            .chain(["total".len()])
+            .max()
+            .unwrap_or(0);
+
+        for (name, duration) in &self.phases {
+            report.push_str(&format!(
+                "  {name:<width$}  {:>8.3}s\n",
+                duration.as_secs_f64()
+            ));
+        }
+        let total: Duration = self.phases.iter().map(|(_, duration)| *duration).sum();
+        report.push_str(&format!(
+            "  {:<width$}  {:>8.3}s\n",
+            "total",
+            total.as_secs_f64()
+        ));
+
+        let mut jobs = self.jobs.iter().collect::<Vec<_>>();
+        jobs.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        if !jobs.is_empty() {
+            report.push_str("Slowest compile jobs:\n");
+        }
+        for (src_path, duration) in jobs.into_iter().take(slowest) {
+            report.push_str(&format!(
+                "  {:>8.3}s  {}\n",
+                duration.as_secs_f64(),
+                src_path.display()
+            ));
+        }
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let mut timings = Timings::default();
+        timings.add("compile", Duration::from_millis(1500));
+        timings.add("link", Duration::from_millis(250));
+        timings.add("compile", Duration::from_millis(500));
+        timings.add_job("source/a.cpp".into(), Duration::from_millis(200));
+        timings.add_job("source/b.cpp".into(), Duration::from_millis(1200));
+        timings.add_job("source/c.cpp".into(), Duration::from_millis(600));
+
+        assert_eq!(
+            timings.report(2),
+            "  compile     2.000s\n\
+             \x20 link        0.250s\n\
+             \x20 total       2.250s\n\
+             Slowest compile jobs:\n\
+             \x20    1.200s  source/b.cpp\n\
+             \x20    0.600s  source/c.cpp\n"
+        );
+    }
+
+    #[test]
+    fn test_start_stop() {
+        let mut timings = Timings::default();
+        timings.start("scan");
+        timings.start("compile");
+        timings.stop();
+        timings.stop();
+
+        let names = timings
+            .phases
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["scan", "compile"]);
+        assert!(timings.current.is_none());
+    }
+}
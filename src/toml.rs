@@ -0,0 +1,251 @@
+use std::collections::BTreeMap;
+
+/// Value of the TOML subset used by magwi.toml.
+#[derive(Debug, PartialEq, Clone)]
+pub enum TomlValue {
+    String(String),
+    Integer(i64),
+    Bool(bool),
+    Array(Vec<TomlValue>),
+}
+
+impl TomlValue {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            TomlValue::String(_) => "string",
+            TomlValue::Integer(_) => "integer",
+            TomlValue::Bool(_) => "bool",
+            TomlValue::Array(_) => "array",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum TomlError {
+    #[error("Line {0}: Invalid table header: {1}")]
+    InvalidTable(usize, String),
+
+    #[error("Line {0}: Expected key = value: {1}")]
+    InvalidKeyValue(usize, String),
+
+    #[error("Line {0}: Invalid value: {1}")]
+    InvalidValue(usize, String),
+
+    #[error("Line {0}: Duplicate key: {1}")]
+    DuplicateKey(usize, String),
+}
+
+/// Keys of a table with the line they were defined on.
+pub type TomlTable = BTreeMap<String, (usize, TomlValue)>;
+
+/// Parses a TOML subset: `[dotted.table]` headers, `key = value` pairs with string, integer,
+/// bool and single line array values, and `#` comments.
+/// Tables are returned by their dotted name, keys before the first header are in table "".
+pub fn parse(s: &str) -> Result<BTreeMap<String, TomlTable>, TomlError> {
+    let mut tables = BTreeMap::new();
+    let mut current = String::new();
+    tables.insert(current.clone(), TomlTable::new());
+
+    for (i, line) in s.lines().enumerate() {
+        let line_number = i + 1;
+        let line = strip_comment(line).trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(header) = line.strip_prefix('[') {
+            let name = header
+                .strip_suffix(']')
+                .map(|n| n.trim())
+                .filter(|n| !n.is_empty() && n.split('.').all(is_bare_key))
+                .ok_or_else(|| TomlError::InvalidTable(line_number, line.to_string()))?;
+
+            current = name.to_string();
+            tables.entry(current.clone()).or_default();
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(TomlError::InvalidKeyValue(line_number, line.to_string()));
+        };
+
+        let key = key.trim();
+        if !is_bare_key(key) {
+            return Err(TomlError::InvalidKeyValue(line_number, line.to_string()));
+        }
+
+        let value = parse_value(value.trim())
+            .ok_or_else(|| TomlError::InvalidValue(line_number, value.trim().to_string()))?;
+
+        let table = tables.get_mut(&current).expect("current table exists");
+        if table.contains_key(key) {
+            return Err(TomlError::DuplicateKey(line_number, key.to_string()));
+        }
+        table.insert(key.to_string(), (line_number, value));
+    }
+
+    Ok(tables)
+}
+
+fn is_bare_key(s: &str) -> bool {
+    !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+
+    line
+}
+
+fn parse_value(s: &str) -> Option<TomlValue> {
+    let (value, rest) = parse_value_prefix(s)?;
+    rest.trim().is_empty().then_some(value)
+}
+
+/// Parses a value at the start of `s` and returns it with the remaining input.
+fn parse_value_prefix(s: &str) -> Option<(TomlValue, &str)> {
+    let s = s.trim_start();
+
+    if let Some(rest) = s.strip_prefix('"') {
+        let mut value = String::new();
+        let mut chars = rest.char_indices();
+
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => return Some((TomlValue::String(value), &rest[i + 1..])),
+                '\\' => value.push(match chars.next()?.1 {
+                    'n' => '\n',
+                    't' => '\t',
+                    '"' => '"',
+                    '\\' => '\\',
+                    _ => return None,
+                }),
+                c => value.push(c),
+            }
+        }
+
+        return None;
+    }
+
+    if let Some(mut rest) = s.strip_prefix('[') {
+        let mut values = Vec::new();
+
+        loop {
+            rest = rest.trim_start();
+            if let Some(rest) = rest.strip_prefix(']') {
+                return Some((TomlValue::Array(values), rest));
+            }
+
+            let (value, after) = parse_value_prefix(rest)?;
+            values.push(value);
+
+            rest = after.trim_start();
+            if let Some(after) = rest.strip_prefix(',') {
+                rest = after;
+            } else if !rest.starts_with(']') {
+                return None;
+            }
+        }
+    }
+
+    let end = s.find([',', ']']).unwrap_or(s.len());
+    let (token, rest) = (s[..end].trim_end(), &s[end..]);
+
+    let value = match token {
+        "true" => TomlValue::Bool(true),
+        "false" => TomlValue::Bool(false),
+        _ => {
+            let digits = token.replace('_', "");
+            let (negative, digits) = match digits.strip_prefix('-') {
+                Some(d) => (true, d.to_string()),
+                None => (false, digits.trim_start_matches('+').to_string()),
+            };
+            let value = match digits.strip_prefix("0x") {
+                Some(hex) => i64::from_str_radix(hex, 16).ok()?,
+                None => digits.parse::<i64>().ok()?,
+            };
+            TomlValue::Integer(if negative { -value } else { value })
+        }
+    };
+
+    Some((value, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let tables = parse(
+            r#"
+name = "mod" # comment
+[variants.eur]
+original = "original/eur#1"
+size = 0x1_000
+offsets = [1, -2, "a\"b"]
+enabled = true
+
+[empty]
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(tables[""]["name"], (2, TomlValue::String("mod".into())));
+
+        let eur = &tables["variants.eur"];
+        assert_eq!(
+            eur["original"].1,
+            TomlValue::String("original/eur#1".into())
+        );
+        assert_eq!(eur["size"].1, TomlValue::Integer(0x1000));
+        assert_eq!(
+            eur["offsets"].1,
+            TomlValue::Array(vec![
+                TomlValue::Integer(1),
+                TomlValue::Integer(-2),
+                TomlValue::String("a\"b".into()),
+            ])
+        );
+        assert_eq!(eur["enabled"].1, TomlValue::Bool(true));
+        assert!(tables["empty"].is_empty());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            parse("[a.]"),
+            Err(TomlError::InvalidTable(1, "[a.]".into()))
+        );
+        assert_eq!(
+            parse("\nkey"),
+            Err(TomlError::InvalidKeyValue(2, "key".into()))
+        );
+        assert_eq!(
+            parse("key = \"open"),
+            Err(TomlError::InvalidValue(1, "\"open".into()))
+        );
+        assert_eq!(
+            parse("key = [1 2]"),
+            Err(TomlError::InvalidValue(1, "[1 2]".into()))
+        );
+        assert_eq!(
+            parse("a = 1\na = 2"),
+            Err(TomlError::DuplicateKey(2, "a".into()))
+        );
+    }
+}
@@ -0,0 +1,133 @@
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+/// Executable used to detect the toolchain, the other tools are expected next to it.
+const PROBE_TOOL: &str = "arm-none-eabi-gcc";
+
+/// Location of the ARM toolchain executables.
+/// The compiler driver finds its own headers and libraries relative to where it's installed,
+/// so only the executables have to be resolved.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Toolchain {
+    /// Directory of the executables, `None` if they are run from PATH
+    pub bin_dir: Option<PathBuf>,
+}
+
+fn find_tool(dir: &Path, name: &str) -> Option<PathBuf> {
+    let path = dir.join(format!("{name}{}", std::env::consts::EXE_SUFFIX));
+    path.is_file().then_some(path)
+}
+
+impl Toolchain {
+    /// Prefers the toolchain on PATH and falls back to `$DEVKITARM/bin`, then to
+    /// `$DEVKITPRO/devkitARM/bin`. If none of them has it, PATH is used anyway so spawning
+    /// reports the missing compiler.
+    pub fn resolve() -> Self {
+        Self::resolve_from(
+            std::env::var_os("PATH").as_deref(),
+            std::env::var_os("DEVKITARM").as_deref(),
+            std::env::var_os("DEVKITPRO").as_deref(),
+        )
+    }
+
+    fn resolve_from(
+        path: Option<&OsStr>,
+        devkitarm: Option<&OsStr>,
+        devkitpro: Option<&OsStr>,
+    ) -> Self {
+        let on_path = path
+            .into_iter()
+            .flat_map(std::env::split_paths)
+            .any(|dir| find_tool(&dir, PROBE_TOOL).is_some());
+        if on_path {
+            return Self::default();
+        }
+
+        let candidates = [
+            devkitarm.map(|dir| Path::new(dir).join("bin")),
+            devkitpro.map(|dir| Path::new(dir).join("devkitARM").join("bin")),
+        ];
+
+        Self {
+            bin_dir: candidates
+                .into_iter()
+                .flatten()
+                .find(|dir| find_tool(dir, PROBE_TOOL).is_some()),
+        }
+    }
+
+    /// Path of the executable `name`, e.g. `arm-none-eabi-g++`.
+    pub fn tool(&self, name: &str) -> PathBuf {
+        match &self.bin_dir {
+            Some(dir) => dir.join(name),
+            None => PathBuf::from(name),
+        }
+    }
+}
+
+impl std::fmt::Display for Toolchain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.bin_dir {
+            Some(dir) => write!(f, "{}", dir.display()),
+            None => f.write_str("PATH"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn install(dir: &Path) {
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(
+            dir.join(format!("{PROBE_TOOL}{}", std::env::consts::EXE_SUFFIX)),
+            "",
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_resolve() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path_dir = tempdir.path().join("usr/bin");
+        let devkitpro = tempdir.path().join("devkitpro");
+        let devkitarm = tempdir.path().join("devkitarm");
+        install(&path_dir);
+        install(&devkitpro.join("devkitARM/bin"));
+
+        let path = std::env::join_paths([tempdir.path().join("empty"), path_dir]).unwrap();
+        assert_eq!(
+            Toolchain::resolve_from(Some(&path), None, Some(devkitpro.as_os_str())),
+            Toolchain::default()
+        );
+
+        // Not installed where $DEVKITARM points to
+        assert_eq!(
+            Toolchain::resolve_from(
+                None,
+                Some(devkitarm.as_os_str()),
+                Some(devkitpro.as_os_str())
+            )
+            .bin_dir,
+            Some(devkitpro.join("devkitARM/bin"))
+        );
+
+        install(&devkitarm.join("bin"));
+        let toolchain = Toolchain::resolve_from(
+            None,
+            Some(devkitarm.as_os_str()),
+            Some(devkitpro.as_os_str()),
+        );
+        assert_eq!(toolchain.bin_dir, Some(devkitarm.join("bin")));
+        assert_eq!(
+            toolchain.tool("arm-none-eabi-g++"),
+            devkitarm.join("bin/arm-none-eabi-g++")
+        );
+
+        assert_eq!(
+            Toolchain::resolve_from(None, None, None).tool("arm-none-eabi-g++"),
+            PathBuf::from("arm-none-eabi-g++")
+        );
+    }
+}
@@ -0,0 +1,73 @@
+use std::str::FromStr;
+
+/// Semantic version without pre-release or build metadata.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+}
+
+impl FromStr for Version {
+    type Err = String;
+
+    /// Parses `major[.minor[.patch]]`, missing components default to 0.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().split('.');
+        let mut next = |required: bool| match parts.next() {
+            Some(part) => part.parse::<u64>().map_err(|_| s.to_string()),
+            None if required => Err(s.to_string()),
+            None => Ok(0),
+        };
+
+        let version = Version {
+            major: next(true)?,
+            minor: next(false)?,
+            patch: next(false)?,
+        };
+
+        if parts.next().is_some() {
+            return Err(s.to_string());
+        }
+
+        Ok(version)
+    }
+}
+
+impl std::fmt::Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(major: u64, minor: u64, patch: u64) -> Version {
+        Version {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("1.2.3".parse(), Ok(v(1, 2, 3)));
+        assert_eq!("0.2".parse(), Ok(v(0, 2, 0)));
+        assert_eq!(" 1 ".parse(), Ok(v(1, 0, 0)));
+        assert_eq!("".parse::<Version>(), Err("".to_string()));
+        assert_eq!("1.x".parse::<Version>(), Err("1.x".to_string()));
+        assert_eq!("1.2.3.4".parse::<Version>(), Err("1.2.3.4".to_string()));
+        assert_eq!(v(1, 2, 3).to_string(), "1.2.3");
+    }
+
+    #[test]
+    fn test_compare() {
+        assert!(v(0, 1, 0) < v(0, 2, 0));
+        assert!(v(0, 10, 0) > v(0, 9, 9));
+        assert!(v(1, 0, 0) > v(0, 99, 99));
+        assert!(v(0, 1, 1) >= v(0, 1, 1));
+    }
+}
@@ -1,4 +1,5 @@
-use std::sync::{mpsc, Arc, Mutex};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc, Mutex, MutexGuard, PoisonError};
 use std::thread;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -7,11 +8,32 @@ pub enum TaskResult {
     Terminate,
 }
 
+/// Returned for tasks submitted after a task terminated the pool, they are never run.
+#[derive(Debug, PartialEq, thiserror::Error)]
+#[error("Worker pool is terminated")]
+pub struct Terminated;
+
 enum WorkerMessage<F: FnOnce(usize) -> TaskResult> {
     Task(F),
     Poke,
 }
 
+/// Locks `mutex`, ignoring poisoning. Tasks run without any lock held and panics are caught,
+/// so the protected state is always consistent.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
 struct Worker {
     thread: Option<thread::JoinHandle<()>>,
 }
@@ -23,29 +45,38 @@ impl Worker {
         rx: Arc<Mutex<mpsc::Receiver<WorkerMessage<F>>>>,
         tx: Arc<Mutex<mpsc::Sender<WorkerMessage<F>>>>,
         terminate: Arc<Mutex<bool>>,
+        panics: Arc<Mutex<Vec<String>>>,
     ) -> Worker
     where
         F: FnOnce(usize) -> TaskResult + Send + 'static,
     {
         let thread = Some(thread::spawn(move || loop {
-            let msg = rx.lock().unwrap().recv().unwrap();
+            let Ok(msg) = lock(&rx).recv() else {
+                break;
+            };
 
-            if *terminate.lock().unwrap() {
+            if *lock(&terminate) {
                 break;
             }
 
             match msg {
-                WorkerMessage::Task(task) => match task(id) {
-                    TaskResult::Ok => {}
-                    TaskResult::Terminate => {
-                        *terminate.lock().unwrap() = true;
-                        let tx = tx.lock().unwrap();
+                WorkerMessage::Task(task) => {
+                    // A panicking task terminates the pool like a failed one
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| task(id)));
+                    let result = result.unwrap_or_else(|payload| {
+                        lock(&panics).push(panic_message(payload.as_ref()));
+                        TaskResult::Terminate
+                    });
+
+                    if result == TaskResult::Terminate {
+                        *lock(&terminate) = true;
+                        let tx = lock(&tx);
                         for _ in 1..num_workers {
                             tx.send(WorkerMessage::Poke).ok();
                         }
                         break;
                     }
-                },
+                }
                 WorkerMessage::Poke => {
                     break;
                 }
@@ -69,6 +100,7 @@ where
     workers: Vec<Worker>,
     tx: Arc<Mutex<mpsc::Sender<WorkerMessage<F>>>>,
     terminate: Arc<Mutex<bool>>,
+    panics: Arc<Mutex<Vec<String>>>,
 }
 
 impl<F: FnOnce(usize) -> TaskResult> WorkerPool<F>
@@ -83,6 +115,7 @@ where
         let rx = Arc::new(Mutex::new(rx));
 
         let terminate = Arc::new(Mutex::new(false));
+        let panics = Arc::new(Mutex::new(Vec::new()));
 
         let mut workers = Vec::with_capacity(num_workers);
 
@@ -93,6 +126,7 @@ where
                 rx.clone(),
                 tx.clone(),
                 terminate.clone(),
+                panics.clone(),
             ));
         }
 
@@ -100,20 +134,25 @@ where
             workers,
             tx,
             terminate,
+            panics,
         }
     }
 
-    /// Submits a task to the pool. The task will be executed by one of the workers.
-    pub fn submit_task(&self, task_fn: F) {
-        self.tx
-            .lock()
-            .unwrap()
+    /// Submits a task to the pool. The task will be executed by one of the workers, unless the
+    /// pool is already terminated.
+    pub fn submit_task(&self, task_fn: F) -> Result<(), Terminated> {
+        if *lock(&self.terminate) {
+            return Err(Terminated);
+        }
+
+        // Fails if every worker has exited
+        lock(&self.tx)
             .send(WorkerMessage::Task(task_fn))
-            .unwrap();
+            .map_err(|_| Terminated)
     }
 
     fn send_poke(&self) -> Result<(), mpsc::SendError<WorkerMessage<F>>> {
-        let tx = self.tx.lock().unwrap();
+        let tx = lock(&self.tx);
         for _ in 0..self.workers.len() {
             tx.send(WorkerMessage::Poke)?;
         }
@@ -127,17 +166,22 @@ where
             worker.join();
         }
 
-        if *self.terminate.lock().unwrap() {
+        if *lock(&self.terminate) {
             TaskResult::Terminate
         } else {
             TaskResult::Ok
         }
     }
 
+    /// Messages of the tasks that panicked.
+    pub fn panics(&self) -> Vec<String> {
+        lock(&self.panics).clone()
+    }
+
     /// Terminates all workers. Currently ongoing tasks will be finished.
     #[allow(dead_code)]
     pub fn terminate(&mut self) {
-        *self.terminate.lock().unwrap() = true;
+        *lock(&self.terminate) = true;
         self.send_poke().ok();
     }
 }
@@ -151,3 +195,46 @@ where
         self.wait();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_panicking_task() {
+        let done = Arc::new(AtomicUsize::new(0));
+        type Task = Box<dyn FnOnce(usize) -> TaskResult + Send>;
+        let mut pool = WorkerPool::<Task>::new(2);
+
+        pool.submit_task(Box::new(|_| -> TaskResult { panic!("job exploded") }))
+            .unwrap();
+        for _ in 0..4 {
+            let done = done.clone();
+            // Rejected if the panic already terminated the pool
+            pool.submit_task(Box::new(move |_| {
+                done.fetch_add(1, Ordering::SeqCst);
+                TaskResult::Ok
+            }))
+            .ok();
+        }
+
+        assert_eq!(pool.wait(), TaskResult::Terminate);
+        assert_eq!(pool.panics(), vec!["job exploded".to_string()]);
+        assert!(done.load(Ordering::SeqCst) <= 4);
+    }
+
+    #[test]
+    fn test_submit_after_terminate() {
+        type Task = fn(usize) -> TaskResult;
+        let mut pool = WorkerPool::<Task>::new(2);
+        pool.submit_task(|_| TaskResult::Terminate).unwrap();
+        assert_eq!(pool.wait(), TaskResult::Terminate);
+        assert_eq!(pool.submit_task(|_| TaskResult::Ok), Err(Terminated));
+
+        let mut pool = WorkerPool::<Task>::new(2);
+        pool.terminate();
+        assert_eq!(pool.submit_task(|_| TaskResult::Ok), Err(Terminated));
+        assert_eq!(pool.wait(), TaskResult::Terminate);
+    }
+}